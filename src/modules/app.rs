@@ -1,13 +1,17 @@
+use crate::modules::apply_update::ApplyUpdateUI;
 use crate::modules::create_update::CreateUpdateUI;
 use crate::modules::settings::SettingsUI;
 use eframe::egui::{CentralPanel, Context, menu, TopBottomPanel, Ui};
 use eframe::{App, Frame};
 use crate::modules::help::HelpUI;
+use crate::modules::verify_install::VerifyInstallUI;
 
 #[derive(Default, PartialEq)]
 pub enum TabBar {
     #[default]
     CreateUpdate,
+    ApplyUpdate,
+    VerifyInstall,
     Settings,
 }
 
@@ -15,29 +19,39 @@ pub enum TabBar {
 pub struct RedAltSteamUpCreator {
     pub tab_bar: TabBar,
     pub create_update_ui: CreateUpdateUI,
+    pub apply_update_ui: ApplyUpdateUI,
+    pub verify_install_ui: VerifyInstallUI,
     pub settings_ui: SettingsUI,
     pub help_ui: HelpUI,
 }
 
 impl App for RedAltSteamUpCreator {
     fn update(&mut self, ctx: &Context, _frame: &mut Frame) {
-        self.settings_ui.read_settings();
         self.display_top_bar(ctx);
         self.display_central_panel(ctx);
-
+        self.settings_ui.track_changes(ctx);
     }
 }
 
 impl RedAltSteamUpCreator {
+    pub fn new() -> Self {
+        let mut app = Self::default();
+        app.settings_ui.read_settings();
+        app
+    }
+
     fn display_top_bar(&mut self, ctx: &Context) {
         TopBottomPanel::top("Tabs").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 // Add tabs for each function
                 ui.selectable_value(&mut self.tab_bar, TabBar::CreateUpdate, "Create Update");
-                ui.selectable_value(&mut self.tab_bar, TabBar::Settings, "Settings");
+                ui.selectable_value(&mut self.tab_bar, TabBar::ApplyUpdate, "Apply Update");
+                ui.selectable_value(&mut self.tab_bar, TabBar::VerifyInstall, "Verify Install");
+                let settings_tab = if self.settings_ui.has_unsaved_changes() { "Settings*" } else { "Settings" };
+                ui.selectable_value(&mut self.tab_bar, TabBar::Settings, settings_tab);
 
                 // Add menu bar
-                self.display_menu_bar(ctx, ui);
+                self.display_menu_bar(ui);
                 // Display other windows
                 self.help_ui.show_help_window(ctx);
                 self.help_ui.show_update_window(ctx);
@@ -55,11 +69,13 @@ impl RedAltSteamUpCreator {
                 &mut self.settings_ui.compression_settings,
                 &mut self.tab_bar,
             ),
+            TabBar::ApplyUpdate => ApplyUpdateUI::display(ui, &mut self.apply_update_ui, &self.settings_ui.compression_settings),
+            TabBar::VerifyInstall => VerifyInstallUI::display(ui, &mut self.verify_install_ui),
             TabBar::Settings => SettingsUI::display(ctx, ui, &mut self.settings_ui),
         });
     }
 
-    fn display_menu_bar(&mut self, ctx: &Context, ui: &mut Ui) {
+    fn display_menu_bar(&mut self, ui: &mut Ui) {
         menu::bar(ui, |ui| {
             ui.menu_button("Help", |ui| {
                 if ui.button("Show help").clicked() {
@@ -0,0 +1,286 @@
+use std::path::{Path, PathBuf};
+use std::thread;
+use crossbeam_channel::{Receiver, Sender};
+use eframe::egui::{Button, ScrollArea, TextEdit, Ui};
+use egui_file::FileDialog;
+use crate::modules::archiver_backend::ArchiverBackend;
+use crate::modules::compression::CompressionSettings;
+use crate::modules::compression_settings::{SevenZipSettings, WinRARSettings};
+use crate::modules::native_archiver::{extract_archive, list_archive};
+use crate::modules::package_metadata::INSTALLER_FOLDER;
+use crate::modules::update_applier::{UpdatePlan, roll_back};
+
+/// What finished in the background: a dry run, an applied update with its backup, or a rollback.
+enum ApplyResult {
+    DryRun,
+    Applied(PathBuf),
+    RolledBack,
+}
+
+/// Applies a package to a game folder, to test it before it is published.
+pub struct ApplyUpdateUI {
+    output_sender: Sender<String>,
+    output_receiver: Receiver<String>,
+    result_sender: Sender<std::io::Result<ApplyResult>>,
+    result_receiver: Receiver<std::io::Result<ApplyResult>>,
+    /// A package folder or the first volume of an archive.
+    package_path: Option<PathBuf>,
+    package_dialog: Option<FileDialog>,
+    game_path: Option<PathBuf>,
+    game_dialog: Option<FileDialog>,
+    /// Used instead of the archivers' passwords from the settings if it is not empty.
+    password: String,
+    dry_run: bool,
+    /// The backup of the last applied update, or one chosen to roll back.
+    backup_path: Option<PathBuf>,
+    backup_dialog: Option<FileDialog>,
+    running: bool,
+    stdout: String,
+}
+
+impl Default for ApplyUpdateUI {
+    fn default() -> Self {
+        let (output_sender, output_receiver) = crossbeam_channel::unbounded();
+        let (result_sender, result_receiver) = crossbeam_channel::bounded(1);
+        Self {
+            output_sender,
+            output_receiver,
+            result_sender,
+            result_receiver,
+            package_path: None,
+            package_dialog: None,
+            game_path: None,
+            game_dialog: None,
+            password: String::new(),
+            dry_run: true,
+            backup_path: None,
+            backup_dialog: None,
+            running: false,
+            stdout: String::new(),
+        }
+    }
+}
+
+impl ApplyUpdateUI {
+    pub fn display(ui: &mut Ui, apply_update_ui: &mut ApplyUpdateUI, compression_settings: &CompressionSettings) {
+        apply_update_ui.display_paths(ui);
+        apply_update_ui.display_buttons(ui, compression_settings);
+        apply_update_ui.receive_result();
+        ui.separator();
+        apply_update_ui.display_stdout(ui);
+    }
+
+    fn display_paths(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            match &self.package_path {
+                None => ui.label("Choose the package:"),
+                Some(path) => ui.label(format!("Package: {}", path.display())),
+            };
+            if ui.button("Open archive").clicked() {
+                let mut dialog = FileDialog::open_file(self.package_path.clone());
+                dialog.open();
+                self.package_dialog = Some(dialog);
+            }
+            if ui.button("Open folder").clicked() {
+                let mut dialog = FileDialog::select_folder(self.package_path.clone());
+                dialog.open();
+                self.package_dialog = Some(dialog);
+            }
+        });
+        show_dialog(ui, &mut self.package_dialog, &mut self.package_path);
+
+        ui.horizontal(|ui| {
+            match &self.game_path {
+                None => ui.label("Choose the game folder:"),
+                Some(path) => ui.label(format!("Game folder: {}", path.display())),
+            };
+            if ui.button("Choose folder").clicked() {
+                let mut dialog = FileDialog::select_folder(self.game_path.clone());
+                dialog.open();
+                self.game_dialog = Some(dialog);
+            }
+        });
+        show_dialog(ui, &mut self.game_dialog, &mut self.game_path);
+
+        ui.horizontal(|ui| {
+            ui.label("Archive password: ");
+            ui.add(TextEdit::singleline(&mut self.password).password(true))
+                .on_hover_text("Leave empty to use the password from the settings");
+        });
+        ui.checkbox(&mut self.dry_run, "Dry run")
+            .on_hover_text("Lists every change without touching the game folder");
+    }
+
+    fn display_buttons(&mut self, ui: &mut Ui, compression_settings: &CompressionSettings) {
+        ui.horizontal(|ui| {
+            let ready = !self.running && self.package_path.is_some() && self.game_path.is_some();
+            if ui.add_enabled(ready, Button::new("Apply update")).clicked() {
+                self.start_update(compression_settings);
+            }
+            if self.running {
+                ui.spinner();
+                ui.ctx().request_repaint();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            match &self.backup_path {
+                None => ui.label("No backup to roll back."),
+                Some(path) => ui.label(format!("Backup: {}", path.display())),
+            };
+            if ui.add_enabled(!self.running, Button::new("Choose backup")).clicked() {
+                let mut dialog = FileDialog::select_folder(self.backup_path.clone());
+                dialog.open();
+                self.backup_dialog = Some(dialog);
+            }
+            if ui.add_enabled(!self.running && self.backup_path.is_some(), Button::new("Roll back")).clicked() {
+                let backup_path = self.backup_path.clone().unwrap();
+                let output_sender = self.output_sender.clone();
+                let result_sender = self.result_sender.clone();
+                self.running = true;
+                let _ = output_sender.send(format!("\nRolling back the update with {}...\n", backup_path.display()));
+                thread::spawn(move || {
+                    let result = roll_back(&backup_path, &output_sender);
+                    let _ = result_sender.send(result.map(|_| ApplyResult::RolledBack));
+                });
+            }
+        });
+        show_dialog(ui, &mut self.backup_dialog, &mut self.backup_path);
+    }
+
+    fn start_update(&mut self, compression_settings: &CompressionSettings) {
+        let package_path = self.package_path.clone().unwrap();
+        let game_path = self.game_path.clone().unwrap();
+        let dry_run = self.dry_run;
+        let mut seven_zip_settings = compression_settings.seven_zip_settings.clone();
+        let mut win_rar_settings = compression_settings.win_rar_settings.clone();
+        if !self.password.is_empty() {
+            seven_zip_settings.password = self.password.clone();
+            win_rar_settings.password = self.password.clone();
+        }
+        let output_sender = self.output_sender.clone();
+        let result_sender = self.result_sender.clone();
+        self.running = true;
+        thread::spawn(move || {
+            let result = apply_package(&package_path, &game_path, dry_run, &seven_zip_settings, &win_rar_settings, &output_sender);
+            let _ = result_sender.send(result);
+        });
+    }
+
+    fn receive_result(&mut self) {
+        if let Ok(result) = self.result_receiver.try_recv() {
+            let _ = match result {
+                Ok(ApplyResult::DryRun) => self.output_sender.send("\nNothing was changed, as this was a dry run.\n".to_string()),
+                Ok(ApplyResult::Applied(backup_path)) => {
+                    let message = format!("\nApplied the update. The replaced files are in {}.\n", backup_path.display());
+                    self.backup_path = Some(backup_path);
+                    self.output_sender.send(message)
+                }
+                Ok(ApplyResult::RolledBack) => {
+                    self.backup_path = None;
+                    self.output_sender.send("\nRolled back the update.\n".to_string())
+                }
+                Err(error) => self.output_sender.send(format!("\nFailed: {}.\n", error)),
+            };
+            self.running = false;
+        }
+    }
+
+    fn display_stdout(&mut self, ui: &mut Ui) {
+        let mut output = self.stdout.clone();
+        ScrollArea::vertical().id_source("Apply Update Output").max_height(ui.available_height()).show(ui, |ui| {
+            ui.add(TextEdit::multiline(&mut output).desired_width(ui.available_width()).cursor_at_end(true));
+            while let Ok(output) = self.output_receiver.try_recv() {
+                self.stdout += &output;
+                ui.scroll_to_cursor(None);
+                ui.ctx().request_repaint();
+            }
+        });
+    }
+}
+
+fn show_dialog(ui: &mut Ui, dialog: &mut Option<FileDialog>, path: &mut Option<PathBuf>) {
+    if let Some(dialog) = dialog {
+        if dialog.show(ui.ctx()).selected() {
+            if let Some(selected) = dialog.path() {
+                *path = Some(selected.to_path_buf());
+            }
+        }
+    }
+}
+
+/// Lists or applies the changes of a package folder or archive. Dry runs only read the archive's
+/// listing and metadata, updates extract it next to the game folder first.
+fn apply_package(package_path: &Path, game_path: &Path, dry_run: bool, seven_zip_settings: &SevenZipSettings,
+                 win_rar_settings: &WinRARSettings, output_sender: &Sender<String>) -> std::io::Result<ApplyResult> {
+    if package_path.is_dir() {
+        let plan = UpdatePlan::new(package_path, game_path)?;
+        return apply_plan(&plan, dry_run, output_sender);
+    }
+
+    // The folder is removed when it is dropped, including when something fails
+    let folder = tempfile::Builder::new()
+        .prefix(".package")
+        .tempdir_in(game_path.parent().unwrap_or(game_path))?;
+    let extract = |files: &[String]| extract_package(package_path, folder.path(), files, seven_zip_settings, win_rar_settings, output_sender);
+    // Formats which cannot be listed, such as tar.xz, are extracted for dry runs too
+    let listing = match dry_run {
+        true => list_package(package_path, seven_zip_settings, win_rar_settings)?
+            .filter(|listing| listing.iter().any(|name| name.contains(INSTALLER_FOLDER))),
+        false => None,
+    };
+    let plan = match listing {
+        Some(listing) => {
+            let _ = output_sender.send(format!("\nReading the listing of {}...\n", package_path.display()));
+            UpdatePlan::from_listing(&listing, folder.path(), game_path, extract)?
+        }
+        None => {
+            let _ = output_sender.send(format!("\nExtracting {}...\n", package_path.display()));
+            extract(&[])?;
+            UpdatePlan::new(folder.path(), game_path)?
+        }
+    };
+    let result = apply_plan(&plan, dry_run, output_sender);
+    folder.close()?;
+    result
+}
+
+fn apply_plan(plan: &UpdatePlan, dry_run: bool, output_sender: &Sender<String>) -> std::io::Result<ApplyResult> {
+    let _ = output_sender.send(format!("\n{}", plan));
+    if dry_run {
+        return Ok(ApplyResult::DryRun);
+    }
+    let _ = output_sender.send("\nApplying the update...\n".to_string());
+    plan.apply(output_sender).map(ApplyResult::Applied)
+}
+
+/// RAR archives need WinRAR, everything else is read by 7-Zip if it is selected, or by the
+/// built-in archiver, which is `None`.
+fn archiver<'a>(archive_path: &Path, seven_zip_settings: &'a SevenZipSettings,
+                win_rar_settings: &'a WinRARSettings) -> Option<&'a dyn ArchiverBackend> {
+    let is_rar = archive_path.extension().is_some_and(|extension| extension == "rar");
+    match (is_rar, seven_zip_settings.executable().is_some()) {
+        (true, _) => Some(win_rar_settings),
+        (false, true) => Some(seven_zip_settings),
+        (false, false) => None,
+    }
+}
+
+/// The names in the archive, or `None` if the archiver cannot list this format.
+fn list_package(archive_path: &Path, seven_zip_settings: &SevenZipSettings,
+                win_rar_settings: &WinRARSettings) -> std::io::Result<Option<Vec<String>>> {
+    let listing = match archiver(archive_path, seven_zip_settings, win_rar_settings) {
+        Some(archiver) => archiver.parse_listing(&archiver.list(archive_path)?),
+        None => Some(list_archive(archive_path)?),
+    };
+    Ok(listing.map(|listing| listing.into_iter().map(|(name, _)| name).collect()))
+}
+
+/// Extracts `files`, or everything if there are none.
+fn extract_package(archive_path: &Path, output_folder: &Path, files: &[String], seven_zip_settings: &SevenZipSettings,
+                   win_rar_settings: &WinRARSettings, output_sender: &Sender<String>) -> std::io::Result<()> {
+    match archiver(archive_path, seven_zip_settings, win_rar_settings) {
+        Some(archiver) => archiver.extract(archive_path, output_folder, files, output_sender.clone()),
+        None => extract_archive(archive_path, output_folder, files),
+    }
+}
@@ -0,0 +1,287 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::thread;
+use crossbeam_channel::{Receiver, Sender};
+use crate::modules::compression_progress::{CompressionProgress, ProgressTracker};
+use crate::modules::native_archiver::collect_entries;
+use crate::modules::paths;
+use crate::modules::reproducible::write_file_list;
+use crate::modules::verification::VerificationReport;
+
+/// An external archiver driven through its command line. Implementations only describe the
+/// arguments; spawning the process and forwarding its input and output is shared.
+pub trait ArchiverBackend: Sync {
+    fn name(&self) -> &'static str;
+
+    fn executable(&self) -> Option<&Path>;
+
+    fn extension(&self) -> &'static str;
+
+    fn is_split(&self) -> bool;
+
+    /// Arguments which add the contents of `download_path` to `archive_path`.
+    fn compress_arguments(&self, download_path: &Path, archive_path: &Path) -> Vec<String>;
+
+    /// The archiver is run once per step. Formats which need an intermediate archive, such as
+    /// tar.xz, use more than one.
+    fn compress_steps(&self, download_path: &Path, archive_path: &Path) -> Vec<Vec<String>> {
+        vec![self.compress_arguments(download_path, archive_path)]
+    }
+
+    /// Files created by the earlier steps, which are removed once compression has finished.
+    fn intermediate_files(&self, _archive_path: &Path) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// The list of files to add, which the archiver reads relative to the download's parent
+    /// folder. Without one, the download folder is added as a whole.
+    fn file_list(&self, _download_path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// The list of files which are added without compression in a separate step.
+    fn stored_file_list(&self, _download_path: &Path) -> Option<PathBuf> {
+        None
+    }
+
+    /// Whether the file, named like the entries of `collect_entries`, goes into the stored list.
+    fn is_stored(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Whether the file, named like the entries of `collect_entries`, is left out of the archive,
+    /// such as a modified file which is shipped as a delta.
+    fn is_excluded(&self, _name: &str) -> bool {
+        false
+    }
+
+    fn test_arguments(&self, archive_path: &Path) -> Vec<String>;
+
+    fn list_arguments(&self, archive_path: &Path) -> Vec<String>;
+
+    /// Arguments which extract `files`, named as in the listing, or everything if there are none
+    /// into `output_folder`, overwriting files.
+    fn extract_arguments(&self, archive_path: &Path, output_folder: &Path, files: &[String]) -> Vec<String>;
+
+    /// Parses the output of the list command into file names and sizes, if they are listed. Returns
+    /// `None` when the listing cannot be compared with the downloaded files.
+    fn parse_listing(&self, listing: &str) -> Option<Vec<(String, Option<u64>)>>;
+
+    /// Parses a line of the archiver's output into the percentage done and the file being added.
+    fn parse_progress(&self, _line: &str) -> Option<(f32, Option<String>)> {
+        None
+    }
+
+    /// Whether the archiver's exit code means that it succeeded, possibly with warnings.
+    fn is_success(&self, status: ExitStatus) -> bool {
+        status.code().is_some_and(|code| code <= 1)
+    }
+
+    /// Split archives are put into their own folder, named after the download folder.
+    fn archive_path(&self, download_path: &Path, output_path: &Path) -> PathBuf {
+        let name = download_path.file_name().unwrap_or_default().to_string_lossy();
+        let folder = if self.is_split() { output_path.join(&*name) } else { output_path.to_path_buf() };
+        folder.join(format!("{}.{}", name, self.extension()))
+    }
+
+    fn compress(
+        &self,
+        download_path: PathBuf,
+        output_path: PathBuf,
+        stdin_receiver: Receiver<String>,
+        stdout_sender: Sender<String>,
+        progress_sender: Sender<CompressionProgress>,
+    ) -> std::io::Result<()> {
+        let _ = stdout_sender.send(format!("\nCompressing files with {}...\n", self.name()));
+        let archive_path = self.archive_path(&download_path, &output_path);
+        std::fs::create_dir_all(archive_path.parent().unwrap())?;
+
+        let file_lists = [self.file_list(&download_path), self.stored_file_list(&download_path)];
+        if file_lists.iter().any(Option::is_some) {
+            std::fs::create_dir_all(paths::job_dir(&download_path))?;
+        }
+        if let Some(file_list) = &file_lists[0] {
+            write_file_list(&download_path, file_list, |name| !self.is_stored(name) && !self.is_excluded(name))?;
+        }
+        if let Some(stored_file_list) = &file_lists[1] {
+            write_file_list(&download_path, stored_file_list, |name| self.is_stored(name) && !self.is_excluded(name))?;
+        }
+        let steps = self.compress_steps(&download_path, &archive_path);
+        let folder_name = download_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let total_bytes = collect_entries(&download_path, &folder_name)?.iter()
+            .filter(|entry| !self.is_excluded(&entry.name))
+            .map(|entry| entry.size)
+            .sum();
+        let mut tracker = ProgressTracker::new(total_bytes, steps.len(), progress_sender);
+        let mut result = Ok(());
+        for arguments in steps {
+            let mut command = self.command()?;
+            command.args(arguments);
+            if file_lists[0].is_some() {
+                command.current_dir(download_path.parent().unwrap());
+            }
+            // Progress lines are turned into progress updates, everything else is forwarded
+            let (output_sender, output_receiver) = crossbeam_channel::unbounded::<String>();
+            result = thread::scope(|s| {
+                let process = s.spawn(|| self.run(command, stdin_receiver.clone(), output_sender));
+                for chunk in output_receiver.iter() {
+                    for line in tracker.split_lines(&chunk) {
+                        match self.parse_progress(&line) {
+                            Some((percentage, file)) => tracker.update(percentage, file.as_deref()),
+                            None => { let _ = stdout_sender.send(line + "\n"); }
+                        }
+                    }
+                }
+                process.join()
+                    .unwrap_or_else(|_| Err(std::io::Error::other(format!("The thread running {} panicked", self.name()))))
+            });
+            let _ = stdout_sender.send(tracker.finish());
+            if result.is_err() {
+                break;
+            }
+            tracker.next_step();
+        }
+
+        for file in self.intermediate_files(&archive_path).into_iter().chain(file_lists.into_iter().flatten()) {
+            let _ = std::fs::remove_file(file);
+        }
+        let _ = std::fs::remove_dir(paths::job_dir(&download_path));
+        result
+    }
+
+    fn test(&self, archive_path: &Path, stdout_sender: Sender<String>) -> std::io::Result<()> {
+        let (_, stdin_receiver) = crossbeam_channel::bounded(1);
+        let mut command = self.command()?;
+        command.args(self.test_arguments(archive_path));
+        self.run(command, stdin_receiver, stdout_sender)
+    }
+
+    fn extract(&self, archive_path: &Path, output_folder: &Path, files: &[String], stdout_sender: Sender<String>) -> std::io::Result<()> {
+        let (_, stdin_receiver) = crossbeam_channel::bounded(1);
+        let mut command = self.command()?;
+        command.args(self.extract_arguments(archive_path, output_folder, files));
+        self.run(command, stdin_receiver, stdout_sender)
+    }
+
+    /// Tests the archive and compares its contents and volumes with the downloaded files.
+    fn verify(&self, download_path: &Path, output_path: &Path, stdout_sender: Sender<String>) -> VerificationReport {
+        let _ = stdout_sender.send(format!("\nVerifying the archive with {}...\n", self.name()));
+        let mut report = VerificationReport::new(self.archive_path(download_path, output_path), self.is_split());
+        let Some(first_volume) = report.first_volume().map(Path::to_path_buf) else {
+            return report;
+        };
+
+        report.test_result = self.test(&first_volume, stdout_sender).map_err(|error| error.to_string());
+        let listing = self.list(&first_volume).and_then(|listing| self.parse_listing(&listing).ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::Unsupported, format!("{} cannot list the files inside this format", self.name()))));
+        if let Err(error) = listing.and_then(|listing| report.compare_listing(download_path, listing, |name| self.is_excluded(name))) {
+            report.listing_result = Err(error.to_string());
+        }
+        report
+    }
+
+    /// Returns the archiver's listing of the archive's contents.
+    fn list(&self, archive_path: &Path) -> std::io::Result<String> {
+        let mut command = self.command()?;
+        let output = command
+            .stdin(Stdio::null())
+            .args(self.list_arguments(archive_path))
+            .output()?;
+        if !self.is_success(output.status) {
+            return Err(std::io::Error::other(format!("{} exited with {}", self.name(), output.status)));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn command(&self) -> std::io::Result<Command> {
+        let executable = self.executable().ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::NotFound, format!("No executable selected for {}", self.name())))?;
+        let mut command = Command::new(executable);
+        hide_console_window(&mut command);
+        Ok(command)
+    }
+
+    fn run(
+        &self,
+        command: Command,
+        stdin_receiver: Receiver<String>,
+        stdout_sender: Sender<String>,
+    ) -> std::io::Result<()> {
+        let status = run_process(command, stdin_receiver, stdout_sender)?;
+        match self.is_success(status) {
+            true => Ok(()),
+            false => Err(std::io::Error::other(format!("{} exited with {}", self.name(), status))),
+        }
+    }
+}
+
+/// Stops console programs from opening a window on Windows.
+#[cfg(windows)]
+pub fn hide_console_window(command: &mut Command) {
+    use std::os::windows::process::CommandExt;
+    command.creation_flags(0x08000000);
+}
+
+#[cfg(not(windows))]
+pub fn hide_console_window(_command: &mut Command) {}
+
+/// Runs the command, forwarding its output to `stdout_sender` and input from `stdin_receiver`
+/// to its stdin until it exits.
+pub fn run_process(
+    mut command: Command,
+    stdin_receiver: Receiver<String>,
+    stdout_sender: Sender<String>,
+) -> std::io::Result<ExitStatus> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stdin = child.stdin.take();
+    thread::scope(|s| {
+        let outputs: [Option<Box<dyn Read + Send>>; 2] = [
+            child.stderr.take().map(|stderr| Box::new(stderr) as Box<dyn Read + Send>),
+            child.stdout.take().map(|stdout| Box::new(stdout) as Box<dyn Read + Send>),
+        ];
+        for mut output in outputs.into_iter().flatten() {
+            let stdout_sender = stdout_sender.clone();
+            s.spawn(move || {
+                let mut buffer = [0; 1024];
+                loop {
+                    match output.read(&mut buffer) {
+                        Ok(n) if n > 0 => {
+                            let _ = stdout_sender.send(String::from_utf8_lossy(&buffer[..n]).to_string());
+                        }
+                        _ => break,
+                    }
+                }
+            });
+        }
+
+        let result = wait_with_input(&mut child, stdin, &stdin_receiver);
+        if result.is_err() {
+            // The output threads only finish once the process has exited
+            let _ = child.kill();
+        }
+        result
+    })
+}
+
+/// Waits for the process to exit, writing every line from `stdin_receiver` to its stdin.
+fn wait_with_input(child: &mut Child, mut stdin: Option<ChildStdin>, stdin_receiver: &Receiver<String>) -> std::io::Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        match stdin_receiver.try_recv() {
+            Ok(line) => {
+                let stdin = stdin.as_mut().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "the process has no stdin"))?;
+                stdin.write_all(format!("{}\n", line).as_bytes())?;
+                stdin.flush()?;
+            }
+            Err(_) => thread::sleep(std::time::Duration::from_millis(100)),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::env::consts::EXE_SUFFIX;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use crate::modules::archiver_backend::hide_console_window;
+use crate::modules::compression::Archiver;
+
+/// Executables which can be used for each archiver, in order of preference. `unrar` can only
+/// test and list archives.
+const EXECUTABLES: [(Archiver, &str, bool); 6] = [
+    (Archiver::SevenZip, "7z", true),
+    (Archiver::SevenZip, "7zz", true),
+    (Archiver::SevenZip, "7za", true),
+    (Archiver::WinRAR, "WinRAR", true),
+    (Archiver::WinRAR, "rar", true),
+    (Archiver::WinRAR, "unrar", false),
+];
+
+#[cfg(windows)]
+const COMMON_LOCATIONS: [&str; 5] = [
+    r"C:\Program Files\7-Zip",
+    r"C:\Program Files (x86)\7-Zip",
+    r"C:\Program Files\WinRAR",
+    r"C:\Program Files (x86)\WinRAR",
+    r"C:\ProgramData\chocolatey\bin",
+];
+
+#[cfg(not(windows))]
+const COMMON_LOCATIONS: [&str; 7] = [
+    "/usr/bin",
+    "/usr/local/bin",
+    "/usr/lib/p7zip",
+    "/opt/homebrew/bin",
+    "/snap/bin",
+    "/var/lib/flatpak/exports/bin",
+    "/opt/7-zip",
+];
+
+#[derive(Clone)]
+pub struct DetectedArchiver {
+    pub archiver: Archiver,
+    pub path: PathBuf,
+    /// Where the executable was found: the registry, PATH or a common install location.
+    pub source: &'static str,
+    pub can_compress: bool,
+    pub version: Option<String>,
+}
+
+/// Finds archivers without running them, so this is cheap enough to be used for defaults.
+pub fn find_archivers() -> Vec<DetectedArchiver> {
+    let mut detected = Vec::new();
+    let mut seen = HashSet::new();
+    let mut add = |folder: &Path, source: &'static str| {
+        for (archiver, name, can_compress) in EXECUTABLES {
+            let path = folder.join(format!("{}{}", name, EXE_SUFFIX));
+            if !path.is_file() {
+                continue;
+            }
+            if seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+                detected.push(DetectedArchiver { archiver, path, source, can_compress, version: None });
+            }
+        }
+    };
+
+    for folder in registry_folders() {
+        add(&folder, "registry");
+    }
+    if let Some(path) = std::env::var_os("PATH") {
+        for folder in std::env::split_paths(&path) {
+            add(&folder, "PATH");
+        }
+    }
+    for folder in COMMON_LOCATIONS {
+        add(Path::new(folder), "common location");
+    }
+
+    // Keep the order of preference within each archiver
+    detected.sort_by_key(|archiver| EXECUTABLES.iter()
+        .position(|(_, name, _)| archiver.path.file_stem().is_some_and(|stem| stem.eq_ignore_ascii_case(name))));
+    detected
+}
+
+/// Finds archivers and runs each one to read its version.
+pub fn detect_archivers() -> Vec<DetectedArchiver> {
+    let mut detected = find_archivers();
+    for archiver in detected.iter_mut() {
+        archiver.version = read_version(&archiver.path);
+    }
+    detected
+}
+
+/// The first archiver of the kind which can create archives.
+pub fn preferred_path(archiver: Archiver) -> Option<PathBuf> {
+    find_archivers().into_iter()
+        .find(|detected| detected.archiver == archiver && detected.can_compress)
+        .map(|detected| detected.path)
+}
+
+/// Reads the version from the banner printed by the command line versions. WinRAR.exe is the GUI
+/// and would open a window, so it is not run.
+fn read_version(path: &Path) -> Option<String> {
+    if path.file_stem()?.eq_ignore_ascii_case("WinRAR") {
+        return None;
+    }
+
+    let mut command = Command::new(path);
+    hide_console_window(&mut command);
+    let output = command.stdin(Stdio::null()).stderr(Stdio::null()).output().ok()?;
+    let banner = String::from_utf8_lossy(&output.stdout);
+    banner.lines().find(|line| !line.trim().is_empty())?
+        .split_whitespace()
+        .find(|word| word.contains('.') && word.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        .map(|version| version.to_string())
+}
+
+/// Whether the 7-Zip build lists the codec in `7z i`. Official builds do not include zstd, but
+/// forks such as 7-Zip ZS do.
+pub fn supports_codec(path: &Path, codec: &str) -> bool {
+    let mut command = Command::new(path);
+    hide_console_window(&mut command);
+    let Ok(output) = command.arg("i").stdin(Stdio::null()).stderr(Stdio::null()).output() else {
+        return false;
+    };
+    let info = String::from_utf8_lossy(&output.stdout);
+    info.lines().any(|line| line.split_whitespace().any(|word| word.eq_ignore_ascii_case(codec)))
+}
+
+#[cfg(windows)]
+fn registry_folders() -> Vec<PathBuf> {
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+
+    let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
+    let subkey = r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths";
+    ["7zFM.exe", "WinRAR.exe"].into_iter()
+        .filter_map(|key| hklm.open_subkey(format!("{}\\{}", subkey, key)).ok())
+        .filter_map(|key| key.get_value::<String, _>("Path").ok())
+        .map(PathBuf::from)
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn registry_folders() -> Vec<PathBuf> {
+    Vec::new()
+}
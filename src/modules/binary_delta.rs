@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::modules::package_metadata::INSTALLER_FOLDER;
+use crate::modules::reproducible::to_hex;
+
+/// Deltas are written into the installer folder, under the path of the file they update.
+pub const DELTA_FOLDER: &str = "deltas";
+pub const DELTA_EXTENSION: &str = "delta";
+/// The format of a delta file, which installers apply to the file of the initial build:
+///
+/// `RSUDELTA` followed by the base and target sizes as little endian u64s, then instructions until
+/// a zero byte. `1` is followed by an offset and a length (u64s) to copy from the base file, `2` by
+/// a length (u64) and that many bytes to insert.
+const DELTA_MAGIC: &[u8; 8] = b"RSUDELTA";
+const COPY: u8 = 1;
+const INSERT: u8 = 2;
+const END: u8 = 0;
+/// Blocks of the base file which are looked for in the new file.
+const BLOCK_SIZE: usize = 16 * 1024;
+/// Inserted bytes are written once this many have been collected.
+const MAX_INSERT: usize = 1024 * 1024;
+/// Deltas which are not smaller than this share of the file are not worth applying.
+const MAX_DELTA_RATIO: f64 = 0.9;
+
+/// The offset and strong hash of every block with the same rolling checksum.
+type BlockIndex = HashMap<u32, Vec<(u64, [u8; 16])>>;
+
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize)]
+pub enum FileStrategy {
+    /// The package contains the whole file.
+    Full,
+    /// The package contains a delta against the file of the initial build.
+    Delta,
+}
+
+/// How one modified file is shipped. Installers check the hashes before and after applying a delta.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct FileStrategyRecord {
+    pub path: String,
+    pub strategy: FileStrategy,
+    pub size: u64,
+    pub sha256: String,
+    /// The delta inside the installer folder, relative to it.
+    pub delta_path: Option<String>,
+    pub delta_size: Option<u64>,
+    pub base_size: Option<u64>,
+    pub base_sha256: Option<String>,
+}
+
+/// The strategy of every modified file and how much the deltas save.
+#[derive(Default)]
+pub struct DeltaPlan {
+    pub records: Vec<FileStrategyRecord>,
+}
+
+impl DeltaPlan {
+    /// Bytes of the modified files as full files, and as they are packaged.
+    pub fn sizes(&self) -> (u64, u64) {
+        let full = self.records.iter().map(|record| record.size).sum();
+        let packaged = self.records.iter().map(|record| record.delta_size.unwrap_or(record.size)).sum();
+        (full, packaged)
+    }
+}
+
+impl Display for DeltaPlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for record in &self.records {
+            match record.delta_size {
+                Some(delta_size) => writeln!(f, "  {}: delta of {:.1} MB instead of {:.1} MB", record.path,
+                                             delta_size as f64 / (1024.0 * 1024.0), record.size as f64 / (1024.0 * 1024.0))?,
+                None => writeln!(f, "  {}: full file of {:.1} MB", record.path, record.size as f64 / (1024.0 * 1024.0))?,
+            }
+        }
+        let (full, packaged) = self.sizes();
+        writeln!(f, "Modified files: {:.1} MB as full files, {:.1} MB with deltas ({:.1} MB saved).",
+                 full as f64 / (1024.0 * 1024.0), packaged as f64 / (1024.0 * 1024.0),
+                 full.saturating_sub(packaged) as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Creates deltas for the modified files which have a copy in `base_path`, the initial build. Files
+/// without one, or whose delta would barely be smaller, stay full files. The download is left as it
+/// is, so the files with a delta have to be left out of the archive.
+pub fn create_deltas(download_path: &Path, base_path: &Path, modified: &[String], output_sender: &Sender<String>) -> std::io::Result<DeltaPlan> {
+    let mut plan = DeltaPlan::default();
+    for path in modified {
+        // Depots list paths with backslashes
+        let path = path.replace('\\', "/");
+        let target = download_path.join(&path);
+        let base = base_path.join(&path);
+        if !target.is_file() {
+            continue;
+        }
+
+        let mut record = FileStrategyRecord {
+            path: path.clone(),
+            strategy: FileStrategy::Full,
+            size: target.metadata()?.len(),
+            sha256: sha256_file(&target)?,
+            delta_path: None,
+            delta_size: None,
+            base_size: None,
+            base_sha256: None,
+        };
+        if base.is_file() {
+            let _ = output_sender.send(format!("Creating a delta for {}...\n", path));
+            let delta_path = format!("{}/{}.{}", DELTA_FOLDER, path, DELTA_EXTENSION);
+            let delta = download_path.join(INSTALLER_FOLDER).join(&delta_path);
+            std::fs::create_dir_all(delta.parent().unwrap())?;
+            let delta_size = create_delta(&base, &target, &delta)?;
+            if (delta_size as f64) < record.size as f64 * MAX_DELTA_RATIO {
+                record.strategy = FileStrategy::Delta;
+                record.delta_path = Some(delta_path);
+                record.delta_size = Some(delta_size);
+                record.base_size = Some(base.metadata()?.len());
+                record.base_sha256 = Some(sha256_file(&base)?);
+            } else {
+                std::fs::remove_file(delta)?;
+            }
+        }
+        plan.records.push(record);
+    }
+    Ok(plan)
+}
+
+/// Writes a delta which turns `base` into `target` and returns its size. Blocks of the base file are
+/// found anywhere in the target with a rolling checksum, so inserted and moved data is handled.
+pub fn create_delta(base: &Path, target: &Path, delta: &Path) -> std::io::Result<u64> {
+    let blocks = index_blocks(base)?;
+    let base_size = base.metadata()?.len();
+    let target_size = target.metadata()?.len();
+    let mut writer = DeltaWriter::new(BufWriter::new(File::create(delta)?));
+    writer.output.write_all(DELTA_MAGIC)?;
+    writer.output.write_all(&base_size.to_le_bytes())?;
+    writer.output.write_all(&target_size.to_le_bytes())?;
+
+    let mut reader = BufReader::new(File::open(target)?);
+    let mut buffer = Vec::new();
+    let mut position = 0;
+    let mut rolling: Option<RollingChecksum> = None;
+    loop {
+        // Keep at least one block after the position in the buffer
+        if buffer.len() - position < BLOCK_SIZE {
+            buffer.drain(..position);
+            position = 0;
+            let length = buffer.len();
+            buffer.resize(length + 4 * 1024 * 1024, 0);
+            let read = read_full(&mut reader, &mut buffer[length..])?;
+            buffer.truncate(length + read);
+            if buffer.len() < BLOCK_SIZE {
+                writer.insert(&buffer)?;
+                break;
+            }
+        }
+
+        let window = &buffer[position..position + BLOCK_SIZE];
+        let checksum = rolling.get_or_insert_with(|| RollingChecksum::new(window)).value();
+        let matched = blocks.get(&checksum).and_then(|candidates| {
+            let strong = strong_hash(window);
+            candidates.iter().find(|(_, hash)| *hash == strong).map(|(offset, _)| *offset)
+        });
+        match matched {
+            Some(offset) => {
+                writer.copy(offset, BLOCK_SIZE as u64)?;
+                position += BLOCK_SIZE;
+                rolling = None;
+            }
+            None => {
+                writer.insert(&[buffer[position]])?;
+                // At the end of the buffer, the checksum is calculated again after reading more
+                rolling = match (rolling.take(), buffer.get(position + BLOCK_SIZE)) {
+                    (Some(mut checksum), Some(&next)) => {
+                        checksum.roll(buffer[position], next);
+                        Some(checksum)
+                    }
+                    _ => None,
+                };
+                position += 1;
+            }
+        }
+    }
+    writer.finish()
+}
+
+/// Writes `target` from the `base` file and a delta made by `create_delta`.
+pub fn apply_delta(base: &Path, delta: &Path, target: &Path) -> std::io::Result<()> {
+    let invalid = |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{}: {}", delta.display(), message));
+    let mut reader = BufReader::new(File::open(delta)?);
+    let mut header = [0; 24];
+    reader.read_exact(&mut header)?;
+    if &header[..8] != DELTA_MAGIC {
+        return Err(invalid("not a delta file"));
+    }
+    let base_size = u64::from_le_bytes(header[8..16].try_into().unwrap());
+    let target_size = u64::from_le_bytes(header[16..24].try_into().unwrap());
+    let mut base_file = File::open(base)?;
+    if base_file.metadata()?.len() != base_size {
+        return Err(invalid("the base file has a different size"));
+    }
+
+    let mut output = BufWriter::new(File::create(target)?);
+    let mut written = 0;
+    loop {
+        let mut instruction = [0; 1];
+        reader.read_exact(&mut instruction)?;
+        let mut number = [0; 8];
+        match instruction[0] {
+            END => break,
+            COPY => {
+                reader.read_exact(&mut number)?;
+                let offset = u64::from_le_bytes(number);
+                reader.read_exact(&mut number)?;
+                let length = u64::from_le_bytes(number);
+                base_file.seek(SeekFrom::Start(offset))?;
+                written += std::io::copy(&mut (&mut base_file).take(length), &mut output)?;
+            }
+            INSERT => {
+                reader.read_exact(&mut number)?;
+                let length = u64::from_le_bytes(number);
+                written += std::io::copy(&mut (&mut reader).take(length), &mut output)?;
+            }
+            _ => return Err(invalid("unknown instruction")),
+        }
+    }
+    output.flush()?;
+    match written == target_size {
+        true => Ok(()),
+        false => Err(invalid("the result has a different size")),
+    }
+}
+
+/// The rolling checksum and strong hashes of every whole block of the file, by rolling checksum.
+fn index_blocks(path: &Path) -> std::io::Result<BlockIndex> {
+    let mut blocks = BlockIndex::new();
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut block = vec![0; BLOCK_SIZE];
+    let mut offset = 0;
+    while read_full(&mut reader, &mut block)? == BLOCK_SIZE {
+        blocks.entry(RollingChecksum::new(&block).value()).or_default().push((offset, strong_hash(&block)));
+        offset += BLOCK_SIZE as u64;
+    }
+    Ok(blocks)
+}
+
+fn strong_hash(block: &[u8]) -> [u8; 16] {
+    Sha256::digest(block)[..16].try_into().unwrap()
+}
+
+pub fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        match reader.read(&mut buffer[total..])? {
+            0 => break,
+            read => total += read,
+        }
+    }
+    Ok(total)
+}
+
+/// The checksum rsync uses, which can move along the data one byte at a time.
+struct RollingChecksum {
+    a: u32,
+    b: u32,
+}
+
+impl RollingChecksum {
+    fn new(window: &[u8]) -> Self {
+        let mut checksum = Self { a: 0, b: 0 };
+        for (index, &byte) in window.iter().enumerate() {
+            checksum.a = checksum.a.wrapping_add(byte as u32);
+            checksum.b = checksum.b.wrapping_add((window.len() - index) as u32 * byte as u32);
+        }
+        checksum
+    }
+
+    fn roll(&mut self, removed: u8, added: u8) {
+        self.a = self.a.wrapping_sub(removed as u32).wrapping_add(added as u32);
+        self.b = self.b.wrapping_sub((BLOCK_SIZE as u32).wrapping_mul(removed as u32)).wrapping_add(self.a);
+    }
+
+    fn value(&self) -> u32 {
+        (self.a & 0xffff) | (self.b << 16)
+    }
+}
+
+/// Merges neighbouring copies and collects inserted bytes before writing them.
+struct DeltaWriter<W: Write> {
+    output: W,
+    written: u64,
+    copy: Option<(u64, u64)>,
+    insert: Vec<u8>,
+}
+
+impl<W: Write> DeltaWriter<W> {
+    fn new(output: W) -> Self {
+        // The header is written directly
+        Self { output, written: 24, copy: None, insert: Vec::new() }
+    }
+
+    fn copy(&mut self, offset: u64, length: u64) -> std::io::Result<()> {
+        self.flush_insert()?;
+        match &mut self.copy {
+            Some((copy_offset, copy_length)) if *copy_offset + *copy_length == offset => *copy_length += length,
+            _ => {
+                self.flush_copy()?;
+                self.copy = Some((offset, length));
+            }
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.flush_copy()?;
+        self.insert.extend_from_slice(bytes);
+        if self.insert.len() >= MAX_INSERT {
+            self.flush_insert()?;
+        }
+        Ok(())
+    }
+
+    fn flush_copy(&mut self) -> std::io::Result<()> {
+        if let Some((offset, length)) = self.copy.take() {
+            self.output.write_all(&[COPY])?;
+            self.output.write_all(&offset.to_le_bytes())?;
+            self.output.write_all(&length.to_le_bytes())?;
+            self.written += 17;
+        }
+        Ok(())
+    }
+
+    fn flush_insert(&mut self) -> std::io::Result<()> {
+        if !self.insert.is_empty() {
+            self.output.write_all(&[INSERT])?;
+            self.output.write_all(&(self.insert.len() as u64).to_le_bytes())?;
+            self.output.write_all(&self.insert)?;
+            self.written += 9 + self.insert.len() as u64;
+            self.insert.clear();
+        }
+        Ok(())
+    }
+
+    /// Writes the end of the delta and returns its size.
+    fn finish(mut self) -> std::io::Result<u64> {
+        self.flush_copy()?;
+        self.flush_insert()?;
+        self.output.write_all(&[END])?;
+        self.output.flush()?;
+        Ok(self.written + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::test_support::random_bytes;
+
+    #[test]
+    fn delta_round_trip() {
+        let folder = tempfile::tempdir().unwrap();
+        let base = random_bytes(200 * 1024, 1);
+        // Data is inserted, changed, moved and appended
+        let mut target = base[..50_000].to_vec();
+        target.extend(random_bytes(3000, 2));
+        target.extend(&base[150_000..]);
+        target.extend(&base[50_000..150_000]);
+        target[120_000] ^= 0xFF;
+        target.extend(random_bytes(777, 3));
+        let (base_path, target_path) = (folder.path().join("base.bin"), folder.path().join("target.bin"));
+        std::fs::write(&base_path, &base).unwrap();
+        std::fs::write(&target_path, &target).unwrap();
+
+        let delta_path = folder.path().join("target.delta");
+        let delta_size = create_delta(&base_path, &target_path, &delta_path).unwrap();
+        assert_eq!(delta_size, std::fs::metadata(&delta_path).unwrap().len());
+        assert!(delta_size < target.len() as u64 / 2);
+
+        let patched_path = folder.path().join("patched.bin");
+        apply_delta(&base_path, &delta_path, &patched_path).unwrap();
+        assert_eq!(std::fs::read(&patched_path).unwrap(), target);
+    }
+
+    #[test]
+    fn delta_of_empty_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let (base_path, target_path) = (folder.path().join("base.bin"), folder.path().join("target.bin"));
+        std::fs::write(&base_path, []).unwrap();
+        std::fs::write(&target_path, random_bytes(100, 4)).unwrap();
+
+        let delta_path = folder.path().join("target.delta");
+        create_delta(&base_path, &target_path, &delta_path).unwrap();
+        let patched_path = folder.path().join("patched.bin");
+        apply_delta(&base_path, &delta_path, &patched_path).unwrap();
+        assert_eq!(std::fs::read(&patched_path).unwrap(), std::fs::read(&target_path).unwrap());
+    }
+
+    #[test]
+    fn apply_delta_rejects_another_base() {
+        let folder = tempfile::tempdir().unwrap();
+        let (base_path, target_path) = (folder.path().join("base.bin"), folder.path().join("target.bin"));
+        std::fs::write(&base_path, random_bytes(40 * 1024, 5)).unwrap();
+        std::fs::write(&target_path, random_bytes(40 * 1024, 6)).unwrap();
+        let delta_path = folder.path().join("target.delta");
+        create_delta(&base_path, &target_path, &delta_path).unwrap();
+
+        std::fs::write(&base_path, random_bytes(1000, 5)).unwrap();
+        let error = apply_delta(&base_path, &delta_path, &folder.path().join("patched.bin")).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn create_deltas_keeps_the_download() {
+        let folder = tempfile::tempdir().unwrap();
+        let (download_path, base_path) = (folder.path().join("Download"), folder.path().join("Base"));
+        std::fs::create_dir_all(download_path.join("Data")).unwrap();
+        std::fs::create_dir_all(base_path.join("Data")).unwrap();
+        let base = random_bytes(100 * 1024, 7);
+        let mut changed = base.clone();
+        changed[10] ^= 0xFF;
+        std::fs::write(base_path.join("Data/changed.pak"), &base).unwrap();
+        std::fs::write(download_path.join("Data/changed.pak"), &changed).unwrap();
+        // Without a copy in the initial build the file stays a full file
+        std::fs::write(download_path.join("Data/new.pak"), random_bytes(1000, 8)).unwrap();
+
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let modified = ["Data\\changed.pak".to_string(), "Data\\new.pak".to_string()];
+        let plan = create_deltas(&download_path, &base_path, &modified, &sender).unwrap();
+        assert!(plan.records[0].strategy == FileStrategy::Delta);
+        assert_eq!(plan.records[0].path, "Data/changed.pak");
+        assert!(plan.records[1].strategy == FileStrategy::Full);
+        assert_eq!(std::fs::read(download_path.join("Data/changed.pak")).unwrap(), changed);
+
+        let delta_path = download_path.join(INSTALLER_FOLDER).join(plan.records[0].delta_path.as_ref().unwrap());
+        let patched_path = folder.path().join("patched.pak");
+        apply_delta(&base_path.join("Data/changed.pak"), &delta_path, &patched_path).unwrap();
+        assert_eq!(sha256_file(&patched_path).unwrap(), plan.records[0].sha256);
+    }
+}
@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Default, Deserialize)]
+#[derive(Clone, Default, Deserialize, Serialize)]
 pub struct Changes {
     pub name: String,
     pub app: String,
@@ -10,10 +10,20 @@ pub struct Changes {
     pub added: Vec<String>,
     pub removed: Vec<String>,
     pub modified: Vec<String>,
-    pub manifest: String
+    pub manifest: String,
+    /// Older scripts leave out the branch, which then is the public one.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub branch: String,
 }
 
 impl Changes {
+    pub fn branch(&self) -> &str {
+        match self.branch.is_empty() {
+            true => "public",
+            false => &self.branch,
+        }
+    }
+
     pub fn new_error(error: String) -> Changes {
         Changes {
             name: String::new(),
@@ -25,6 +35,7 @@ impl Changes {
             removed: vec![],
             modified: vec![],
             manifest: error,
+            branch: String::new(),
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use sha2::{Digest, Sha256};
+use crate::modules::reproducible::to_hex;
+
+pub const SHA256_EXTENSION: &str = "sha256";
+pub const SFV_EXTENSION: &str = "sfv";
+
+/// The hashes of one archive or volume.
+#[derive(Clone)]
+pub struct FileChecksum {
+    pub file_name: String,
+    pub sha256: String,
+    pub crc32: u32,
+}
+
+impl FileChecksum {
+    /// Reads the file once for both hashes.
+    pub fn compute(path: &Path) -> std::io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut sha256 = Sha256::new();
+        let mut crc32 = crc32fast::Hasher::new();
+        let mut buffer = vec![0; 1024 * 1024];
+        loop {
+            let read = file.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            sha256.update(&buffer[..read]);
+            crc32.update(&buffer[..read]);
+        }
+        Ok(Self {
+            file_name: path.file_name().unwrap().to_string_lossy().to_string(),
+            sha256: to_hex(&sha256.finalize()),
+            crc32: crc32.finalize(),
+        })
+    }
+}
+
+/// Checksums of every volume of an archive, written as a `.sha256` and a `.sfv` file next to them.
+#[derive(Clone, Default)]
+pub struct Checksums {
+    pub files: Vec<FileChecksum>,
+}
+
+impl Checksums {
+    pub fn compute(files: &[PathBuf]) -> std::io::Result<Self> {
+        Ok(Self {
+            files: files.iter().map(|file| FileChecksum::compute(file)).collect::<std::io::Result<_>>()?,
+        })
+    }
+
+    /// The format `sha256sum --check` reads.
+    pub fn sha256_file(&self) -> String {
+        self.files.iter().map(|file| format!("{} *{}\n", file.sha256, file.file_name)).collect()
+    }
+
+    pub fn sfv_file(&self) -> String {
+        let mut sfv = "; Generated by RedAlt-SteamUp-Creator\n".to_string();
+        for file in &self.files {
+            sfv += &format!("{} {:08X}\n", file.file_name, file.crc32);
+        }
+        sfv
+    }
+
+    /// Writes both checksum files into the folder, named after the update, and returns their paths.
+    pub fn write(&self, folder: &Path, name: &str) -> std::io::Result<(PathBuf, PathBuf)> {
+        let sha256_path = folder.join(format!("{}.{}", name, SHA256_EXTENSION));
+        let sfv_path = folder.join(format!("{}.{}", name, SFV_EXTENSION));
+        std::fs::write(&sha256_path, self.sha256_file())?;
+        std::fs::write(&sfv_path, self.sfv_file())?;
+        Ok((sha256_path, sfv_path))
+    }
+}
+
+impl Display for Checksums {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for file in &self.files {
+            writeln!(f, "{}", file.file_name)?;
+            writeln!(f, "  SHA-256: {}", file.sha256)?;
+            writeln!(f, "  CRC32: {:08X}", file.crc32)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn is_checksum_file(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == SHA256_EXTENSION || extension == SFV_EXTENSION)
+}
+
+/// Checks every file listed in a `.sha256` or `.sfv` file, which are looked for next to it.
+/// Returns each file with whether it matched, or why not.
+pub fn verify_checksum_file(path: &Path) -> std::io::Result<Vec<(String, Result<(), String>)>> {
+    let is_sfv = path.extension().is_some_and(|extension| extension == SFV_EXTENSION);
+    let folder = path.parent().unwrap_or(Path::new(""));
+    let mut results = Vec::new();
+    for line in std::fs::read_to_string(path)?.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+        // sha256sum puts the hash first, SFV puts the CRC last
+        let parsed = match is_sfv {
+            true => line.rsplit_once(' ').map(|(name, crc32)| (name.trim(), crc32)),
+            false => line.split_once(' ').map(|(sha256, name)| (name.trim_start().trim_start_matches('*'), sha256)),
+        };
+        let Some((name, expected)) = parsed else {
+            results.push((line.to_string(), Err("the line could not be read".to_string())));
+            continue;
+        };
+
+        let result = match FileChecksum::compute(&folder.join(name)) {
+            Err(error) => Err(error.to_string()),
+            Ok(checksum) => {
+                let actual = match is_sfv {
+                    true => format!("{:08X}", checksum.crc32),
+                    false => checksum.sha256,
+                };
+                match actual.eq_ignore_ascii_case(expected) {
+                    true => Ok(()),
+                    false => Err(format!("expected {}, got {}", expected, actual)),
+                }
+            }
+        };
+        results.push((name.to_string(), result));
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_volumes(folder: &Path) -> Vec<PathBuf> {
+        let volumes = vec![folder.join("Game.7z.001"), folder.join("Game.7z.002")];
+        std::fs::write(&volumes[0], "hello").unwrap();
+        std::fs::write(&volumes[1], "world").unwrap();
+        volumes
+    }
+
+    #[test]
+    fn formats_sha256_and_sfv_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let checksums = Checksums::compute(&write_volumes(folder.path())).unwrap();
+        assert_eq!(checksums.sha256_file(), "\
+            2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824 *Game.7z.001\n\
+            486ea46224d1bb4fb680f34f7c9ad96a8f24ec88be73ea8e5a6c65260e9cb8a7 *Game.7z.002\n");
+        assert_eq!(checksums.sfv_file(), "; Generated by RedAlt-SteamUp-Creator\nGame.7z.001 3610A686\nGame.7z.002 3A771143\n");
+    }
+
+    #[test]
+    fn verifies_the_written_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let checksums = Checksums::compute(&write_volumes(folder.path())).unwrap();
+        let (sha256_path, sfv_path) = checksums.write(folder.path(), "Game").unwrap();
+        assert!(is_checksum_file(&sha256_path) && is_checksum_file(&sfv_path));
+        for path in [&sha256_path, &sfv_path] {
+            let results = verify_checksum_file(path).unwrap();
+            assert_eq!(results.len(), 2);
+            assert!(results.iter().all(|(_, result)| result.is_ok()));
+        }
+
+        std::fs::write(folder.path().join("Game.7z.002"), "WORLD").unwrap();
+        std::fs::remove_file(folder.path().join("Game.7z.001")).unwrap();
+        for path in [&sha256_path, &sfv_path] {
+            let results = verify_checksum_file(path).unwrap();
+            assert_eq!(results[0].0, "Game.7z.001");
+            assert!(results.iter().all(|(_, result)| result.is_err()));
+        }
+    }
+}
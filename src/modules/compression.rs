@@ -1,24 +1,60 @@
+use std::collections::BTreeSet;
 use std::env::current_dir;
 use std::fmt::{Display, Formatter};
 use std::path::PathBuf;
+use crossbeam_channel::{Receiver, Sender};
 use egui_file::FileDialog;
 use serde::{Deserialize, Serialize};
-use winreg::enums::HKEY_LOCAL_MACHINE;
-use winreg::RegKey;
-use crate::modules::compression_settings::{SevenZipSettings, WinRARSettings};
+use crate::modules::archiver_backend::ArchiverBackend;
+use crate::modules::archiver_detection::{DetectedArchiver, find_archivers};
+use crate::modules::binary_delta::{FileStrategy, create_deltas};
+use crate::modules::checksums::Checksums;
+use crate::modules::compression_progress::CompressionProgress;
+use crate::modules::compression_settings::{SevenZipArchiveFormat, SevenZipSettings, WinRARSettings};
+use crate::modules::content_classification::ContentPlan;
+use crate::modules::job_summary::JobSummary;
+use crate::modules::native_archiver::NativeSettings;
+use crate::modules::paths;
+use crate::modules::memory::memory_budget;
+use crate::modules::native_archiver::collect_entries;
+use crate::modules::package_metadata::{INSTALLER_FOLDER, PackageMetadata};
+use crate::modules::presets::CompressionPreset;
+use crate::modules::size_estimation::{SampleCodec, SizeEstimate, estimate_archive_size};
+use crate::modules::torrent::{TORRENT_EXTENSION, create_torrent};
+use crate::modules::reproducible::{REPRODUCIBLE_THREADS, write_input_manifest};
+use crate::modules::upload_destinations::UploadDestination;
+use crate::modules::verification::find_volumes;
 
 #[derive(Clone, Deserialize, PartialEq, Serialize)]
 pub enum Archiver {
     SevenZip,
     WinRAR,
-    // Zip
+    Native,
 }
 
 impl Display for Archiver {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
             Archiver::SevenZip => "7zip",
-            Archiver::WinRAR => "WinRAR"
+            Archiver::WinRAR => "WinRAR",
+            Archiver::Native => "Built-in",
+        })
+    }
+}
+
+/// When the downloaded files are removed. They are kept until the archive passed verification,
+/// so that Depot Downloader can resume or verify the download if anything fails.
+#[derive(Clone, Copy, Deserialize, PartialEq, Serialize)]
+pub enum CleanupPolicy {
+    Never,
+    AfterVerifiedArchive,
+}
+
+impl Display for CleanupPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            CleanupPolicy::Never => "Never",
+            CleanupPolicy::AfterVerifiedArchive => "After the archive passed verification",
         })
     }
 }
@@ -27,6 +63,7 @@ impl Display for Archiver {
 #[serde(default)]
 pub struct CompressionSettings {
     pub download_path: PathBuf,
+    pub output_root: PathBuf,
     pub archiver: Archiver,
     #[serde(skip)]
     pub open_archiver_dialog: Option<FileDialog>,
@@ -34,6 +71,34 @@ pub struct CompressionSettings {
     pub archiver_path: Option<PathBuf>,
     pub seven_zip_settings: SevenZipSettings,
     pub win_rar_settings: WinRARSettings,
+    pub native_settings: NativeSettings,
+    pub presets: Vec<CompressionPreset>,
+    pub upload_destinations: Vec<UploadDestination>,
+    #[serde(skip)]
+    pub selected_destination: usize,
+    #[serde(skip)]
+    pub selected_preset: usize,
+    #[serde(skip)]
+    pub preset_export_dialog: Option<FileDialog>,
+    #[serde(skip)]
+    pub preset_import_dialog: Option<FileDialog>,
+    /// Test the archive and compare it with the downloaded files after compressing.
+    pub verify_archives: bool,
+    pub cleanup_policy: CleanupPolicy,
+    /// Build archives which are identical for identical downloads.
+    pub reproducible: bool,
+    /// Store already compressed files without compressing them again and group the others by type.
+    pub content_aware: bool,
+    /// Build a .torrent for every finished archive.
+    pub create_torrent: bool,
+    pub torrent_trackers: Vec<String>,
+    /// A warning is shown when compressing needs more than this share of the available memory.
+    pub memory_warning_percentage: u8,
+    #[serde(skip)]
+    pub detected_archivers: Option<Vec<DetectedArchiver>>,
+    /// The 7-Zip executable which was last checked for zstd support, and whether it has it.
+    #[serde(skip)]
+    pub seven_zip_zstd_support: Option<(PathBuf, bool)>,
     pub multiup_direct_path: Option<PathBuf>,
     #[serde(skip)]
     pub multiup_direct_file_dialog: Option<FileDialog>,
@@ -43,24 +108,34 @@ impl Default for CompressionSettings {
     fn default() -> Self {
         Self {
             download_path: current_dir().unwrap().to_path_buf(),
+            output_root: paths::default_output_root(),
             archiver: {
-                let paths = CompressionSettings::get_detected_paths();
-                let mut archiver = Archiver::SevenZip;
-                for path in paths.into_iter().flatten() {
-                    if path.contains("7z.exe") {
-                        archiver = Archiver::SevenZip;
-
-                        break; // Use 7zip if possible instead of WinRAR
-                    } else if path.contains("WinRAR.exe") {
-                        archiver = Archiver::WinRAR;
-                    }
-                }
-                archiver
+                // Use 7zip if possible instead of WinRAR, and the built-in archiver if neither is installed
+                let detected = find_archivers();
+                [Archiver::SevenZip, Archiver::WinRAR].into_iter()
+                    .find(|archiver| detected.iter().any(|detected| &detected.archiver == archiver && detected.can_compress))
+                    .unwrap_or(Archiver::Native)
             },
             open_archiver_dialog: None,
             archiver_path: None,
             seven_zip_settings: SevenZipSettings::default(),
             win_rar_settings: WinRARSettings::default(),
+            native_settings: NativeSettings::default(),
+            presets: Vec::new(),
+            upload_destinations: Vec::new(),
+            selected_destination: 0,
+            selected_preset: 0,
+            preset_export_dialog: None,
+            preset_import_dialog: None,
+            verify_archives: true,
+            cleanup_policy: CleanupPolicy::Never,
+            reproducible: false,
+            content_aware: true,
+            create_torrent: false,
+            torrent_trackers: Vec::new(),
+            memory_warning_percentage: 80,
+            detected_archivers: None,
+            seven_zip_zstd_support: None,
             multiup_direct_path: {
                 let mut executable = None;
                 for file in current_dir().unwrap().read_dir().unwrap().flatten() {
@@ -76,36 +151,319 @@ impl Default for CompressionSettings {
     }
 }
 
-impl CompressionSettings {
-    pub fn get_detected_paths() -> Vec<Option<String>> {
-        let mut paths: Vec<Option<String>> = Vec::new();
-        // Try to find 7zip in the registry
-        let hklm = RegKey::predef(HKEY_LOCAL_MACHINE);
-        let subkey = r"SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths";
-
-        let keys = [(format!("{}\\7zFM.exe", subkey), "7z.exe"), (format!("{}\\WinRAR.exe", subkey), "\\WinRAR.exe")];
-        for (key, file) in keys {
-            if let Ok(key) = hklm.open_subkey(key) {
-                if let Ok(path) = key.get_value("Path") {
-                    let mut path: String = path;
-                    path += file;
-                    paths.push(Some(path));
-                } else {
-                    paths.push(None);
-                }
+/// Everything needed to compress one download, copied from the settings when the job starts.
+pub struct CompressionJob {
+    pub archiver: Archiver,
+    pub download_path: PathBuf,
+    pub output_root: PathBuf,
+    pub seven_zip_settings: SevenZipSettings,
+    pub win_rar_settings: WinRARSettings,
+    pub native_settings: NativeSettings,
+    pub verify_archives: bool,
+    pub cleanup_policy: CleanupPolicy,
+    pub reproducible: bool,
+    pub content_aware: bool,
+    pub create_torrent: bool,
+    pub torrent_trackers: Vec<String>,
+    /// A warning is shown when compressing needs more than this share of the available memory.
+    pub memory_warning_percentage: u8,
+    /// Describes the update in the torrent, such as its app, depot and builds.
+    pub torrent_comment: String,
+    /// The initial build, to store modified files as deltas against.
+    pub delta_base_path: Option<PathBuf>,
+    /// The modified files of the update, as listed in the changes.
+    pub modified_files: Vec<String>,
+    /// Describes the update, written into the installer folder once the files are hashed.
+    pub metadata: Option<PackageMetadata>,
+}
+
+impl CompressionJob {
+    pub fn new(compression_settings: &CompressionSettings) -> Self {
+        let mut job = Self {
+            archiver: compression_settings.archiver.clone(),
+            download_path: compression_settings.download_path.clone(),
+            output_root: compression_settings.output_root.clone(),
+            seven_zip_settings: compression_settings.seven_zip_settings.clone(),
+            win_rar_settings: compression_settings.win_rar_settings.clone(),
+            native_settings: compression_settings.native_settings.clone(),
+            verify_archives: compression_settings.verify_archives,
+            cleanup_policy: compression_settings.cleanup_policy,
+            reproducible: compression_settings.reproducible,
+            content_aware: compression_settings.content_aware,
+            create_torrent: compression_settings.create_torrent,
+            torrent_trackers: compression_settings.torrent_trackers.clone(),
+            memory_warning_percentage: compression_settings.memory_warning_percentage,
+            torrent_comment: String::new(),
+            delta_base_path: None,
+            modified_files: Vec::new(),
+            metadata: None,
+        };
+        job.fix_parameters();
+        job
+    }
+
+    pub fn apply_preset(&mut self, preset: &CompressionPreset) {
+        preset.apply(&mut self.seven_zip_settings, &mut self.win_rar_settings);
+        self.fix_parameters();
+    }
+
+    /// Reproducible archives use the same thread count on every computer, as the archivers split
+    /// the input between their threads.
+    fn fix_parameters(&mut self) {
+        if !self.reproducible {
+            return;
+        }
+        self.seven_zip_settings.reproducible = true;
+        self.seven_zip_settings.number_of_cpu_threads = REPRODUCIBLE_THREADS;
+        self.win_rar_settings.reproducible = true;
+        self.win_rar_settings.number_of_cpu_threads = REPRODUCIBLE_THREADS;
+        self.native_settings.reproducible = true;
+        self.native_settings.number_of_cpu_threads = REPRODUCIBLE_THREADS;
+    }
+
+    /// Bytes to compress, without Depot Downloader's own files which are not archived.
+    pub fn input_size(&self) -> u64 {
+        collect_entries(&self.download_path, "")
+            .map(|entries| entries.iter().map(|entry| entry.size).sum())
+            .unwrap_or(0)
+    }
+
+    /// Splits the archive into volumes the destination accepts, or not at all if an archive of
+    /// about `archive_bytes` fits. Estimates can be low, so archives within a tenth of the limit
+    /// are split too, which leaves them in one volume if they do fit.
+    pub fn apply_destination(&mut self, destination: &UploadDestination, archive_bytes: u64) {
+        let (split_size, split_size_unit) = destination.split_size(archive_bytes + archive_bytes / 10)
+            .unwrap_or((0, destination.max_file_size_unit));
+        self.seven_zip_settings.split_size = split_size;
+        self.seven_zip_settings.split_size_unit = split_size_unit;
+        self.win_rar_settings.split_size = split_size;
+        self.win_rar_settings.split_size_unit = split_size_unit;
+        self.native_settings.split_size = split_size;
+        self.native_settings.split_size_unit = split_size_unit;
+    }
+
+    /// Uses the password for this job only, instead of the one in the settings.
+    pub fn set_password(&mut self, password: &str) {
+        self.seven_zip_settings.password = password.to_string();
+        self.win_rar_settings.password = password.to_string();
+    }
+
+    /// Compresses the download, verifies the archive if enabled and writes the job's summary.
+    pub fn run(
+        &mut self,
+        input_receiver: Receiver<String>,
+        output_sender: Sender<String>,
+        progress_sender: Sender<CompressionProgress>,
+    ) -> std::io::Result<JobSummary> {
+        let (compression_memory, decompression_memory) = self.memory_usage();
+        let budget = memory_budget(self.memory_warning_percentage);
+        if compression_memory > budget {
+            let _ = output_sender.send(format!("\nWarning: compressing needs about {} MB, more than the {}% of the available memory ({} MB) it should use.\n",
+                                               compression_memory, self.memory_warning_percentage, budget));
+        }
+        // Packages without an installer folder, such as entire depots, have no metadata
+        if self.download_path.join(INSTALLER_FOLDER).is_dir() {
+            let mut metadata = PackageMetadata::read(&self.download_path);
+            metadata.decompression_memory = Some(decompression_memory);
+            metadata.write(&self.download_path)?;
+        }
+        let input_hash = match self.reproducible {
+            true => {
+                let _ = output_sender.send("\nHashing the input files...\n".to_string());
+                Some(write_input_manifest(&self.download_path, |name| self.native_settings.excluded_files.contains(name))?)
             }
+            false => None,
+        };
+
+        if self.content_aware && self.compression_level() > 0 {
+            let _ = output_sender.send("\nLooking for files which are already compressed...\n".to_string());
+            let plan = ContentPlan::new(&self.download_path, self.compression_level())?;
+            let _ = output_sender.send(plan.to_string());
+            self.seven_zip_settings.content_plan = Some(plan.clone());
+            self.win_rar_settings.content_plan = Some(plan.clone());
+            self.native_settings.content_plan = Some(plan);
+        }
+
+        let download_path = self.download_path.clone();
+        let output_root = self.output_root.clone();
+        match self.archiver {
+            Archiver::SevenZip => self.seven_zip_settings.compress(download_path, output_root, input_receiver, output_sender.clone(), progress_sender)?,
+            Archiver::WinRAR => self.win_rar_settings.compress(download_path, output_root, input_receiver, output_sender.clone(), progress_sender)?,
+            Archiver::Native => self.native_settings.compress(download_path, output_root, output_sender.clone(), progress_sender)?,
+        }
+
+        let mut summary = self.summary();
+        summary.input_hash = input_hash;
+        if self.verify_archives {
+            let report = match self.archiver {
+                Archiver::SevenZip => self.seven_zip_settings.verify(&self.download_path, &self.output_root, output_sender.clone()),
+                Archiver::WinRAR => self.win_rar_settings.verify(&self.download_path, &self.output_root, output_sender.clone()),
+                Archiver::Native => self.native_settings.verify(&self.download_path, &self.output_root, output_sender.clone()),
+            };
+            let _ = output_sender.send(format!("\n{}", report));
+            let report_path = report.write()?;
+            summary.verified = Some(report.passed());
+            if !report.passed() {
+                summary.write(&self.output_root)?;
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                               format!("the archive failed verification, see {}", report_path.display())));
+            }
+        }
+
+        let volumes = find_volumes(&summary.archive_path, self.is_split());
+        let checksums = Checksums::compute(&volumes)?;
+        let (sha256_path, sfv_path) = checksums.write(summary.archive_path.parent().unwrap(), &summary.name)?;
+        let _ = output_sender.send(format!("\nSaved the checksums to {} and {}.\n", sha256_path.display(), sfv_path.display()));
+        summary.checksums = Some(checksums);
+        if self.create_torrent {
+            // Split archives are shared as their folder of volumes
+            let content = match self.is_split() {
+                true => summary.archive_path.parent().unwrap().to_path_buf(),
+                false => summary.archive_path.clone(),
+            };
+            let _ = output_sender.send("\nCreating the torrent...\n".to_string());
+            let torrent_path = self.output_root.join(format!("{}.{}", summary.name, TORRENT_EXTENSION));
+            let torrent = create_torrent(&content, &torrent_path, &self.torrent_trackers, &self.torrent_comment)?;
+            let _ = output_sender.send(format!("Saved the torrent to {}.\n", torrent.path.display()));
+            summary.torrent = Some(torrent);
+        }
+
+        let summary_path = summary.write(&self.output_root)?;
+        let _ = output_sender.send(format!("\n{}Saved the summary to {}.\n", summary, summary_path.display()));
+        self.clean_up(&summary, &output_sender);
+        Ok(summary)
+    }
+
+    /// Hashes the downloaded files and writes the package metadata.
+    pub fn write_metadata(&mut self, output_sender: &Sender<String>) -> std::io::Result<()> {
+        let Some(metadata) = &mut self.metadata else {
+            return Ok(());
+        };
+        let _ = output_sender.send("\nHashing the downloaded files for the package metadata...\n".to_string());
+        metadata.hash_files(&self.download_path)?;
+        metadata.write(&self.download_path)?;
+        let _ = output_sender.send(format!("Saved the metadata of {} files to {}.\n",
+                                           metadata.files.len(), PackageMetadata::path(&self.download_path).display()));
+        Ok(())
+    }
+
+    /// Creates deltas for modified files where the initial build has a copy of them, leaves those
+    /// files out of the archive and records the strategy of every modified file in the package metadata.
+    pub fn create_deltas(&mut self, output_sender: &Sender<String>) -> std::io::Result<()> {
+        let Some(base_path) = &self.delta_base_path else {
+            return Ok(());
+        };
+        if !self.download_path.join(INSTALLER_FOLDER).is_dir() {
+            return Ok(());
+        }
+        let _ = output_sender.send(format!("\nCreating deltas against {}...\n", base_path.display()));
+        let plan = create_deltas(&self.download_path, base_path, &self.modified_files, output_sender)?;
+        let _ = output_sender.send(plan.to_string());
+        let folder_name = self.download_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let excluded_files: BTreeSet<String> = plan.records.iter()
+            .filter(|record| record.strategy == FileStrategy::Delta)
+            .map(|record| format!("{}/{}", folder_name, record.path))
+            .collect();
+        self.seven_zip_settings.excluded_files = excluded_files.clone();
+        self.win_rar_settings.excluded_files = excluded_files.clone();
+        self.native_settings.excluded_files = excluded_files;
+        let mut metadata = PackageMetadata::read(&self.download_path);
+        metadata.file_strategies = plan.records;
+        metadata.write(&self.download_path)
+    }
+
+    /// Estimates the archive's size before compressing, so a job which will not fit can be stopped.
+    pub fn estimate_size(&self, output_sender: &Sender<String>) -> std::io::Result<SizeEstimate> {
+        let _ = output_sender.send("\nEstimating the archive size...\n".to_string());
+        let estimate = estimate_archive_size(&self.download_path, self.sample_codec())?;
+        let _ = output_sender.send(format!("The archive will be {}.\n", estimate));
+        Ok(estimate)
+    }
+
+    /// The memory needed to compress and to decompress the archive in MB.
+    pub fn memory_usage(&self) -> (u64, u64) {
+        match self.archiver {
+            Archiver::SevenZip => self.seven_zip_settings.memory_usage(),
+            Archiver::WinRAR => self.win_rar_settings.memory_usage(),
+            Archiver::Native => self.native_settings.memory_usage(),
         }
-        paths
     }
-}
 
-// pub fn compress_files(archiver: Archiver,
-//                       download_path: String,
-//                       seven_zip_settings: SevenZipSettings,
-//                       win_rar_settings: WinRARSettings,
-//                       input_window_opened_sender: Sender<bool>,
-//                       input_receiver: Receiver<String>,
-//                       output_sender: Sender<String>,
-//                       status_sender: Sender<std::io::Result<()>>) {
-//
-// }
\ No newline at end of file
+    pub fn is_split(&self) -> bool {
+        match self.archiver {
+            Archiver::SevenZip => self.seven_zip_settings.is_split(),
+            Archiver::WinRAR => self.win_rar_settings.is_split(),
+            Archiver::Native => self.native_settings.split_size > 0,
+        }
+    }
+
+    /// The selected archiver's level, from 0 (store) to 9 (ultra).
+    pub fn compression_level(&self) -> u8 {
+        match self.archiver {
+            Archiver::SevenZip => self.seven_zip_settings.compression_level,
+            Archiver::WinRAR => self.win_rar_settings.normalized_level(),
+            Archiver::Native => self.native_settings.compression_level,
+        }
+    }
+
+    /// The codec which estimates the selected archiver's archive size.
+    pub fn sample_codec(&self) -> SampleCodec {
+        match self.archiver {
+            Archiver::SevenZip => self.seven_zip_settings.sample_codec(),
+            Archiver::WinRAR => self.win_rar_settings.sample_codec(),
+            Archiver::Native => self.native_settings.sample_codec(),
+        }
+    }
+
+    fn clean_up(&self, summary: &JobSummary, output_sender: &Sender<String>) {
+        match (self.cleanup_policy, summary.verified) {
+            (CleanupPolicy::AfterVerifiedArchive, Some(true)) => {
+                let _ = match std::fs::remove_dir_all(&self.download_path) {
+                    Ok(_) => output_sender.send(format!("Removed the downloaded files in {}.\n", self.download_path.display())),
+                    Err(error) => output_sender.send(format!("Failed to remove the downloaded files in {}: {}.\n", self.download_path.display(), error)),
+                };
+            }
+            (CleanupPolicy::AfterVerifiedArchive, _) => {
+                let _ = output_sender.send("The downloaded files were kept, as the archive was not verified.\n".to_string());
+            }
+            (CleanupPolicy::Never, _) => {}
+        }
+    }
+
+    fn summary(&self) -> JobSummary {
+        let name = self.download_path.file_name().unwrap().to_str().unwrap().to_string();
+        let (archiver, archive_path, password, hide_file_names) = match self.archiver {
+            Archiver::SevenZip => {
+                let settings = &self.seven_zip_settings;
+                (settings.name(),
+                 settings.archive_path(&self.download_path, &self.output_root),
+                 Some(settings.password.clone()).filter(|password| !password.is_empty() && settings.supports_password()),
+                 settings.hide_file_names && settings.archive_format == SevenZipArchiveFormat::SevenZip)
+            }
+            Archiver::WinRAR => {
+                let settings = &self.win_rar_settings;
+                (settings.name(),
+                 settings.archive_path(&self.download_path, &self.output_root),
+                 Some(settings.password.clone()).filter(|password| !password.is_empty()),
+                 settings.hide_file_names)
+            }
+            // The built-in archiver cannot encrypt archives
+            Archiver::Native => ("Built-in",
+                                 self.native_settings.archive_path(&self.download_path, &self.output_root),
+                                 None,
+                                 false),
+        };
+        JobSummary {
+            name,
+            archiver: archiver.to_string(),
+            archive_path,
+            password,
+            hide_file_names,
+            decompression_memory: self.memory_usage().1,
+            verified: None,
+            input_hash: None,
+            checksums: None,
+            torrent: None,
+        }
+    }
+}
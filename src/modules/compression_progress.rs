@@ -0,0 +1,159 @@
+use std::time::{Duration, Instant};
+use crossbeam_channel::Sender;
+
+#[derive(Clone, Default)]
+pub struct CompressionProgress {
+    /// From 0 to 1, over every step of the compression.
+    pub fraction: f32,
+    pub current_file: String,
+    /// Input bytes compressed per second, averaged since the start.
+    pub bytes_per_second: f64,
+    pub remaining: Option<Duration>,
+}
+
+/// Turns the percentages reported by an archiver into progress updates for the UI.
+pub struct ProgressTracker {
+    sender: Sender<CompressionProgress>,
+    total_bytes: u64,
+    started: Instant,
+    steps: usize,
+    step: usize,
+    current_file: String,
+    pending: String,
+}
+
+impl ProgressTracker {
+    pub fn new(total_bytes: u64, steps: usize, sender: Sender<CompressionProgress>) -> Self {
+        Self {
+            sender,
+            total_bytes,
+            started: Instant::now(),
+            steps: steps.max(1),
+            step: 0,
+            current_file: String::new(),
+            pending: String::new(),
+        }
+    }
+
+    pub fn next_step(&mut self) {
+        self.step = (self.step + 1).min(self.steps - 1);
+    }
+
+    /// `percentage` is the progress of the current step, the file is kept until another is reported.
+    pub fn update(&mut self, percentage: f32, current_file: Option<&str>) {
+        if let Some(file) = current_file.filter(|file| !file.is_empty()) {
+            self.current_file = file.to_string();
+        }
+
+        let fraction = ((self.step as f32 + percentage.clamp(0.0, 100.0) / 100.0) / self.steps as f32).min(1.0);
+        let elapsed = self.started.elapsed();
+        let bytes_per_second = match elapsed.as_secs_f64() {
+            seconds if seconds > 0.0 => self.total_bytes as f64 * fraction as f64 / seconds,
+            _ => 0.0,
+        };
+        // Wait for the first percent, as the estimate is meaningless before that
+        let remaining = (fraction >= 0.01).then(|| elapsed.mul_f32((1.0 - fraction) / fraction));
+
+        let _ = self.sender.send(CompressionProgress {
+            fraction,
+            current_file: self.current_file.clone(),
+            bytes_per_second,
+            remaining,
+        });
+    }
+
+    /// Splits the output into lines. Archivers redraw their progress with carriage returns and
+    /// backspaces, so those end a line too. Incomplete lines are kept until the next chunk.
+    pub fn split_lines(&mut self, chunk: &str) -> Vec<String> {
+        self.pending += chunk;
+        let mut lines: Vec<String> = self.pending.split(['\n', '\r', '\u{8}']).map(|line| line.to_string()).collect();
+        self.pending = lines.pop().unwrap_or_default();
+        lines.retain(|line| !line.trim().is_empty());
+        lines
+    }
+
+    /// Returns what is left of the output once the archiver has exited.
+    pub fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Parses the percentage at the start of 7-Zip's `-bsp1` progress, such as ` 42% 17 + folder\file`.
+pub fn parse_7zip_progress(line: &str) -> Option<(f32, Option<String>)> {
+    let (percentage, rest) = line.trim_start().split_once('%')?;
+    let percentage = percentage.parse::<f32>().ok()?;
+    // The number of files done and the operation, such as `+` or `U`, come before the file name
+    let rest = rest.trim_start()
+        .trim_start_matches(|c: char| c.is_ascii_digit())
+        .trim_start();
+    let file = match rest.split_once(' ') {
+        Some((operation, file)) if operation.chars().count() == 1 => file,
+        None if rest.chars().count() == 1 => "",
+        _ => rest,
+    }.trim();
+    Some((percentage, (!file.is_empty()).then(|| file.to_string())))
+}
+
+/// Parses rar's progress, which follows the file being added, such as `Adding    folder\file   42%`.
+/// The percentage is redrawn on its own after backspaces.
+pub fn parse_rar_progress(line: &str) -> Option<(f32, Option<String>)> {
+    let line = line.trim();
+    let (rest, last_word) = line.rsplit_once(char::is_whitespace).unwrap_or(("", line));
+    let percentage = last_word.strip_suffix('%')?.parse::<f32>().ok()?;
+    let file = ["Adding", "Updating"].into_iter()
+        .find_map(|operation| rest.trim_start().strip_prefix(operation))
+        .map(|file| file.trim().to_string());
+    Some((percentage, file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_7zip_progress() {
+        assert_eq!(parse_7zip_progress("  42% 13 + Game\\Content\\Paks\\Game.pak"), Some((42.0, Some("Game\\Content\\Paks\\Game.pak".to_string()))));
+        assert_eq!(parse_7zip_progress(" 5% U Update.bin"), Some((5.0, Some("Update.bin".to_string()))));
+        // Names which start like an operation are kept whole
+        assert_eq!(parse_7zip_progress("  7% 2 + Audio/Track.ogg"), Some((7.0, Some("Audio/Track.ogg".to_string()))));
+        assert_eq!(parse_7zip_progress("  9% + 2 Towers.pak"), Some((9.0, Some("2 Towers.pak".to_string()))));
+        assert_eq!(parse_7zip_progress(" 12% 3 +"), Some((12.0, None)));
+        assert_eq!(parse_7zip_progress("100%"), Some((100.0, None)));
+        assert_eq!(parse_7zip_progress("Everything is Ok"), None);
+    }
+
+    #[test]
+    fn parses_rar_progress() {
+        assert_eq!(parse_rar_progress("Adding    Game\\Game.exe      42%"), Some((42.0, Some("Game\\Game.exe".to_string()))));
+        assert_eq!(parse_rar_progress("Updating  Game\\My Save.sav  100%"), Some((100.0, Some("Game\\My Save.sav".to_string()))));
+        assert_eq!(parse_rar_progress(" 57%"), Some((57.0, None)));
+        assert_eq!(parse_rar_progress("Creating archive Game.rar"), None);
+    }
+
+    #[test]
+    fn splits_redrawn_lines() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let mut tracker = ProgressTracker::new(100, 1, sender);
+        assert_eq!(tracker.split_lines("  1%\r  2"), ["  1%"]);
+        assert_eq!(tracker.split_lines("%\u{8}\u{8}\u{8}\n\nDone"), ["  2%"]);
+        assert_eq!(tracker.finish(), "Done");
+        assert_eq!(tracker.finish(), "");
+    }
+
+    #[test]
+    fn reports_progress_over_every_step() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut tracker = ProgressTracker::new(1000, 2, sender);
+        tracker.update(50.0, Some("Game.pak"));
+        let progress = receiver.try_recv().unwrap();
+        assert_eq!(progress.fraction, 0.25);
+        assert_eq!(progress.current_file, "Game.pak");
+
+        tracker.next_step();
+        tracker.update(150.0, None);
+        let progress = receiver.try_recv().unwrap();
+        assert_eq!(progress.fraction, 1.0);
+        assert_eq!(progress.current_file, "Game.pak");
+        assert_eq!(progress.remaining, Some(Duration::ZERO));
+    }
+}
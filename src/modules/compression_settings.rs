@@ -1,315 +1,848 @@
-use std::env::current_dir;
-use std::fs::create_dir;
-use crate::modules::compression::CompressionSettings;
-use crossbeam_channel::{Receiver, Sender};
+use crate::modules::archiver_backend::ArchiverBackend;
+use crate::modules::archiver_detection::preferred_path;
+use crate::modules::compression::Archiver;
+use crate::modules::compression_progress::{parse_7zip_progress, parse_rar_progress};
+use crate::modules::content_classification::ContentPlan;
+use crate::modules::depot_downloader::DEPOT_DOWNLOADER_FOLDER;
+use crate::modules::paths;
+use crate::modules::size_estimation::SampleCodec;
 use serde::{Deserialize, Serialize};
-use std::io::{Read, Write};
-use std::os::windows::process::CommandExt;
-use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::sync::{Arc, Mutex};
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::ops::RangeInclusive;
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
 use std::thread;
 
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum SizeUnit {
+    Megabytes,
+    Gigabytes,
+}
+
+impl SizeUnit {
+    /// The suffix used by the archivers' command line switches.
+    pub fn switch(&self) -> &'static str {
+        match self {
+            SizeUnit::Megabytes => "m",
+            SizeUnit::Gigabytes => "g",
+        }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        match self {
+            SizeUnit::Megabytes => 1024 * 1024,
+            SizeUnit::Gigabytes => 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl Display for SizeUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            SizeUnit::Megabytes => "MB",
+            SizeUnit::Gigabytes => "GB",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum SevenZipArchiveFormat {
+    SevenZip,
+    Zip,
+    Tar,
+    /// xz only holds a single file, so the files are put into a tar first.
+    Xz,
+}
+
+impl SevenZipArchiveFormat {
+    pub const ALL: [SevenZipArchiveFormat; 4] = [
+        SevenZipArchiveFormat::SevenZip,
+        SevenZipArchiveFormat::Zip,
+        SevenZipArchiveFormat::Tar,
+        SevenZipArchiveFormat::Xz,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            SevenZipArchiveFormat::SevenZip => "7z",
+            SevenZipArchiveFormat::Zip => "zip",
+            SevenZipArchiveFormat::Tar => "tar",
+            SevenZipArchiveFormat::Xz => "tar.xz",
+        }
+    }
+
+    /// The value of the `-t` switch.
+    pub fn switch(&self) -> &'static str {
+        match self {
+            SevenZipArchiveFormat::SevenZip => "7z",
+            SevenZipArchiveFormat::Zip => "zip",
+            SevenZipArchiveFormat::Tar => "tar",
+            SevenZipArchiveFormat::Xz => "xz",
+        }
+    }
+
+    pub fn supports_method(&self, method: SevenZipCompressionMethod) -> bool {
+        match self {
+            SevenZipArchiveFormat::SevenZip => true,
+            SevenZipArchiveFormat::Zip => method != SevenZipCompressionMethod::Lzma2 && method != SevenZipCompressionMethod::Zstd,
+            SevenZipArchiveFormat::Tar => false,
+            SevenZipArchiveFormat::Xz => method == SevenZipCompressionMethod::Lzma2,
+        }
+    }
+
+    /// The method used when the selected one is not supported by the format.
+    pub fn default_method(&self) -> SevenZipCompressionMethod {
+        match self {
+            SevenZipArchiveFormat::Zip => SevenZipCompressionMethod::Deflate,
+            _ => SevenZipCompressionMethod::Lzma2,
+        }
+    }
+}
+
+impl Display for SevenZipArchiveFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum SevenZipCompressionMethod {
+    Lzma,
+    Lzma2,
+    Ppmd,
+    BZip2,
+    Deflate,
+    /// Only available in 7-Zip builds with the zstd codec, such as 7-Zip ZS.
+    Zstd,
+}
+
+impl SevenZipCompressionMethod {
+    pub const ALL: [SevenZipCompressionMethod; 6] = [
+        SevenZipCompressionMethod::Lzma,
+        SevenZipCompressionMethod::Lzma2,
+        SevenZipCompressionMethod::Ppmd,
+        SevenZipCompressionMethod::BZip2,
+        SevenZipCompressionMethod::Deflate,
+        SevenZipCompressionMethod::Zstd,
+    ];
+
+    /// The method's name in the `-m0=` and `-mm=` switches.
+    pub fn switch(&self) -> &'static str {
+        match self {
+            SevenZipCompressionMethod::Lzma => "LZMA",
+            SevenZipCompressionMethod::Lzma2 => "LZMA2",
+            SevenZipCompressionMethod::Ppmd => "PPMd",
+            SevenZipCompressionMethod::BZip2 => "BZip2",
+            SevenZipCompressionMethod::Deflate => "Deflate",
+            SevenZipCompressionMethod::Zstd => "zstd",
+        }
+    }
+
+    /// The parameter and range (in MB) of the dictionary, or the model memory for PPMd.
+    pub fn dictionary_parameter(&self) -> Option<(&'static str, &'static str, RangeInclusive<u16>)> {
+        match self {
+            SevenZipCompressionMethod::Lzma | SevenZipCompressionMethod::Lzma2 => Some(("d", "Dictionary size (MB):", 1..=2048)),
+            SevenZipCompressionMethod::Ppmd => Some(("mem", "Model memory (MB):", 1..=2048)),
+            _ => None,
+        }
+    }
+
+    /// The parameter and range of the word size, or the model order for PPMd.
+    pub fn word_parameter(&self) -> Option<(&'static str, &'static str, RangeInclusive<u32>)> {
+        match self {
+            SevenZipCompressionMethod::Lzma | SevenZipCompressionMethod::Lzma2 => Some(("fb", "Word size:", 5..=273)),
+            SevenZipCompressionMethod::Ppmd => Some(("o", "Model order:", 2..=32)),
+            SevenZipCompressionMethod::Deflate => Some(("fb", "Word size:", 3..=258)),
+            _ => None,
+        }
+    }
+
+    /// How many threads the method can use for a single stream.
+    pub fn max_threads(&self) -> u8 {
+        match self {
+            SevenZipCompressionMethod::Lzma => 2,
+            SevenZipCompressionMethod::Ppmd => 1,
+            _ => u8::MAX,
+        }
+    }
+
+    /// zstd uses its own levels from 1 to 22, which are mapped from 7-Zip's levels.
+    pub fn level(&self, compression_level: u8) -> u8 {
+        match self {
+            SevenZipCompressionMethod::Zstd => (compression_level * 2 + 1).min(22),
+            _ => compression_level,
+        }
+    }
+}
+
+impl Display for SevenZipCompressionMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.switch())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum WinRARArchiveFormat {
+    Rar,
+}
+
+impl WinRARArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            WinRARArchiveFormat::Rar => "rar",
+        }
+    }
+}
+
+impl Display for WinRARArchiveFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension().to_uppercase())
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct SevenZipSettings {
     pub path: Option<PathBuf>,
+    // Plain text passwords are only read from older settings files
+    #[serde(skip_serializing)]
     pub password: String,
+    pub password_nonce: [u8; 12],
+    pub encrypted_password: Vec<u8>,
+    /// Encrypts the archive's headers too, so the file names cannot be listed without the password.
+    pub hide_file_names: bool,
     // Compression settings
-    pub archive_format: String,
+    pub archive_format: SevenZipArchiveFormat,
     pub compression_level: u8,
-    pub compression_method: String,
+    pub compression_method: SevenZipCompressionMethod,
     pub dictionary_size: u16,
     pub word_size: u32,
     pub solid_block_size: u32,
-    pub solid_block_size_unit: String,
+    pub solid_block_size_unit: SizeUnit,
     pub number_of_cpu_threads: u8,
     pub split_size: u16,
-    pub split_size_unit: String,
+    pub split_size_unit: SizeUnit,
+    /// Set by reproducible jobs, which add a sorted file list without timestamps.
+    #[serde(skip)]
+    pub reproducible: bool,
+    /// Set by jobs which store already compressed files and group the others by type.
+    #[serde(skip)]
+    pub content_plan: Option<ContentPlan>,
+    /// Set by jobs which ship modified files as deltas, named like the entries of `collect_entries`.
+    #[serde(skip)]
+    pub excluded_files: BTreeSet<String>,
 }
 
 impl Default for SevenZipSettings {
     fn default() -> Self {
         Self {
-            path: {
-                let paths = CompressionSettings::get_detected_paths();
-                match paths.first() {
-                    Some(Some(path)) => Some(path.into()),
-                    _ => None,
-                }
-            },
+            path: preferred_path(Archiver::SevenZip),
             password: String::new(),
-            archive_format: "7z".to_string(),
+            password_nonce: [0; 12],
+            encrypted_password: Vec::new(),
+            hide_file_names: true,
+            archive_format: SevenZipArchiveFormat::SevenZip,
             compression_level: 9,
-            compression_method: "LZMA2".to_string(),
+            compression_method: SevenZipCompressionMethod::Lzma2,
             dictionary_size: 64,
             word_size: 273,
             solid_block_size: 16,
-            solid_block_size_unit: "g".to_string(),
+            solid_block_size_unit: SizeUnit::Gigabytes,
             number_of_cpu_threads: thread::available_parallelism().unwrap().get() as u8,
             split_size: 0,
-            split_size_unit: "g".to_string(),
+            split_size_unit: SizeUnit::Gigabytes,
+            reproducible: false,
+            content_plan: None,
+            excluded_files: BTreeSet::new(),
         }
     }
 }
 
 impl SevenZipSettings {
-    pub fn compress(
-        &self,
-        download_path: PathBuf,
-        input_window_opened_sender: Sender<bool>,
-        stdin_receiver: Receiver<String>,
-        stdout_sender: Sender<String>,
-    ) -> std::io::Result<()> {
-        let _ = stdout_sender.send("\nCompressing files with 7-Zip...\n".to_string());
-        let archiver_path = self.path.as_ref().unwrap().to_str().unwrap();
-        let mut command = Command::new(archiver_path);
-        let _ = std::fs::remove_dir_all(download_path.join(".DepotDownloader"));
-        let _ = std::fs::create_dir("./Completed");
-        command
-            .creation_flags(0x08000000)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .arg("a")
-            .arg(format!("-w{}", current_dir().unwrap().join("Completed").to_str().unwrap()))
-            .arg(format!("-mx{}", self.compression_level))
-            .arg(format!("-md{}m", self.dictionary_size))
-            .arg(format!("-mfb{}", self.word_size))
-            .arg(format!("-ms{}{}", self.solid_block_size, self.solid_block_size_unit))
-            .arg(format!("-mmt{}", self.number_of_cpu_threads));
-        if self.split_size > 0 {
-            command.arg(format!("-v{}{}", self.split_size, self.split_size_unit));
+    /// The switches for the level, method and its parameters, which depend on the format.
+    fn method_arguments(&self) -> Vec<String> {
+        let method = self.compression_method;
+        if self.archive_format == SevenZipArchiveFormat::Tar {
+            return Vec::new();
         }
-        if !self.password.is_empty() {
-            command.arg(format!("-p{}", self.password));
+        if self.compression_level == 0 {
+            return vec!["-mx0".to_string()];
         }
-        let split_folder = if self.split_size > 0 {
-            download_path.file_name().unwrap().to_str().unwrap()
-        } else {
-            ""
+
+        let mut arguments = vec![format!("-mx{}", method.level(self.compression_level))];
+        let dictionary = method.dictionary_parameter()
+            .map(|(parameter, _, _)| format!("{}={}m", parameter, self.dictionary_size));
+        let word = method.word_parameter()
+            .map(|(parameter, _, _)| format!("{}={}", parameter, self.word_size));
+        match self.archive_format {
+            SevenZipArchiveFormat::Zip => {
+                arguments.push(format!("-mm={}", method.switch()));
+                arguments.extend(dictionary.into_iter().chain(word).map(|parameter| format!("-m{}", parameter)));
+            }
+            _ => {
+                let mut method_switch = format!("-m0={}", method.switch());
+                for parameter in dictionary.into_iter().chain(word) {
+                    method_switch += &format!(":{}", parameter);
+                }
+                arguments.push(method_switch);
+            }
+        }
+
+        arguments.push(format!("-mmt{}", self.number_of_cpu_threads.min(method.max_threads())));
+        if self.archive_format == SevenZipArchiveFormat::SevenZip {
+            arguments.push(format!("-ms{}{}", self.solid_block_size, self.solid_block_size_unit.switch()));
+        }
+        arguments
+    }
+
+    /// The codec which estimates the archive's size, LZMA2 for the methods without a codec here.
+    pub fn sample_codec(&self) -> SampleCodec {
+        let method = self.compression_method;
+        if self.compression_level == 0 || self.archive_format == SevenZipArchiveFormat::Tar {
+            return SampleCodec::Store;
+        }
+        let dictionary_size = method.dictionary_parameter().map(|_| self.dictionary_size);
+        match method {
+            SevenZipCompressionMethod::Lzma | SevenZipCompressionMethod::Lzma2 => SampleCodec::Lzma2 { preset: self.compression_level, dictionary_size },
+            SevenZipCompressionMethod::Deflate => SampleCodec::Deflate { level: self.compression_level },
+            SevenZipCompressionMethod::Zstd => SampleCodec::Zstd { level: method.level(self.compression_level) as i32 },
+            // The PPMd model memory is no dictionary, so the preset's is used
+            SevenZipCompressionMethod::Ppmd | SevenZipCompressionMethod::BZip2 => {
+                SampleCodec::Substitute { method: method.switch(), preset: self.compression_level, dictionary_size: None }
+            }
+        }
+    }
+
+    /// The memory needed to compress and to decompress the archive in MB, modelled on how 7-Zip
+    /// allocates memory for each method.
+    pub fn memory_usage(&self) -> (u64, u64) {
+        if self.compression_level == 0 || self.archive_format == SevenZipArchiveFormat::Tar {
+            return (1, 1);
+        }
+
+        let method = self.compression_method;
+        let threads = self.number_of_cpu_threads.min(method.max_threads()).max(1) as u64;
+        match method {
+            SevenZipCompressionMethod::Lzma | SevenZipCompressionMethod::Lzma2 => self.lzma_memory_usage(),
+            // PPMd uses the same model to compress and decompress
+            SevenZipCompressionMethod::Ppmd => {
+                let size = self.dictionary_size as u64 + 2;
+                (size, size)
+            }
+            // Each thread compresses its own 900 KB block
+            SevenZipCompressionMethod::BZip2 => (10 * threads, 4),
+            SevenZipCompressionMethod::Deflate => (2 * threads, 1),
+            SevenZipCompressionMethod::Zstd => zstd_memory_usage(method.level(self.compression_level), threads),
+        }
+    }
+
+    fn lzma_memory_usage(&self) -> (u64, u64) {
+        let bytes_ratio = 1024 * 1024;
+        // Convert the dictionary size to bytes
+        let dictionary_size = self.dictionary_size as u64 * bytes_ratio;
+
+        let mut size = 0;
+
+        if self.archive_format == SevenZipArchiveFormat::SevenZip && self.compression_level == 9 {
+            size += 29 * bytes_ratio;
+        }
+
+        let mut hs = dictionary_size - 1;
+        hs |= hs >> 1;
+        hs |= hs >> 2;
+        hs |= hs >> 4;
+        hs |= hs >> 8;
+        hs >>= 1;
+        hs |= 0xFFFF;
+        if hs > (1 << 24) {
+            hs >>= 1;
+        }
+        hs += 1;
+
+        let mut size_1 = hs * 4;
+        size_1 += dictionary_size * 4;
+        if self.compression_level >= 5 {
+            size_1 += dictionary_size * 4;
+        }
+        size_1 += 2 * bytes_ratio;
+
+        let mut num_threads_1 = 1;
+        if self.number_of_cpu_threads > 1 && self.compression_level >= 5 {
+            size_1 += (2 * bytes_ratio) + (4 * bytes_ratio);
+            num_threads_1 = 2;
+        }
+
+        // LZMA only uses a second thread for the match finder, LZMA2 also compresses blocks in parallel
+        let num_block_threads = match self.compression_method {
+            SevenZipCompressionMethod::Lzma => 1,
+            _ => (self.number_of_cpu_threads / num_threads_1).max(1),
         };
-        command
-            .arg(format!("{}.7z", current_dir().unwrap().join("Completed").join(split_folder).join(download_path.file_name().unwrap()).to_str().unwrap()))
-            .arg(download_path);
-        let mut child = command.spawn()?;
-
-        let result = Arc::new(Mutex::new(Err(std::io::Error::new(std::io::ErrorKind::Other, "Unknown error"))));
-
-        thread::scope(|s| {
-            if let Some(mut stderr) = child.stderr.take() {
-                let stdo_sender = stdout_sender.clone();
-                let input_window_opened_sender = input_window_opened_sender.clone();
-                s.spawn(move || {
-                    let mut buffer = [0; 1024];
-                    loop {
-                        match stderr.read(&mut buffer) {
-                            Ok(n) if n > 0 => {
-                                let _ = stdo_sender.send(String::from_utf8_lossy(&buffer[..n]).parse().unwrap());
-                            }
-                            _ => break,
-                        }
-                    }
-                });
+
+        if num_block_threads == 1 {
+            size_1 += (dictionary_size * 3) / 2;
+        } else {
+            let mut chunk_size = dictionary_size * 4;
+            chunk_size = chunk_size.max(bytes_ratio);
+            chunk_size = chunk_size.min(256 * bytes_ratio);
+            chunk_size = chunk_size.max(dictionary_size);
+            size_1 += chunk_size * 2;
+        }
+
+        // calculate the solid block size
+        let mut solid_block_size = dictionary_size;
+        if self.compression_level == 9 {
+            solid_block_size *= num_block_threads as u64;
+        }
+
+        size += size_1 * num_block_threads as u64 + solid_block_size;
+
+        // Return the size for compression and decompression in MB
+        (
+            size.div_ceil(bytes_ratio),
+            (dictionary_size + 2 * bytes_ratio).div_ceil(bytes_ratio),
+        )
+    }
+
+    /// Lowers the threads, then the dictionary size, until compressing needs at most `budget` MB.
+    /// Returns whether the settings fit.
+    pub fn fit_to_memory(&mut self, budget: u64) -> bool {
+        while self.memory_usage().0 > budget {
+            if self.number_of_cpu_threads > 1 {
+                self.number_of_cpu_threads -= 1;
+            } else if self.compression_method.dictionary_parameter().is_some() && self.dictionary_size > 1 {
+                self.dictionary_size /= 2;
+            } else {
+                return false;
             }
+        }
+        true
+    }
+
+    /// tar and xz cannot be encrypted.
+    pub fn supports_password(&self) -> bool {
+        matches!(self.archive_format, SevenZipArchiveFormat::SevenZip | SevenZipArchiveFormat::Zip)
+    }
 
-            if let Some(mut stdout) = child.stdout.take() {
-                let stdo_sender = stdout_sender.clone();
-                let input_window_opened_sender = input_window_opened_sender.clone();
-                s.spawn(move || {
-                    let mut buffer = [0; 1024];
-                    loop {
-                        match stdout.read(&mut buffer) {
-                            Ok(n) if n > 0 => {
-                                let _ = stdo_sender.send(String::from_utf8_lossy(&buffer[..n]).parse().unwrap());
-                            }
-                            _ => break,
-                        }
-                    }
-                });
+    /// Keeps the method and its parameters within what the format and method support.
+    pub fn fit_to_method(&mut self) {
+        if !self.archive_format.supports_method(self.compression_method) {
+            self.compression_method = self.archive_format.default_method();
+        }
+        if let Some((_, _, range)) = self.compression_method.dictionary_parameter() {
+            self.dictionary_size = self.dictionary_size.clamp(*range.start(), *range.end());
+        }
+        if let Some((_, _, range)) = self.compression_method.word_parameter() {
+            self.word_size = self.word_size.clamp(*range.start(), *range.end());
+        }
+    }
+
+    /// The arguments which add `input`, a folder or a `@` list file, to the archive.
+    fn add_arguments(&self, input: String, archive_path: &Path) -> Vec<String> {
+        let mut arguments = vec![
+            "a".to_string(),
+            "-bsp1".to_string(),
+            format!("-w{}", archive_path.parent().unwrap().to_str().unwrap()),
+            format!("-t{}", self.archive_format.switch()),
+            format!("-xr!{}", DEPOT_DOWNLOADER_FOLDER),
+        ];
+        arguments.extend(self.method_arguments());
+        if self.split_size > 0 {
+            arguments.push(format!("-v{}{}", self.split_size, self.split_size_unit.switch()));
+        }
+        if !self.password.is_empty() && self.supports_password() {
+            arguments.push(format!("-p{}", self.password));
+            // zip cannot encrypt its headers
+            if self.hide_file_names && self.archive_format == SevenZipArchiveFormat::SevenZip {
+                arguments.push("-mhe=on".to_string());
             }
+        }
+        if self.archive_format == SevenZipArchiveFormat::SevenZip {
+            if self.reproducible {
+                arguments.extend(["-mtm=off".to_string(), "-mtc=off".to_string(), "-mta=off".to_string()]);
+            }
+            // Sorts the files by type, so similar files end up next to each other in solid blocks
+            if self.content_plan.is_some() && self.compression_level > 0 {
+                arguments.push("-mqs=on".to_string());
+            }
+        }
+        if input.starts_with('@') {
+            arguments.push("-scsUTF-8".to_string());
+        }
+        arguments.push(archive_path.to_str().unwrap().to_string());
+        arguments.push(input);
+        arguments
+    }
 
-            let stdin = Arc::new(Mutex::new(child.stdin.take().expect("Failed to take stdin")));
-            let result_clone = Arc::clone(&result);
-            s.spawn(move || loop {
-                match child.try_wait() {
-                    Ok(Some(_exit_status)) => {
-                        *result_clone.lock().unwrap() = Ok(());
-                        break;
-                    },
-                    Ok(None) => {
-                        match stdin_receiver.try_recv() {
-                            Ok(code) => {
-                                let stdin = stdin.clone();
-                                let code = format!("{}\n", code);
-                                stdin.lock().expect("Failed to lock stdin").write_all(code.as_bytes()).expect("Failed to write to stdin");
-                                stdin.lock().expect("Failed to lock stdin").flush().expect("Failed to flush stdin");
-                            },
-                            Err(_) => {
-                                thread::sleep(std::time::Duration::from_millis(100));
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        *result_clone.lock().unwrap() = Err(error);
-                        break;
-                    }
-                }
-            });
-        });
+    /// Whether already compressed files are added in a second step. 7-Zip adds them by updating
+    /// the archive, which is not possible for split archives.
+    fn has_stored_section(&self) -> bool {
+        self.content_plan.as_ref().is_some_and(|plan| !plan.stored_extensions.is_empty())
+            && matches!(self.archive_format, SevenZipArchiveFormat::SevenZip | SevenZipArchiveFormat::Zip)
+            && self.split_size == 0
+            && self.compression_level > 0
+    }
+}
+
+impl ArchiverBackend for SevenZipSettings {
+    fn name(&self) -> &'static str {
+        "7-Zip"
+    }
+
+    fn executable(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn extension(&self) -> &'static str {
+        self.archive_format.extension()
+    }
+
+    fn is_split(&self) -> bool {
+        self.split_size > 0
+    }
 
-        Arc::into_inner(result).unwrap().into_inner().unwrap()
+    fn compress_arguments(&self, download_path: &Path, archive_path: &Path) -> Vec<String> {
+        let input = match self.file_list(download_path) {
+            Some(file_list) => format!("@{}", file_list.to_str().unwrap()),
+            None => download_path.to_str().unwrap().to_string(),
+        };
+        self.add_arguments(input, archive_path)
     }
+
+    fn compress_steps(&self, download_path: &Path, archive_path: &Path) -> Vec<Vec<String>> {
+        // Already compressed files are added to the archive afterwards, without compression
+        if self.has_stored_section() {
+            let stored = SevenZipSettings {
+                compression_level: 0,
+                ..self.clone()
+            };
+            let stored_file_list = paths::stored_file_list(download_path);
+            return vec![
+                self.compress_arguments(download_path, archive_path),
+                stored.add_arguments(format!("@{}", stored_file_list.to_str().unwrap()), archive_path),
+            ];
+        }
+        if self.archive_format != SevenZipArchiveFormat::Xz {
+            return vec![self.compress_arguments(download_path, archive_path)];
+        }
+
+        let tar_path = archive_path.with_extension("");
+        let tar = SevenZipSettings {
+            archive_format: SevenZipArchiveFormat::Tar,
+            split_size: 0,
+            ..self.clone()
+        };
+        // The tar is compressed on its own, not the files in the list
+        let xz = SevenZipSettings {
+            reproducible: false,
+            excluded_files: BTreeSet::new(),
+            ..self.clone()
+        };
+        vec![
+            tar.compress_arguments(download_path, &tar_path),
+            xz.compress_arguments(&tar_path, archive_path),
+        ]
+    }
+
+    fn file_list(&self, download_path: &Path) -> Option<PathBuf> {
+        (self.reproducible || self.has_stored_section() || !self.excluded_files.is_empty())
+            .then(|| paths::archive_file_list(download_path))
+    }
+
+    fn stored_file_list(&self, download_path: &Path) -> Option<PathBuf> {
+        self.has_stored_section().then(|| paths::stored_file_list(download_path))
+    }
+
+    fn is_stored(&self, name: &str) -> bool {
+        self.has_stored_section() && self.content_plan.as_ref().is_some_and(|plan| plan.is_stored(name))
+    }
+
+    fn is_excluded(&self, name: &str) -> bool {
+        self.excluded_files.contains(name)
+    }
+
+    fn parse_progress(&self, line: &str) -> Option<(f32, Option<String>)> {
+        parse_7zip_progress(line)
+    }
+
+    fn intermediate_files(&self, archive_path: &Path) -> Vec<PathBuf> {
+        match self.archive_format {
+            SevenZipArchiveFormat::Xz => vec![archive_path.with_extension("")],
+            _ => Vec::new(),
+        }
+    }
+
+    fn test_arguments(&self, archive_path: &Path) -> Vec<String> {
+        let mut arguments = vec!["t".to_string()];
+        if !self.password.is_empty() {
+            arguments.push(format!("-p{}", self.password));
+        }
+        arguments.push(archive_path.to_str().unwrap().to_string());
+        arguments
+    }
+
+    fn list_arguments(&self, archive_path: &Path) -> Vec<String> {
+        let mut arguments = vec!["l".to_string(), "-slt".to_string()];
+        if !self.password.is_empty() {
+            arguments.push(format!("-p{}", self.password));
+        }
+        arguments.push(archive_path.to_str().unwrap().to_string());
+        arguments
+    }
+
+    fn extract_arguments(&self, archive_path: &Path, output_folder: &Path, files: &[String]) -> Vec<String> {
+        let mut arguments = vec!["x".to_string(), "-y".to_string(), format!("-o{}", output_folder.display())];
+        if !self.password.is_empty() {
+            arguments.push(format!("-p{}", self.password));
+        }
+        arguments.push(archive_path.to_str().unwrap().to_string());
+        arguments.extend(files.iter().cloned());
+        arguments
+    }
+
+    fn parse_listing(&self, listing: &str) -> Option<Vec<(String, Option<u64>)>> {
+        // xz holds the tar as a single file, so its contents cannot be listed
+        if self.archive_format == SevenZipArchiveFormat::Xz {
+            return None;
+        }
+        Some(parse_7zip_listing(listing))
+    }
+}
+
+/// Parses the technical listing (`l -slt`), where each file is a block of `Key = Value` lines. The
+/// block before the `----------` line describes the archive itself.
+fn parse_7zip_listing(listing: &str) -> Vec<(String, Option<u64>)> {
+    let mut files = Vec::new();
+    let mut file: Option<(String, Option<u64>, bool)> = None;
+    for line in listing.lines().skip_while(|line| !line.starts_with("----------")).skip(1) {
+        let Some((key, value)) = line.split_once(" = ") else {
+            continue;
+        };
+        match key {
+            "Path" => {
+                files.extend(file.take());
+                file = Some((value.to_string(), None, false));
+            }
+            "Size" => if let Some(file) = &mut file { file.1 = value.parse().ok() },
+            "Folder" => if let Some(file) = &mut file { file.2 |= value == "+" },
+            "Attributes" => if let Some(file) = &mut file { file.2 |= value.starts_with('D') },
+            _ => {}
+        }
+    }
+    files.extend(file);
+    files.into_iter()
+        .filter(|(_, _, is_folder)| !is_folder)
+        .map(|(name, size, _)| (name, size))
+        .collect()
 }
 
 #[derive(Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct WinRARSettings {
     pub path: Option<PathBuf>,
+    // Plain text passwords are only read from older settings files
+    #[serde(skip_serializing)]
     pub password: String,
+    pub password_nonce: [u8; 12],
+    pub encrypted_password: Vec<u8>,
+    /// Uses `-hp` instead of `-p`, which encrypts the file names as well as the data.
+    pub hide_file_names: bool,
     // Compression settings
-    pub archive_format: String,
+    pub archive_format: WinRARArchiveFormat,
     pub compression_level: u8,
     pub dictionary_size: u16,
     pub solid: bool,
     pub number_of_cpu_threads: u8,
     pub split_size: u16,
-    pub split_size_unit: String,
+    pub split_size_unit: SizeUnit,
+    /// Set by reproducible jobs, which add a sorted file list without timestamps or attributes.
+    #[serde(skip)]
+    pub reproducible: bool,
+    /// Set by jobs which store already compressed files.
+    #[serde(skip)]
+    pub content_plan: Option<ContentPlan>,
+    /// Set by jobs which ship modified files as deltas, named like the entries of `collect_entries`.
+    #[serde(skip)]
+    pub excluded_files: BTreeSet<String>,
 }
 
 impl Default for WinRARSettings {
     fn default() -> Self {
         Self {
-            path: {
-                let paths = CompressionSettings::get_detected_paths();
-                match paths.get(1) {
-                    Some(Some(path)) => Some(path.into()),
-                    _ => None,
-                }
-            },
+            path: preferred_path(Archiver::WinRAR),
             password: String::new(),
-            archive_format: "rar".to_string(),
+            password_nonce: [0; 12],
+            encrypted_password: Vec::new(),
+            hide_file_names: true,
+            archive_format: WinRARArchiveFormat::Rar,
             compression_level: 5,
             dictionary_size: 512,
             solid: true,
             number_of_cpu_threads: thread::available_parallelism().unwrap().get() as u8,
             split_size: 0,
-            split_size_unit: "g".to_string(),
+            split_size_unit: SizeUnit::Gigabytes,
+            reproducible: false,
+            content_plan: None,
+            excluded_files: BTreeSet::new(),
         }
     }
 }
 
 impl WinRARSettings {
-    pub fn compress(
-        &self,
-        download_path: PathBuf,
-        input_window_opened_sender: Sender<bool>,
-        stdin_receiver: Receiver<String>,
-        stdo_sender: Sender<String>,
-    ) -> std::io::Result<()> {
-        let _ = stdo_sender.send("\nCompressing files with WinRAR...\n".to_string());
-        let archiver_path = self.path.as_ref().unwrap().to_str().unwrap();
-        let mut command = Command::new(archiver_path);
-        let _ = std::fs::remove_dir_all(download_path.join(".DepotDownloader"));
-        let _ = std::fs::create_dir("./Completed");
-        command
-            .creation_flags(0x08000000)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .arg("a")
-            .arg(format!("-w{}", current_dir().unwrap().join("Completed").to_str().unwrap()))
-            .arg(format!("-m{}", self.compression_level))
-            .arg(format!("-md{}m", self.dictionary_size))
-            .arg(format!("-mt{}", self.number_of_cpu_threads));
-        if archiver_path.contains("WinRAR.exe") {
-            command.arg(format!("-af{}", self.archive_format));
+    /// WinRAR's levels go from 0 to 5, this maps them onto the 0 to 9 of the other archivers.
+    pub fn normalized_level(&self) -> u8 {
+        (self.compression_level * 2).min(9)
+    }
+
+    /// The memory needed to compress and to decompress the archive in MB.
+    pub fn memory_usage(&self) -> (u64, u64) {
+        (self.dictionary_size as u64 * 6, self.dictionary_size as u64)
+    }
+
+    /// RAR has no codec here, so the archive's size is estimated with LZMA2 and the same dictionary.
+    pub fn sample_codec(&self) -> SampleCodec {
+        match self.normalized_level() {
+            0 => SampleCodec::Store,
+            level => SampleCodec::Substitute { method: "RAR", preset: level, dictionary_size: Some(self.dictionary_size) },
+        }
+    }
+
+    /// Halves the dictionary size until compressing needs at most `budget` MB. WinRAR's threads
+    /// share the dictionary, so they are left alone. Returns whether the settings fit.
+    pub fn fit_to_memory(&mut self, budget: u64) -> bool {
+        while self.memory_usage().0 > budget {
+            if self.dictionary_size <= 1 {
+                return false;
+            }
+            self.dictionary_size /= 2;
+        }
+        true
+    }
+}
+
+impl ArchiverBackend for WinRARSettings {
+    fn name(&self) -> &'static str {
+        "WinRAR"
+    }
+
+    fn executable(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    fn extension(&self) -> &'static str {
+        self.archive_format.extension()
+    }
+
+    fn is_split(&self) -> bool {
+        self.split_size > 0
+    }
+
+    fn compress_arguments(&self, download_path: &Path, archive_path: &Path) -> Vec<String> {
+        let mut arguments = vec![
+            "a".to_string(),
+            format!("-w{}", archive_path.parent().unwrap().to_str().unwrap()),
+            format!("-m{}", self.compression_level),
+            format!("-md{}m", self.dictionary_size),
+            format!("-mt{}", self.number_of_cpu_threads),
+        ];
+        if self.path.as_ref().is_some_and(|path| path.to_string_lossy().contains("WinRAR.exe")) {
+            arguments.push(format!("-af{}", self.archive_format.extension()));
         }
         if self.solid {
-            command.arg(format!("-s{}", if self.split_size > 0 { "v-" } else { "" }));
+            arguments.push(format!("-s{}", if self.split_size > 0 { "v-" } else { "" }));
         }
         if self.split_size > 0 {
-            command.arg(format!("-v{}{}", self.split_size, self.split_size_unit));
+            arguments.push(format!("-v{}{}", self.split_size, self.split_size_unit.switch()));
         }
         if !self.password.is_empty() {
-            command.arg(format!("-p{}", self.password));
+            arguments.push(format!("-{}{}", if self.hide_file_names { "hp" } else { "p" }, self.password));
         }
-        let split_folder = if self.split_size > 0 {
-            download_path.file_name().unwrap().to_str().unwrap()
-        } else {
-            ""
-        };
-        let _ = create_dir(current_dir().unwrap().join("Completed").join(split_folder));
-        command
-            .arg("-ep1")
-            .arg(format!("{}", current_dir().unwrap().join("Completed").join(split_folder).join(download_path.file_name().unwrap()).to_str().unwrap()))
-            .arg(download_path);
-        let mut child = command.spawn()?;
-
-        let result = Arc::new(Mutex::new(Err(std::io::Error::new(std::io::ErrorKind::Other, "Unknown error"))));
-
-        thread::scope(|s| {
-            if let Some(mut stderr) = child.stderr.take() {
-                let stdo_sender = stdo_sender.clone();
-                let input_window_opened_sender = input_window_opened_sender.clone();
-                s.spawn(move || {
-                    let mut buffer = [0; 1024];
-                    loop {
-                        match stderr.read(&mut buffer) {
-                            Ok(n) if n > 0 => {
-                                let _ = stdo_sender.send(String::from_utf8_lossy(&buffer[..n]).parse().unwrap());
-                            }
-                            _ => break,
-                        }
-                    }
-                });
+        if self.reproducible {
+            arguments.extend(["-tsm-".to_string(), "-tsc-".to_string(), "-tsa-".to_string(), "-ai".to_string()]);
+        }
+        // Solid archives are sorted by extension already, so only storing needs a switch
+        if let Some(plan) = self.content_plan.as_ref().filter(|plan| !plan.stored_extensions.is_empty()) {
+            let extensions: Vec<&str> = plan.stored_extensions.iter().map(String::as_str).collect();
+            arguments.push(format!("-ms{}", extensions.join(";")));
+        }
+        // Exclude the folder and everything in it
+        arguments.push(format!("-x*{}{}", MAIN_SEPARATOR, DEPOT_DOWNLOADER_FOLDER));
+        arguments.push(format!("-x*{}{}{}*", MAIN_SEPARATOR, DEPOT_DOWNLOADER_FOLDER, MAIN_SEPARATOR));
+        match self.file_list(download_path) {
+            // The listed paths already start at the download folder
+            Some(file_list) => {
+                arguments.push("-scul".to_string());
+                arguments.push(archive_path.to_str().unwrap().to_string());
+                arguments.push(format!("@{}", file_list.to_str().unwrap()));
             }
-
-            if let Some(mut stdout) = child.stdout.take() {
-                let stdo_sender = stdo_sender.clone();
-                let input_window_opened_sender = input_window_opened_sender.clone();
-                s.spawn(move || {
-                    let mut buffer = [0; 1024];
-                    loop {
-                        match stdout.read(&mut buffer) {
-                            Ok(n) if n > 0 => {
-                                let _ = stdo_sender.send(String::from_utf8_lossy(&buffer[..n]).parse().unwrap());
-                            }
-                            _ => break,
-                        }
-                    }
-                });
+            None => {
+                arguments.push("-ep1".to_string());
+                arguments.push(archive_path.to_str().unwrap().to_string());
+                arguments.push(download_path.to_str().unwrap().to_string());
             }
+        }
+        arguments
+    }
 
-            let stdin = Arc::new(Mutex::new(child.stdin.take().expect("Failed to take stdin")));
-            let result_clone = Arc::clone(&result);
-            s.spawn(move || loop {
-                match child.try_wait() {
-                    Ok(Some(_exit_status)) => {
-                        *result_clone.lock().unwrap() = Ok(());
-                        break;
-                    },
-                    Ok(None) => {
-                        match stdin_receiver.try_recv() {
-                            Ok(code) => {
-                                let stdin = stdin.clone();
-                                let code = format!("{}\n", code);
-                                stdin.lock().expect("Failed to lock stdin").write_all(code.as_bytes()).expect("Failed to write to stdin");
-                                stdin.lock().expect("Failed to lock stdin").flush().expect("Failed to flush stdin");
-                            },
-                            Err(_) => {
-                                thread::sleep(std::time::Duration::from_millis(100));
-                            }
-                        }
-                    }
-                    Err(error) => {
-                        *result_clone.lock().unwrap() = Err(error);
-                        break;
-                    }
-                }
-            });
-        });
+    fn file_list(&self, download_path: &Path) -> Option<PathBuf> {
+        (self.reproducible || !self.excluded_files.is_empty()).then(|| paths::archive_file_list(download_path))
+    }
+
+    fn is_excluded(&self, name: &str) -> bool {
+        self.excluded_files.contains(name)
+    }
+
+    // Only the console version prints progress, WinRAR.exe shows its own window
+    fn parse_progress(&self, line: &str) -> Option<(f32, Option<String>)> {
+        parse_rar_progress(line)
+    }
+
+    fn test_arguments(&self, archive_path: &Path) -> Vec<String> {
+        vec![
+            "t".to_string(),
+            format!("-p{}", if self.password.is_empty() { "-" } else { self.password.as_str() }),
+            archive_path.to_str().unwrap().to_string(),
+        ]
+    }
 
-        Arc::into_inner(result).unwrap().into_inner().unwrap()
+    fn list_arguments(&self, archive_path: &Path) -> Vec<String> {
+        vec![
+            "lb".to_string(),
+            format!("-p{}", if self.password.is_empty() { "-" } else { self.password.as_str() }),
+            archive_path.to_str().unwrap().to_string(),
+        ]
     }
+
+    // The trailing separator makes WinRAR treat the output as a folder
+    fn extract_arguments(&self, archive_path: &Path, output_folder: &Path, files: &[String]) -> Vec<String> {
+        let mut arguments = vec![
+            "x".to_string(),
+            "-y".to_string(),
+            format!("-p{}", if self.password.is_empty() { "-" } else { self.password.as_str() }),
+            archive_path.to_str().unwrap().to_string(),
+        ];
+        arguments.extend(files.iter().cloned());
+        arguments.push(format!("{}{}", output_folder.display(), std::path::MAIN_SEPARATOR));
+        arguments
+    }
+
+    // The bare listing has no sizes, and folders cannot be told apart from files
+    fn parse_listing(&self, listing: &str) -> Option<Vec<(String, Option<u64>)>> {
+        Some(listing.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| (line.to_string(), None))
+            .collect())
+    }
+}
+
+/// zstd's window grows with the level, from 1 MB up to 128 MB for the ultra levels. Each thread
+/// needs about three windows to compress, while decompressing needs a single window.
+pub fn zstd_memory_usage(level: u8, threads: u64) -> (u64, u64) {
+    let bytes_ratio = 1024 * 1024;
+    let window = 1u64 << (20 + (level.saturating_sub(3) / 3).min(7));
+    let compress = (window * 3 + 4 * bytes_ratio) * threads;
+    (
+        compress.div_ceil(bytes_ratio),
+        (window + bytes_ratio).div_ceil(bytes_ratio),
+    )
 }
@@ -0,0 +1,175 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use sevenz_rust::lzma::{CountingWriter, LZMA2Options, LZMA2Writer};
+use crate::modules::native_archiver::{ArchiveEntry, collect_entries};
+
+/// Extensions of formats which are compressed already. Their samples only need to look random
+/// enough, other files must look almost completely random to be stored.
+const INCOMPRESSIBLE_EXTENSIONS: [&str; 22] = [
+    "7z", "bik", "bk2", "cab", "flac", "gz", "jpeg", "jpg", "m4a", "mkv", "mp3", "mp4", "ogg", "opus",
+    "pak", "png", "rar", "usm", "webm", "wem", "xz", "zip",
+];
+/// Bits per byte above which a sample is treated as incompressible.
+const KNOWN_THRESHOLD: f64 = 7.5;
+const UNKNOWN_THRESHOLD: f64 = 7.9;
+/// Each sampled file is read at its start, middle and end.
+const SAMPLE_SIZE: u64 = 64 * 1024;
+/// Only the largest files of each extension are sampled.
+const SAMPLED_FILES_PER_EXTENSION: usize = 16;
+/// Samples of stored files which are compressed to estimate what storing them saves.
+const MEASURED_BYTES: usize = 8 * 1024 * 1024;
+
+/// Which extensions are stored without compression, and what that is estimated to save.
+#[derive(Clone, Default)]
+pub struct ContentPlan {
+    pub stored_extensions: BTreeSet<String>,
+    pub stored_files: usize,
+    pub stored_bytes: u64,
+    pub total_files: usize,
+    pub total_bytes: u64,
+    pub saved_bytes: u64,
+    pub saved_time: Duration,
+}
+
+impl ContentPlan {
+    /// Samples the largest files of every extension. An extension is stored when most of its
+    /// sampled bytes are incompressible. `compression_level` is from 0 to 9 and is used to
+    /// estimate the time saved.
+    pub fn new(download_path: &Path, compression_level: u8) -> std::io::Result<Self> {
+        let entries = collect_entries(download_path, "")?;
+        let mut extensions: BTreeMap<String, Vec<&ArchiveEntry>> = BTreeMap::new();
+        for entry in entries.iter().filter(|entry| entry.path.is_file()) {
+            extensions.entry(extension(&entry.name).unwrap_or_default()).or_default().push(entry);
+        }
+
+        let mut plan = ContentPlan::default();
+        let mut measured = Vec::new();
+        for (extension, mut files) in extensions {
+            plan.total_files += files.len();
+            plan.total_bytes += files.iter().map(|file| file.size).sum::<u64>();
+            // Files without an extension cannot be told apart by the archivers
+            if extension.is_empty() {
+                continue;
+            }
+
+            files.sort_by_key(|file| std::cmp::Reverse(file.size));
+            let mut sampled_bytes = 0;
+            let mut incompressible_bytes = 0;
+            let mut samples = Vec::new();
+            for file in files.iter().take(SAMPLED_FILES_PER_EXTENSION) {
+                let sample = read_sample(&file.path, file.size)?;
+                sampled_bytes += file.size;
+                if is_incompressible(&sample, &extension) {
+                    incompressible_bytes += file.size;
+                    samples.push(sample);
+                }
+            }
+
+            if incompressible_bytes * 2 > sampled_bytes {
+                plan.stored_files += files.len();
+                plan.stored_bytes += files.iter().map(|file| file.size).sum::<u64>();
+                plan.stored_extensions.insert(extension);
+                for sample in samples {
+                    if measured.len() < MEASURED_BYTES {
+                        measured.extend(sample);
+                    }
+                }
+            }
+        }
+
+        if !measured.is_empty() && compression_level > 0 {
+            let (compressed, time) = compress_sample(&measured, compression_level)?;
+            let scale = plan.stored_bytes as f64 / measured.len() as f64;
+            plan.saved_bytes = (compressed.saturating_sub(measured.len()) as f64 * scale) as u64;
+            plan.saved_time = time.mul_f64(scale);
+        }
+        Ok(plan)
+    }
+
+    pub fn is_stored(&self, name: &str) -> bool {
+        extension(name).is_some_and(|extension| self.stored_extensions.contains(&extension))
+    }
+}
+
+impl Display for ContentPlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.stored_extensions.is_empty() {
+            return writeln!(f, "No already compressed files were found in {} files, they are grouped by type.", self.total_files);
+        }
+        let extensions: Vec<&str> = self.stored_extensions.iter().map(String::as_str).collect();
+        writeln!(f, "{} of {} files ({:.2} of {:.2} GB) are already compressed and are stored as they are: {}.",
+                 self.stored_files, self.total_files, self.stored_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+                 self.total_bytes as f64 / (1024.0 * 1024.0 * 1024.0), extensions.join(", "))?;
+        writeln!(f, "This saves about {:.1} MB and {} of compression on one thread. The other files are grouped by type.",
+                 self.saved_bytes as f64 / (1024.0 * 1024.0), format_minutes(self.saved_time))
+    }
+}
+
+/// The lowercase extension of a file name inside the archive, if it has one.
+pub fn extension(name: &str) -> Option<String> {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    file_name.rsplit_once('.')
+        .filter(|(stem, extension)| !stem.is_empty() && !extension.is_empty())
+        .map(|(_, extension)| extension.to_lowercase())
+}
+
+/// Reads up to three parts of the file, from its start, middle and end.
+pub fn read_sample(path: &Path, size: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut sample = Vec::new();
+    if size <= SAMPLE_SIZE * 3 {
+        file.read_to_end(&mut sample)?;
+        return Ok(sample);
+    }
+    for offset in [0, size / 2 - SAMPLE_SIZE / 2, size - SAMPLE_SIZE] {
+        file.seek(SeekFrom::Start(offset))?;
+        Read::by_ref(&mut file).take(SAMPLE_SIZE).read_to_end(&mut sample)?;
+    }
+    Ok(sample)
+}
+
+/// Compresses the sample with LZMA2 at the level from 0 to 9 and returns the compressed size and
+/// the time it took. 7-Zip and WinRAR compress at a similar speed and ratio.
+pub fn compress_sample(sample: &[u8], compression_level: u8) -> std::io::Result<(usize, Duration)> {
+    let start = Instant::now();
+    let output = CountingWriter::new(std::io::sink());
+    let compressed = output.counting();
+    let mut writer = LZMA2Writer::new(output, &LZMA2Options::with_preset(compression_level.min(9) as u32));
+    writer.write_all(sample)?;
+    writer.finish()?;
+    Ok((compressed.get(), start.elapsed()))
+}
+
+fn is_incompressible(sample: &[u8], extension: &str) -> bool {
+    let threshold = match INCOMPRESSIBLE_EXTENSIONS.contains(&extension) {
+        true => KNOWN_THRESHOLD,
+        false => UNKNOWN_THRESHOLD,
+    };
+    !sample.is_empty() && entropy(sample) >= threshold
+}
+
+/// Shannon entropy of the bytes in bits per byte, from 0 (constant) to 8 (random).
+fn entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0u64; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
+    }
+    counts.iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let probability = count as f64 / bytes.len() as f64;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+fn format_minutes(duration: Duration) -> String {
+    match duration.as_secs() {
+        seconds if seconds < 60 => format!("{} s", seconds),
+        seconds => format!("{} min", seconds / 60),
+    }
+}
@@ -5,13 +5,26 @@ use std::fs::create_dir;
 use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::thread;
+use std::time::Duration;
+use chrono::Local;
 use crossbeam_channel::{Receiver, Sender};
-use eframe::egui::{Button, ComboBox, Context, ScrollArea, TextEdit, Ui, Window};
+use eframe::egui::{Button, Checkbox, Color32, ComboBox, Context, ProgressBar, ScrollArea, TextEdit, Ui, Window};
 use egui_file::FileDialog;
 use crate::modules::app::TabBar;
 use crate::modules::changes::Changes;
-use crate::modules::compression::{Archiver, CompressionSettings};
-use crate::modules::depot_downloader::{DepotDownloaderSettings, download_changes, download_manifest};
+use crate::modules::checksums::{Checksums, is_checksum_file, verify_checksum_file};
+use crate::modules::compression::{Archiver, CompressionJob, CompressionSettings};
+use crate::modules::compression_progress::CompressionProgress;
+use crate::modules::credentials::generate_password;
+use crate::modules::depot_downloader::{DepotDownloaderSettings, download_changes};
+use crate::modules::job_summary::{JobSummary, is_job_file};
+use crate::modules::package_metadata::{INSTALLER_FOLDER, PackageMetadata};
+use crate::modules::size_estimation::SizeEstimator;
+
+const GENERATED_PASSWORD_LENGTH: usize = 24;
+
+/// A checksum file with the result for every file in it.
+type ChecksumResults = (PathBuf, std::io::Result<Vec<(String, Result<(), String>)>>);
 
 
 pub struct CreateUpdateChannels {
@@ -23,8 +36,14 @@ pub struct CreateUpdateChannels {
     output_receiver: Receiver<String>,
     depot_downloader_path_sender: Sender<std::io::Result<PathBuf>>,
     depot_downloader_path_receiver: Receiver<std::io::Result<PathBuf>>,
-    compression_status_sender: Sender<std::io::Result<()>>,
-    compression_status_receiver: Receiver<std::io::Result<()>>,
+    compression_status_sender: Sender<std::io::Result<Option<JobSummary>>>,
+    compression_status_receiver: Receiver<std::io::Result<Option<JobSummary>>>,
+    progress_sender: Sender<CompressionProgress>,
+    progress_receiver: Receiver<CompressionProgress>,
+    checksum_result_sender: Sender<ChecksumResults>,
+    checksum_result_receiver: Receiver<ChecksumResults>,
+    volume_plan_sender: Sender<String>,
+    volume_plan_receiver: Receiver<String>,
 }
 
 impl Default for CreateUpdateChannels {
@@ -34,6 +53,9 @@ impl Default for CreateUpdateChannels {
         let (output_sender, output_receiver) = crossbeam_channel::unbounded();
         let (depot_downloader_path_sender, depot_downloader_path_receiver) = crossbeam_channel::bounded(1);
         let (compression_status_sender, compression_status_receiver) = crossbeam_channel::bounded(1);
+        let (progress_sender, progress_receiver) = crossbeam_channel::unbounded();
+        let (checksum_result_sender, checksum_result_receiver) = crossbeam_channel::bounded(1);
+        let (volume_plan_sender, volume_plan_receiver) = crossbeam_channel::unbounded();
         Self {
             input_window_opened_sender,
             input_window_opened_receiver,
@@ -45,6 +67,12 @@ impl Default for CreateUpdateChannels {
             depot_downloader_path_receiver,
             compression_status_sender,
             compression_status_receiver,
+            progress_sender,
+            progress_receiver,
+            checksum_result_sender,
+            checksum_result_receiver,
+            volume_plan_sender,
+            volume_plan_receiver,
         }
     }
 }
@@ -73,8 +101,31 @@ pub struct CreateUpdateUI {
     changes: Changes,
     target_os: TargetOS,
     compress_files: bool,
+    /// The name of the compression preset used instead of the current settings.
+    job_preset: Option<String>,
+    /// The name of the upload destination which decides the split size.
+    job_destination: Option<String>,
+    /// The expected number of volumes for the last compression job.
+    volume_plan: Option<String>,
+    /// Estimates the archive size before each job, or on request for the last download.
+    size_estimator: SizeEstimator,
+    /// Store modified files as deltas against a local copy of the initial build.
+    binary_deltas: bool,
+    delta_base_path: Option<PathBuf>,
+    delta_base_dialog: Option<FileDialog>,
+    generate_password: bool,
+    /// The password generated for the last compression job.
+    job_password: Option<String>,
     stdout: String,
     child_process_running: bool,
+    profile_selected_for_app: String,
+    compression_progress: Option<CompressionProgress>,
+    /// The checksums of the last compression job's archive.
+    job_checksums: Option<Checksums>,
+    checksum_file_dialog: Option<FileDialog>,
+    /// The last checksum file which was verified, with the result for every file in it.
+    checksum_results: Option<ChecksumResults>,
+    verifying_checksums: bool,
 }
 
 impl Default for CreateUpdateUI {
@@ -86,8 +137,23 @@ impl Default for CreateUpdateUI {
             changes: Changes::default(),
             target_os: TargetOS::Windows,
             compress_files: true,
+            job_preset: None,
+            job_destination: None,
+            volume_plan: None,
+            size_estimator: SizeEstimator::default(),
+            binary_deltas: false,
+            delta_base_path: None,
+            delta_base_dialog: None,
+            generate_password: false,
+            job_password: None,
             stdout: String::new(),
             child_process_running: false,
+            profile_selected_for_app: String::new(),
+            compression_progress: None,
+            job_checksums: None,
+            checksum_file_dialog: None,
+            checksum_results: None,
+            verifying_checksums: false,
         }
     }
 }
@@ -103,10 +169,12 @@ impl CreateUpdateUI {
         if !create_update_ui.changes.depot.is_empty() {
             create_update_ui.display_download_stuff(ui, depot_downloader_settings, compression_settings, tab_bar);
             create_update_ui.display_depot_downloader_input_window(ui, depot_downloader_settings);
+            create_update_ui.display_compression_progress(ui);
             ui.separator();
             create_update_ui.display_stdout(ui);
             create_update_ui.multiup_direct_button(ui, compression_settings);
         }
+        create_update_ui.display_checksums(ui, compression_settings);
     }
 
     fn display_file_dialog(&mut self, ctx: &Context, ui: &mut Ui) {
@@ -198,10 +266,76 @@ impl CreateUpdateUI {
         ui.checkbox(&mut depot_downloader_settings.download_entire_depot, "Ignore changes and download entire depot");
         ui.checkbox(&mut depot_downloader_settings.download_manifest, "Download manifest");
         ui.checkbox(&mut self.compress_files, "Compress files after downloading");
+        if self.compress_files && compression_settings.archiver != Archiver::Native && !compression_settings.presets.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label("Compression preset: ");
+                ComboBox::from_id_source("Job Compression Preset")
+                    .selected_text(self.job_preset.clone().unwrap_or("Current settings".to_string()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.job_preset, None, "Current settings");
+                        for preset in &compression_settings.presets {
+                            ui.selectable_value(&mut self.job_preset, Some(preset.name.clone()), preset.name.as_str());
+                        }
+                    });
+            });
+        }
+        if self.compress_files && !compression_settings.upload_destinations.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label("Upload destination: ");
+                ComboBox::from_id_source("Job Upload Destination")
+                    .selected_text(self.job_destination.clone().unwrap_or("Split size from settings".to_string()))
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut self.job_destination, None, "Split size from settings");
+                        for destination in &compression_settings.upload_destinations {
+                            ui.selectable_value(&mut self.job_destination, Some(destination.name.clone()), destination.name.as_str());
+                        }
+                    });
+            });
+        }
+        while let Ok(volume_plan) = self.channels.volume_plan_receiver.try_recv() {
+            self.volume_plan = Some(volume_plan);
+        }
+        if let Some(volume_plan) = &self.volume_plan {
+            ui.label(volume_plan);
+        }
+        if self.compress_files {
+            let codec = self.job(compression_settings).sample_codec();
+            self.size_estimator.display(ui, &compression_settings.download_path, codec);
+        }
+        if self.compress_files && !depot_downloader_settings.download_entire_depot {
+            self.display_delta_settings(ui);
+        }
+        ui.add_enabled(self.compress_files && compression_settings.archiver != Archiver::Native,
+                       Checkbox::new(&mut self.generate_password, "Generate a new archive password for this update"));
+        if let Some(password) = &self.job_password {
+            ui.horizontal(|ui| {
+                ui.label(format!("Archive password: {}", password));
+                if ui.button("Copy").clicked() {
+                    ui.output_mut(|output| output.copied_text = password.clone());
+                }
+            });
+        }
+
+        if depot_downloader_settings.auto_select_profile && !self.child_process_running && self.profile_selected_for_app != self.changes.app {
+            depot_downloader_settings.select_profile_for_app(&self.changes.app);
+            self.profile_selected_for_app = self.changes.app.clone();
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Account profile: ");
+            ui.add_enabled_ui(!self.child_process_running, |ui| {
+                ComboBox::from_id_source("Account Profile").selected_text(depot_downloader_settings.profile().name.clone())
+                    .show_ui(ui, |ui| {
+                        for (index, profile) in depot_downloader_settings.profiles.iter().enumerate() {
+                            ui.selectable_value(&mut depot_downloader_settings.selected_profile, index, profile.name.as_str());
+                        }
+                    });
+            });
+        });
 
-        if !depot_downloader_settings.username.is_empty() && (!depot_downloader_settings.password.is_empty() || depot_downloader_settings.remember_credentials) {
+        if depot_downloader_settings.profile().has_credentials() {
             ui.horizontal(|ui| {
-                if ui.add_enabled(!self.child_process_running, Button::new(format!("Download changes as {}", depot_downloader_settings.username))).clicked() {
+                if ui.add_enabled(!self.child_process_running, Button::new(format!("Download changes as {}", depot_downloader_settings.profile().username))).clicked() {
                     let changes = self.changes.clone();
                     let depot_downloader_settings = depot_downloader_settings.clone();
                     let sender = self.channels.input_window_opened_sender.clone();
@@ -209,6 +343,8 @@ impl CreateUpdateUI {
                     let path_sender = self.channels.depot_downloader_path_sender.clone();
                     let stdio_sender = self.channels.output_sender.clone();
                     self.child_process_running = true;
+                    self.compression_progress = None;
+                    self.volume_plan = None;
                     thread::spawn(move || {
                         let status = download_changes(&changes, &depot_downloader_settings, sender, receiver, stdio_sender);
                         let _ = path_sender.send(status);
@@ -230,7 +366,7 @@ impl CreateUpdateUI {
                     compression_settings.download_path = download_path.clone();
                     // Copy JSON changes file to download path
                     if !depot_downloader_settings.download_entire_depot {
-                        let installer_path = download_path.join(".RedAlt-SteamUp-Installer");
+                        let installer_path = download_path.join(INSTALLER_FOLDER);
                         let _ = create_dir(&installer_path);
                         if let Some(file) = &self.changes_json_file {
                             let changes_path = installer_path.join(file.file_name().unwrap());
@@ -259,25 +395,76 @@ impl CreateUpdateUI {
                         }
                     }
 
+                    // Packages of entire depots have no installer folder
+                    let metadata = match depot_downloader_settings.download_entire_depot {
+                        true => None,
+                        false => Some(PackageMetadata::new(&self.changes, &self.target_os.to_string())),
+                    };
                     if self.compress_files {
-                        let archiver = compression_settings.archiver.clone();
-                        let download_path = compression_settings.download_path.clone();
-                        let seven_zip_settings = compression_settings.seven_zip_settings.clone();
-                        let win_rar_settings = compression_settings.win_rar_settings.clone();
-                        let input_window_opened_sender = self.channels.input_window_opened_sender.clone();
+                        let mut job = self.job(compression_settings);
+                        job.metadata = metadata;
+                        job.torrent_comment = format!("{} (App {}, Depot {}) from Build {} to Build {}",
+                                                      self.changes.name, self.changes.app, self.changes.depot,
+                                                      self.changes.initial_build, self.changes.final_build);
+                        if self.binary_deltas {
+                            job.delta_base_path = self.delta_base_path.clone();
+                            job.modified_files = self.changes.modified.clone();
+                        }
+                        let destination = self.job_destination.as_ref()
+                            .and_then(|name| compression_settings.upload_destinations.iter().find(|destination| &destination.name == name))
+                            .cloned();
+                        // A generated password is only used for this job, the built-in archiver cannot use one
+                        self.job_password = match (self.generate_password, &job.archiver) {
+                            (true, Archiver::SevenZip | Archiver::WinRAR) => Some(generate_password(GENERATED_PASSWORD_LENGTH)),
+                            _ => None,
+                        };
+                        if let Some(password) = &self.job_password {
+                            job.set_password(password);
+                        }
                         let input_receiver = self.channels.input_receiver.clone();
                         let output_sender = self.channels.output_sender.clone();
                         let status_sender = self.channels.compression_status_sender.clone();
+                        let progress_sender = self.channels.progress_sender.clone();
+                        let estimate_sender = self.size_estimator.sender();
+                        let volume_plan_sender = self.channels.volume_plan_sender.clone();
+                        self.compression_progress = Some(CompressionProgress::default());
                         thread::spawn(move || {
-                            let status = match archiver {
-                                Archiver::SevenZip => seven_zip_settings.compress(download_path.clone(), input_window_opened_sender, input_receiver, output_sender),
-                                Archiver::WinRAR => win_rar_settings.compress(download_path.clone(), input_window_opened_sender, input_receiver, output_sender),
-                            };
-
-                            let _ = status_sender.send(status);
+                            // Deltas replace files, so the files are hashed and the deltas created before the size is estimated
+                            if let Err(error) = job.write_metadata(&output_sender).and_then(|_| job.create_deltas(&output_sender)) {
+                                let _ = status_sender.send(Err(error));
+                                return;
+                            }
+                            let estimate = job.estimate_size(&output_sender);
+                            // The volumes are planned from the estimate, or from the files' size without one
+                            if let Some(destination) = &destination {
+                                let archive_bytes = match &estimate {
+                                    Ok(estimate) => estimate.archive_bytes,
+                                    Err(_) => job.input_size(),
+                                };
+                                job.apply_destination(destination, archive_bytes);
+                                let volume_plan = format!("Expecting {} volume(s) of at most {} {} for {} (about {:.2} GB to upload)",
+                                                          destination.expected_volumes(archive_bytes), destination.max_file_size,
+                                                          destination.max_file_size_unit, destination.name,
+                                                          archive_bytes as f64 / (1024.0 * 1024.0 * 1024.0));
+                                let _ = output_sender.send(format!("{}\n", volume_plan));
+                                let _ = volume_plan_sender.send(volume_plan);
+                            }
+                            let estimate = estimate.map_err(|error| error.to_string());
+                            let _ = estimate_sender.send((job.download_path.clone(), job.sample_codec(), estimate));
+                            let status = job.run(input_receiver, output_sender, progress_sender);
+                            let _ = status_sender.send(status.map(Some));
                         });
                     } else {
-                        let _ = self.channels.compression_status_sender.send(Ok(()));
+                        let status_sender = self.channels.compression_status_sender.clone();
+                        match metadata {
+                            Some(mut metadata) => {
+                                thread::spawn(move || {
+                                    let status = metadata.hash_files(&download_path).and_then(|_| metadata.write(&download_path));
+                                    let _ = status_sender.send(status.map(|_| None));
+                                });
+                            }
+                            None => { let _ = status_sender.send(Ok(None)); }
+                        }
                     }
                 }
                 Err(error) => {
@@ -290,10 +477,11 @@ impl CreateUpdateUI {
 
         if let Ok(status) = self.channels.compression_status_receiver.try_recv() {
             match status {
-                Ok(_) => {
+                Ok(summary) => {
                     if self.compress_files {
                         let _ = self.channels.output_sender.send("\nFinished compressing files.\n".to_string());
                     }
+                    self.job_checksums = summary.and_then(|summary| summary.checksums);
                 }
                 Err(error) => {
                     let _ = self.channels.output_sender.send(format!("\nFailed to compress files: {}.\n", error));
@@ -327,6 +515,67 @@ impl CreateUpdateUI {
         depot_downloader_settings.depot_downloader_input_window_opened = open;
     }
 
+    fn display_delta_settings(&mut self, ui: &mut Ui) {
+        ui.checkbox(&mut self.binary_deltas, "Store modified files as binary deltas")
+            .on_hover_text("Modified files which exist in the initial build are shipped as deltas against it");
+        if !self.binary_deltas {
+            return;
+        }
+        ui.horizontal(|ui| {
+            match &self.delta_base_path {
+                None => ui.label(format!("Choose the folder with Build {}:", self.changes.initial_build)),
+                Some(path) => ui.label(format!("Initial build: {}", path.display())),
+            };
+            if ui.button("Choose folder").clicked() {
+                let mut dialog = FileDialog::select_folder(self.delta_base_path.clone());
+                dialog.open();
+                self.delta_base_dialog = Some(dialog);
+            }
+        });
+        if self.delta_base_path.is_none() {
+            ui.colored_label(Color32::RED, "Without the initial build, every modified file is shipped in full.");
+        }
+
+        if let Some(dialog) = &mut self.delta_base_dialog {
+            if dialog.show(ui.ctx()).selected() {
+                if let Some(folder) = dialog.path() {
+                    self.delta_base_path = Some(folder.to_path_buf());
+                }
+            }
+        }
+    }
+
+    /// A compression job with the settings and the preset chosen for this update.
+    fn job(&self, compression_settings: &CompressionSettings) -> CompressionJob {
+        let mut job = CompressionJob::new(compression_settings);
+        let preset = self.job_preset.as_ref()
+            .and_then(|name| compression_settings.presets.iter().find(|preset| &preset.name == name));
+        if let Some(preset) = preset {
+            job.apply_preset(preset);
+        }
+        job
+    }
+
+    fn display_compression_progress(&mut self, ui: &mut Ui) {
+        while let Ok(progress) = self.channels.progress_receiver.try_recv() {
+            self.compression_progress = Some(progress);
+            ui.ctx().request_repaint();
+        }
+
+        let Some(progress) = &self.compression_progress else {
+            return;
+        };
+        let mut text = format!("{:.0}% - {:.1} MB/s", progress.fraction * 100.0, progress.bytes_per_second / (1024.0 * 1024.0));
+        if let (true, Some(remaining)) = (self.child_process_running, progress.remaining) {
+            let finish = Local::now() + chrono::Duration::from_std(remaining).unwrap_or(chrono::Duration::zero());
+            text += &format!(" - finishes around {} ({} left)", finish.format("%H:%M:%S"), format_duration(remaining));
+        }
+        ui.add(ProgressBar::new(progress.fraction).text(text));
+        if self.child_process_running && !progress.current_file.is_empty() {
+            ui.label(format!("Compressing {}", progress.current_file));
+        }
+    }
+
     fn display_stdout(&mut self, ui: &mut Ui) {
         let mut output = self.stdout.clone();
         ScrollArea::vertical().id_source("Standard Output").max_height(ui.available_height() * 2.0 / 3.0).show(ui, |ui| {
@@ -339,31 +588,109 @@ impl CreateUpdateUI {
         });
     }
 
+    /// Shows the last archive's checksums and checks the files listed in a `.sha256` or `.sfv` file.
+    fn display_checksums(&mut self, ui: &mut Ui, compression_settings: &CompressionSettings) {
+        if let Some(checksums) = &self.job_checksums {
+            ui.collapsing("Checksums", |ui| {
+                ui.label(checksums.to_string());
+                ui.horizontal(|ui| {
+                    if ui.button("Copy SHA-256").clicked() {
+                        ui.output_mut(|output| output.copied_text = checksums.sha256_file());
+                    }
+                    if ui.button("Copy SFV").clicked() {
+                        ui.output_mut(|output| output.copied_text = checksums.sfv_file());
+                    }
+                });
+            });
+        }
+
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!self.verifying_checksums, Button::new("Verify checksums")).clicked() {
+                let filter = Box::new(|path: &Path| -> bool { is_checksum_file(path) });
+                let mut dialog = FileDialog::open_file(Some(compression_settings.output_root.clone())).show_files_filter(filter);
+                dialog.open();
+                self.checksum_file_dialog = Some(dialog);
+            }
+            if self.verifying_checksums {
+                ui.spinner();
+                ui.ctx().request_repaint();
+            }
+        });
+        if let Some(dialog) = &mut self.checksum_file_dialog {
+            if dialog.show(ui.ctx()).selected() {
+                if let Some(file) = dialog.path().map(Path::to_path_buf) {
+                    let sender = self.channels.checksum_result_sender.clone();
+                    self.verifying_checksums = true;
+                    thread::spawn(move || {
+                        let results = verify_checksum_file(&file);
+                        let _ = sender.send((file, results));
+                    });
+                }
+            }
+        }
+
+        if let Ok(results) = self.channels.checksum_result_receiver.try_recv() {
+            self.verifying_checksums = false;
+            self.checksum_results = Some(results);
+        }
+        match &self.checksum_results {
+            Some((file, Ok(results))) => {
+                let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+                match failed {
+                    0 => ui.label(format!("All {} files in {} are intact.", results.len(), file.display())),
+                    _ => ui.colored_label(Color32::RED, format!("{} of {} files in {} failed:", failed, results.len(), file.display())),
+                };
+                for (name, result) in results {
+                    if let Err(error) = result {
+                        ui.label(format!("  {}: {}", name, error));
+                    }
+                }
+            }
+            Some((file, Err(error))) => {
+                ui.colored_label(Color32::RED, format!("Failed to read {}: {}", file.display(), error));
+            }
+            None => {}
+        }
+    }
+
     fn multiup_direct_button(&mut self, ui: &mut Ui, compression_settings: &mut CompressionSettings) {
         // Check if there is an executable in the current directory
         if let Some(path) = &compression_settings.multiup_direct_path {
             if ui.button("Upload with MultiUp Direct").clicked() {
                 let download_path = compression_settings.download_path.clone();
+                let output_root = compression_settings.output_root.clone();
                 let path = path.clone();
                 thread::spawn(move || {
                     let mut command = Command::new(path);
                     command.args(["--upload", "disk_upload"]);
-                    for entry in current_dir().unwrap().join("Completed").read_dir().unwrap().flatten() {
+                    for entry in output_root.read_dir().unwrap().flatten() {
                         if entry.file_name().to_str().unwrap().contains(download_path.file_name().unwrap().to_str().unwrap()) {
                             if entry.file_type().unwrap().is_file() {
-                                command.arg(entry.path());
+                                if !is_job_file(&entry.path()) {
+                                    command.arg(entry.path());
+                                }
                             } else if entry.file_type().unwrap().is_dir() {
                                 for file in entry.path().read_dir().unwrap().flatten() {
-                                    command.arg(file.path());
+                                    if !is_job_file(&file.path()) {
+                                        command.arg(file.path());
+                                    }
                                 }
                             }
                         }
                     }
-                    println!("{:?}", command);
                     let mut child = command.spawn().unwrap();
                     let _ = child.wait();
                 });
             }
         }
     }
-}
\ No newline at end of file
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
@@ -0,0 +1,114 @@
+use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit};
+use aes_gcm::aead::{Aead, Nonce, OsRng};
+use aes_gcm::aead::rand_core::RngCore;
+use keyring::Entry;
+use serde::{Deserialize, Serialize};
+use crate::modules::paths;
+
+const KEYRING_SERVICE: &str = "RedAlt-SteamUp-Creator";
+const KEYRING_USER: &str = "settings-encryption-key";
+
+/// The key file written by older versions, which only obfuscated the key with a XOR cipher.
+#[derive(Clone, Deserialize, Serialize)]
+struct LegacyEncryptionKey {
+    encrypted_encryption_key: [u8; 32],
+}
+
+/// Encrypts secrets stored in `settings.json` with a key kept in the OS keyring
+/// (Windows Credential Manager, macOS Keychain or the Secret Service on Linux).
+pub struct CredentialStore {
+    cipher: Aes256Gcm,
+}
+
+impl CredentialStore {
+    /// Loads the key from the OS keyring, creating one on first use. A `key.json` left by an
+    /// older version is moved into the keyring so that existing settings can still be decrypted.
+    pub fn open() -> Result<Self, String> {
+        let entry = Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|error| error.to_string())?;
+
+        let key = match read_legacy_key() {
+            Some(key) => {
+                entry.set_password(&to_hex(&key)).map_err(|error| error.to_string())?;
+                let _ = std::fs::remove_file(paths::legacy_key_file());
+                key
+            }
+            None => match entry.get_password() {
+                Ok(key) => from_hex(&key).ok_or("The stored encryption key is corrupted")?,
+                Err(keyring::Error::NoEntry) => {
+                    let key: [u8; 32] = Aes256Gcm::generate_key(OsRng).as_slice().try_into().unwrap();
+                    entry.set_password(&to_hex(&key)).map_err(|error| error.to_string())?;
+                    key
+                }
+                Err(error) => return Err(error.to_string()),
+            },
+        };
+
+        Ok(Self { cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)) })
+    }
+
+    /// Returns the nonce and the ciphertext of the secret.
+    pub fn encrypt(&self, secret: &str) -> Result<([u8; 12], Vec<u8>), String> {
+        let nonce = Aes256Gcm::generate_nonce(OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, secret.as_bytes()).map_err(|error| error.to_string())?;
+        Ok((nonce.as_slice().try_into().unwrap(), ciphertext))
+    }
+
+    pub fn decrypt(&self, nonce: &[u8; 12], ciphertext: &[u8]) -> Option<String> {
+        if ciphertext.is_empty() {
+            return None;
+        }
+
+        let nonce = Nonce::<Aes256Gcm>::from_slice(nonce);
+        self.cipher.decrypt(nonce, ciphertext).ok()
+            .and_then(|secret| String::from_utf8(secret).ok())
+    }
+}
+
+/// Generates a random archive password. Characters which are easily confused, or which need
+/// quoting on a command line, are left out.
+pub fn generate_password(length: usize) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnpqrstuvwxyz23456789";
+    let mut password = String::with_capacity(length);
+    while password.len() < length {
+        let mut byte = [0; 1];
+        OsRng.fill_bytes(&mut byte);
+        // Reject the bytes past the last multiple of the alphabet's length to avoid bias
+        if (byte[0] as usize) < 256 - 256 % ALPHABET.len() {
+            password.push(ALPHABET[byte[0] as usize % ALPHABET.len()] as char);
+        }
+    }
+    password
+}
+
+fn read_legacy_key() -> Option<[u8; 32]> {
+    let file = std::fs::File::open(paths::legacy_key_file()).ok()?;
+    let key: LegacyEncryptionKey = serde_json::from_reader(file).ok()?;
+    if key.encrypted_encryption_key == [0; 32] {
+        return None;
+    }
+    Some(xor_cipher(&key.encrypted_encryption_key))
+}
+
+fn xor_cipher(key: &[u8; 32]) -> [u8; 32] {
+    let secret_key = "red-alt-steam-up-creator-xor-key".as_bytes();
+    let mut output = [0; 32];
+    for i in 0..32 {
+        output[i] = key[i] ^ secret_key[i];
+    }
+    output
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<[u8; 32]> {
+    let mut output = [0; 32];
+    if hex.len() != output.len() * 2 {
+        return None;
+    }
+    for (i, byte) in output.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(output)
+}
@@ -1,26 +1,22 @@
-use std::env::current_dir;
+use crate::modules::archiver_backend::hide_console_window;
 use crate::modules::changes::Changes;
+use crate::modules::paths;
 use crossbeam_channel::{Receiver, Sender};
 use serde::{Deserialize, Serialize};
 use std::io::{Read, Write};
-use std::os::windows::process::CommandExt;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use aes_gcm::aead::generic_array::GenericArray;
 
+/// Depot Downloader keeps the state it needs to resume and verify downloads in this folder.
+pub const DEPOT_DOWNLOADER_FOLDER: &str = ".DepotDownloader";
 
-#[derive(Clone, Deserialize, Serialize)]
-pub struct EncryptionKey {
-    pub encrypted_encryption_key: [u8; 32],
-}
 
 #[derive(Clone, Deserialize, Serialize)]
 #[serde(default)]
-pub struct DepotDownloaderSettings {
-    #[serde(skip)]
-    pub encryption_key: EncryptionKey,
+pub struct AccountProfile {
+    pub name: String,
     pub username_nonce: [u8; 12],
     pub encrypted_username: Vec<u8>,
     // Used by Depot Downloader
@@ -28,10 +24,46 @@ pub struct DepotDownloaderSettings {
     pub username: String,
     #[serde(skip)]
     pub password: String,
+    // Used by app
+    pub remember_credentials: bool,
+    /// Comma-separated app IDs which automatically select this profile.
+    pub app_ids: String,
+}
+
+impl Default for AccountProfile {
+    fn default() -> Self {
+        Self {
+            name: "Default".to_string(),
+            username_nonce: [0; 12],
+            encrypted_username: Vec::new(),
+            username: String::new(),
+            password: String::new(),
+            remember_credentials: true,
+            app_ids: String::new(),
+        }
+    }
+}
+
+impl AccountProfile {
+    pub fn has_credentials(&self) -> bool {
+        !self.username.is_empty() && (!self.password.is_empty() || self.remember_credentials)
+    }
+
+    pub fn matches_app(&self, app: &str) -> bool {
+        !app.is_empty() && self.app_ids.split(',').any(|app_id| app_id.trim() == app)
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct DepotDownloaderSettings {
+    pub profiles: Vec<AccountProfile>,
+    pub selected_profile: usize,
+    pub auto_select_profile: bool,
     pub max_servers: u8,
     pub max_downloads: u8,
+    pub download_root: PathBuf,
     // Used by app
-    pub remember_credentials: bool,
     #[serde(skip)]
     pub download_manifest: bool,
     #[serde(skip)]
@@ -45,14 +77,12 @@ pub struct DepotDownloaderSettings {
 impl Default for DepotDownloaderSettings {
     fn default() -> Self {
         Self {
-            encryption_key: EncryptionKey { encrypted_encryption_key: [0; 32] },
-            username_nonce: [0; 12],
-            encrypted_username: Vec::new(),
-            username: String::new(),
-            password: String::new(),
+            profiles: vec![AccountProfile::default()],
+            selected_profile: 0,
+            auto_select_profile: true,
             max_servers: 20,
             max_downloads: 8,
-            remember_credentials: true,
+            download_root: paths::default_download_root(),
             download_manifest: true,
             download_entire_depot: false,
             depot_downloader_input_window_opened: false,
@@ -61,12 +91,44 @@ impl Default for DepotDownloaderSettings {
     }
 }
 
-fn write_changes_to_file(changes: &Changes) -> std::io::Result<()> {
-    let download_files = changes.added.join("\n") + &changes.modified.join("\n");
+impl DepotDownloaderSettings {
+    /// Makes sure that there is always a valid selected profile.
+    pub fn ensure_valid_profile(&mut self) {
+        if self.profiles.is_empty() {
+            self.profiles.push(AccountProfile::default());
+        }
+
+        if self.selected_profile >= self.profiles.len() {
+            self.selected_profile = 0;
+        }
+    }
+
+    pub fn profile(&self) -> &AccountProfile {
+        &self.profiles[self.selected_profile]
+    }
+
+    pub fn profile_mut(&mut self) -> &mut AccountProfile {
+        &mut self.profiles[self.selected_profile]
+    }
+
+    /// Selects the first profile assigned to the app, returning whether one was found.
+    pub fn select_profile_for_app(&mut self, app: &str) -> bool {
+        match self.profiles.iter().position(|profile| profile.matches_app(app)) {
+            Some(index) => {
+                self.selected_profile = index;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+fn write_changes_to_file(changes: &Changes) -> std::io::Result<PathBuf> {
+    let download_files = changes.added.iter().chain(&changes.modified).cloned().collect::<Vec<String>>().join("\n");
     // Write changes to file files.txt
-    let path = "files.txt";
-    std::fs::write(path, download_files)?;
-    Ok(())
+    let path = paths::file_list();
+    std::fs::write(&path, download_files)?;
+    Ok(path)
 }
 
 pub fn download_changes(
@@ -76,34 +138,35 @@ pub fn download_changes(
     input_receiver: Receiver<String>,
     output_sender: Sender<String>,
 ) -> std::io::Result<PathBuf> {
-    write_changes_to_file(changes)?;
+    let file_list = write_changes_to_file(changes)?;
     let _ = output_sender.clone().send("Starting Depot Downloader...\n".to_string());
     // Download path
-    let download_path = current_dir().unwrap().to_path_buf().join("Downloads")
+    let download_path = settings.download_root
         .join(format!("{} - Depot {} (Build {} to {})",
                       changes.name, changes.depot, changes.initial_build, changes.final_build));
     let download_path_clone = download_path.clone();
     // Run Depot Downloader
     let mut command = Command::new("./DepotDownloader.exe");
+    hide_console_window(&mut command);
     command
-        .creation_flags(0x08000000)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .args(["-app", &changes.app, "-depot", &changes.depot, "-manifest", &changes.manifest])
-        .args(["-dir", &download_path.to_str().unwrap()]);
+        .args(["-dir", download_path.to_str().unwrap()]);
 
     if !settings.download_entire_depot {
-        command.args(["-filelist", "files.txt"]);
+        command.arg("-filelist").arg(&file_list);
     }
 
-    match settings.remember_credentials {
-        true => if !settings.password.is_empty() {
-            command.args(["-username", &settings.username, "-password", &settings.password, "-remember-password"])
+    let profile = settings.profile();
+    match profile.remember_credentials {
+        true => if !profile.password.is_empty() {
+            command.args(["-username", &profile.username, "-password", &profile.password, "-remember-password"])
         } else {
-            command.args(["-username", &settings.username, "-remember-password"])
+            command.args(["-username", &profile.username, "-remember-password"])
         },
-        false => command.args(["-username", &settings.username, "-password", &settings.password])
+        false => command.args(["-username", &profile.username, "-password", &profile.password])
     };
 
     command
@@ -118,7 +181,7 @@ pub fn download_changes(
         "Enter account password",
     ];
 
-    let result = Arc::new(Mutex::new(Err(std::io::Error::new(std::io::ErrorKind::Other, "Unknown error"))));
+    let result = Arc::new(Mutex::new(Err(std::io::Error::other("Unknown error"))));
 
     thread::scope(|s| {
         if let Some(mut stderr) = child.stderr.take() {
@@ -204,23 +267,24 @@ pub fn download_changes(
 pub fn download_manifest(download_path: PathBuf, changes: &Changes, settings: &DepotDownloaderSettings) -> std::io::Result<()> {
     // Run Depot Downloader
     let mut command = Command::new("./DepotDownloader.exe");
+    hide_console_window(&mut command);
     command
-        .creation_flags(0x08000000)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .args(["-app", &changes.app, "-depot", &changes.depot, "-manifest", &changes.manifest])
-        .args(["-dir", &download_path.to_str().unwrap()])
+        .args(["-dir", download_path.to_str().unwrap()])
         .arg("-manifest-only");
 
 
-    match settings.remember_credentials {
-        true => if !settings.password.is_empty() {
-            command.args(["-username", &settings.username, "-password", &settings.password, "-remember-password"])
+    let profile = settings.profile();
+    match profile.remember_credentials {
+        true => if !profile.password.is_empty() {
+            command.args(["-username", &profile.username, "-password", &profile.password, "-remember-password"])
         } else {
-            command.args(["-username", &settings.username, "-remember-password"])
+            command.args(["-username", &profile.username, "-remember-password"])
         },
-        false => command.args(["-username", &settings.username, "-password", &settings.password])
+        false => command.args(["-username", &profile.username, "-password", &profile.password])
     };
 
     let mut child = command.spawn()?;
@@ -0,0 +1,278 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use crossbeam_channel::Sender;
+use sha1::{Digest, Sha1};
+use crate::modules::changes::Changes;
+use crate::modules::native_archiver::collect_entries;
+use crate::modules::package_metadata::INSTALLER_FOLDER;
+use crate::modules::reproducible::to_hex;
+
+/// Depot Downloader marks folders with this flag.
+const DIRECTORY_FLAG: u32 = 64;
+
+/// A file listed in a manifest, with its path as Steam writes it.
+pub struct ManifestFile {
+    pub path: String,
+    pub size: u64,
+    pub sha1: String,
+}
+
+/// The text manifest Depot Downloader writes as `manifest_<depot>_<id>.txt`.
+pub struct DepotManifest {
+    pub depot: String,
+    pub manifest: String,
+    pub files: Vec<ManifestFile>,
+}
+
+impl DepotManifest {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        // The file name has both IDs, the header is only used for renamed files
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let mut ids = stem.strip_prefix("manifest_").unwrap_or_default().split('_');
+        let mut manifest = Self {
+            depot: ids.next().unwrap_or_default().to_string(),
+            manifest: ids.next().unwrap_or_default().to_string(),
+            files: Vec::new(),
+        };
+
+        let mut in_file_list = false;
+        for line in text.lines() {
+            if let Some(depot) = line.strip_prefix("Content Manifest for Depot ") {
+                manifest.depot = depot.trim().to_string();
+            } else if let Some((_, value)) = line.split_once("Manifest ID / date").and_then(|(_, rest)| rest.split_once(':')) {
+                manifest.manifest = value.split('/').next().unwrap_or_default().trim().to_string();
+            } else if line.trim_start().starts_with("Size Chunks File SHA") {
+                in_file_list = true;
+            } else if in_file_list && !line.trim().is_empty() {
+                // Size, chunks, SHA-1 and flags come first, the name can contain spaces
+                let mut rest = line.trim_start();
+                let mut fields = Vec::new();
+                for _ in 0..4 {
+                    let (field, remainder) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    fields.push(field);
+                    rest = remainder.trim_start();
+                }
+                let (Ok(size), Ok(flags)) = (fields[0].parse::<u64>(), fields[3].parse::<u32>()) else {
+                    continue;
+                };
+                if flags & DIRECTORY_FLAG == 0 && !rest.is_empty() {
+                    manifest.files.push(ManifestFile {
+                        path: rest.to_string(),
+                        size,
+                        sha1: fields[2].to_lowercase(),
+                    });
+                }
+            }
+        }
+        match manifest.files.is_empty() {
+            true => Err(std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{} lists no files", path.display()))),
+            false => Ok(manifest),
+        }
+    }
+}
+
+/// How an installed game differs from the build of a manifest.
+pub struct InstallReport {
+    pub game_path: PathBuf,
+    pub checked_files: usize,
+    pub missing: Vec<String>,
+    pub mismatched: Vec<String>,
+    /// Files in the game folder which are not part of the build, such as saves or mods.
+    pub extra: Vec<String>,
+}
+
+impl InstallReport {
+    pub fn is_intact(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+
+    /// Changes which download only the missing and mismatched files, for the normal download flow.
+    pub fn repair_changes(&self, manifest: &DepotManifest, app: &str, build: &str, remove_extra: bool) -> Changes {
+        let name = self.game_path.file_name().unwrap_or_default().to_string_lossy();
+        Changes {
+            name: format!("{} (Repair)", name),
+            app: app.to_string(),
+            depot: manifest.depot.clone(),
+            initial_build: "Installed".to_string(),
+            final_build: match build.is_empty() {
+                true => manifest.manifest.clone(),
+                false => build.to_string(),
+            },
+            added: self.missing.clone(),
+            removed: if remove_extra { self.extra.clone() } else { Vec::new() },
+            modified: self.mismatched.clone(),
+            manifest: manifest.manifest.clone(),
+            branch: String::new(),
+        }
+    }
+}
+
+impl Display for InstallReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Checked {} files in {}.", self.checked_files, self.game_path.display())?;
+        let lists = [("Missing", &self.missing), ("Different", &self.mismatched), ("Not part of the build", &self.extra)];
+        for (title, files) in lists {
+            writeln!(f, "{}: {}", title, files.len())?;
+            for file in files {
+                writeln!(f, "  {}", file)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The path with slashes, in lowercase where the file system ignores case.
+#[cfg(windows)]
+fn comparable_path(path: &str) -> String {
+    path.replace('\\', "/").to_lowercase()
+}
+
+#[cfg(not(windows))]
+fn comparable_path(path: &str) -> String {
+    path.replace('\\', "/")
+}
+
+/// Hashes every file of the manifest in the game folder on all cores, and looks for files which
+/// the manifest does not list.
+pub fn verify_install(game_path: &Path, manifest: &DepotManifest, output_sender: &Sender<String>) -> std::io::Result<InstallReport> {
+    let next_file = AtomicUsize::new(0);
+    let checked_files = AtomicUsize::new(0);
+    let missing = Mutex::new(Vec::new());
+    let mismatched = Mutex::new(Vec::new());
+    let threads = thread::available_parallelism().map(|threads| threads.get()).unwrap_or(4);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                while let Some(file) = manifest.files.get(next_file.fetch_add(1, Ordering::Relaxed)) {
+                    let path = game_path.join(file.path.replace('\\', "/"));
+                    match path.metadata() {
+                        Ok(metadata) if metadata.is_file() => {
+                            if metadata.len() != file.size || hash_file(&path).ok().as_ref() != Some(&file.sha1) {
+                                mismatched.lock().unwrap().push(file.path.clone());
+                            }
+                        }
+                        _ => missing.lock().unwrap().push(file.path.clone()),
+                    }
+                    let checked = checked_files.fetch_add(1, Ordering::Relaxed) + 1;
+                    if checked.is_multiple_of(1000) {
+                        let _ = output_sender.send(format!("Checked {} of {} files...\n", checked, manifest.files.len()));
+                    }
+                }
+            });
+        }
+    });
+
+    let listed: BTreeSet<String> = manifest.files.iter().map(|file| comparable_path(&file.path)).collect();
+    let uses_backslashes = manifest.files.iter().any(|file| file.path.contains('\\'));
+    let mut extra = Vec::new();
+    for entry in collect_entries(game_path, "")? {
+        let path = entry.name.trim_start_matches('/');
+        if entry.path.is_file() && !listed.contains(&comparable_path(path)) && !path.starts_with(&format!("{}/", INSTALLER_FOLDER)) {
+            extra.push(if uses_backslashes { path.replace('/', "\\") } else { path.to_string() });
+        }
+    }
+
+    let mut missing = missing.into_inner().unwrap();
+    let mut mismatched = mismatched.into_inner().unwrap();
+    missing.sort();
+    mismatched.sort();
+    Ok(InstallReport {
+        game_path: game_path.to_path_buf(),
+        checked_files: checked_files.into_inner(),
+        missing,
+        mismatched,
+        extra,
+    })
+}
+
+fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha1::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "Content Manifest for Depot 228990 
+
+Manifest ID / date     : 1829726630299308803 / 05/18/2023 10:49:26 
+Total number of files  : 3 
+Total number of chunks : 2 
+Total bytes on disk    : 11 
+Total bytes compressed : 11 
+
+
+          Size Chunks File SHA                                 Flags Name
+             5      1 AAF4C61DDCC5E8A2DABEDE0F3B482CD9AEA9434D     0 Game.exe
+             0      0 0000000000000000000000000000000000000000    64 Data
+             6      1 8D2F6DBE4F8A2A9B5D8C0F0A1E2B3C4D5E6F7A8B     0 Data\\Level One.pak
+";
+
+    fn write_manifest(folder: &Path) -> PathBuf {
+        let path = folder.join("manifest_228990_1.txt");
+        std::fs::write(&path, MANIFEST).unwrap();
+        path
+    }
+
+    #[test]
+    fn reads_the_files_of_a_manifest() {
+        let folder = tempfile::tempdir().unwrap();
+        let manifest = DepotManifest::read(&write_manifest(folder.path())).unwrap();
+        assert_eq!(manifest.depot, "228990");
+        assert_eq!(manifest.manifest, "1829726630299308803");
+        let files: Vec<(&str, u64, &str)> = manifest.files.iter()
+            .map(|file| (file.path.as_str(), file.size, file.sha1.as_str()))
+            .collect();
+        assert_eq!(files, [
+            ("Game.exe", 5, "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"),
+            ("Data\\Level One.pak", 6, "8d2f6dbe4f8a2a9b5d8c0f0a1e2b3c4d5e6f7a8b"),
+        ]);
+    }
+
+    #[test]
+    fn rejects_a_manifest_without_files() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("manifest_228990_1.txt");
+        std::fs::write(&path, "Content Manifest for Depot 228990\n").unwrap();
+        assert_eq!(DepotManifest::read(&path).err().unwrap().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn verifies_an_install() {
+        let folder = tempfile::tempdir().unwrap();
+        let manifest = DepotManifest::read(&write_manifest(folder.path())).unwrap();
+        let game_path = folder.path().join("Game");
+        std::fs::create_dir_all(game_path.join("Data")).unwrap();
+        std::fs::create_dir_all(game_path.join(INSTALLER_FOLDER)).unwrap();
+        // The SHA-1 of "hello" matches the manifest, the level does not
+        std::fs::write(game_path.join("Game.exe"), "hello").unwrap();
+        std::fs::write(game_path.join("Data/save.dat"), "save").unwrap();
+        std::fs::write(game_path.join(INSTALLER_FOLDER).join("changes.json"), "{}").unwrap();
+
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let report = verify_install(&game_path, &manifest, &sender).unwrap();
+        assert_eq!(report.checked_files, 2);
+        assert!(report.mismatched.is_empty());
+        assert_eq!(report.missing, ["Data\\Level One.pak"]);
+        assert_eq!(report.extra, ["Data\\save.dat"]);
+        assert!(!report.is_intact());
+
+        std::fs::write(game_path.join("Data/Level One.pak"), "level1").unwrap();
+        let report = verify_install(&game_path, &manifest, &sender).unwrap();
+        assert!(report.missing.is_empty());
+        assert_eq!(report.mismatched, ["Data\\Level One.pak"]);
+
+        let changes = report.repair_changes(&manifest, "228980", "", true);
+        assert_eq!(changes.final_build, "1829726630299308803");
+        assert_eq!(changes.modified, ["Data\\Level One.pak"]);
+        assert_eq!(changes.removed, ["Data\\save.dat"]);
+    }
+}
@@ -1,11 +1,11 @@
 use std::env::current_dir;
-use std::os::windows::process::CommandExt;
 use std::process::{Command, Stdio};
 use std::thread;
 use crossbeam_channel::{Receiver, Sender};
 use eframe::egui::{Button, Context, ScrollArea, Window};
 use self_update::update::Release;
 use self_update::version::bump_is_greater;
+use crate::modules::archiver_backend::hide_console_window;
 
 #[derive(Default)]
 pub enum UpdateStatus {
@@ -22,9 +22,12 @@ enum AppType {
     Installer
 }
 
+/// The latest release, its version and whether it is newer, for the creator and the installer.
+type LatestReleases = ((Release, String, bool), (Release, String, bool));
+
 struct HelpChannels {
-    pub release_sender: Sender<Result<((Release, String, bool), (Release, String, bool)), String>>,
-    pub release_receiver: Receiver<Result<((Release, String, bool), (Release, String, bool)), String>>,
+    pub release_sender: Sender<Result<LatestReleases, String>>,
+    pub release_receiver: Receiver<Result<LatestReleases, String>>,
     pub update_status_sender: Sender<Result<AppType, (AppType, String)>>,
     pub update_status_receiver: Receiver<Result<AppType, (AppType, String)>>,
 
@@ -53,8 +56,6 @@ struct LatestVersions {
 #[derive(Default)]
 struct AllUpdateStatus {
     checked: UpdateStatus,
-    creator: UpdateStatus,
-    installer: UpdateStatus,
 }
 
 pub struct HelpUI {
@@ -66,7 +67,6 @@ pub struct HelpUI {
     updating: (bool, bool),
     creator_status: String,
     installer_status: String,
-    pub link_to_latest_version: String,
 }
 
 impl Default for HelpUI {
@@ -80,7 +80,6 @@ impl Default for HelpUI {
             updating: (false, false),
             creator_status: String::new(),
             installer_status: String::new(),
-            link_to_latest_version: String::new(),
         }
     }
 }
@@ -90,7 +89,6 @@ const HOMEPAGE: &str = "https://cs.rin.ru/forum/viewtopic.php?f=14&t=138413";
 const DOCUMENTATION: &str = "https://reddiepoint.github.io/RedAlt-SteamUp-Documentation/using-the-creator.html";
 
 impl HelpUI {
-    pub fn display(&mut self, ctx: &Context) {}
     pub fn show_help_window(&mut self, ctx: &Context) {
         Window::new("Help").open(&mut self.show_help).show(ctx, |ui| ScrollArea::vertical().min_scrolled_height(ui.available_height()).id_source("Help").show(ui, |ui| {
             ui.horizontal(|ui| {
@@ -284,7 +282,7 @@ impl HelpUI {
         });
     }
 
-    fn check_for_updates() -> Result<((Release, String, bool), (Release, String, bool)), Box<dyn std::error::Error>> {
+    fn check_for_updates() -> Result<LatestReleases, Box<dyn std::error::Error>> {
         let creator_current_version = env!("CARGO_PKG_VERSION").to_string();
         let creator_update = self_update::backends::github::Update::configure()
             .repo_owner("Reddiepoint")
@@ -296,8 +294,8 @@ impl HelpUI {
             .get_latest_release()?;
 
         let mut command = Command::new("./RedAlt-SteamUp-Installer.exe");
+        hide_console_window(&mut command);
         command
-            .creation_flags(0x08000000)
             .stdout(Stdio::piped())
             .arg("--version");
         let installer_current_version = match command.spawn() {
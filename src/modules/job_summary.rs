@@ -0,0 +1,75 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::modules::checksums::Checksums;
+use crate::modules::torrent::{TORRENT_EXTENSION, Torrent};
+use crate::modules::verification::REPORT_SUFFIX;
+
+/// Appended to the download folder's name for the summary, which is written to the output folder.
+pub const SUMMARY_SUFFIX: &str = ".summary.txt";
+
+/// What is needed to post an update: the archive, its password and whether it was verified.
+pub struct JobSummary {
+    pub name: String,
+    pub archiver: String,
+    pub archive_path: PathBuf,
+    pub password: Option<String>,
+    pub hide_file_names: bool,
+    /// Memory needed to extract the archive, in MB.
+    pub decompression_memory: u64,
+    /// Whether the archive passed verification, if it was verified.
+    pub verified: Option<bool>,
+    /// SHA-256 of the input hash manifest, if the archive was built reproducibly.
+    pub input_hash: Option<String>,
+    /// Hashes of every volume, once the archive is finished.
+    pub checksums: Option<Checksums>,
+    pub torrent: Option<Torrent>,
+}
+
+impl JobSummary {
+    pub fn summary_path(&self, output_path: &Path) -> PathBuf {
+        output_path.join(format!("{}{}", self.name, SUMMARY_SUFFIX))
+    }
+
+    /// Writes the summary into the output folder and returns its path.
+    pub fn write(&self, output_path: &Path) -> std::io::Result<PathBuf> {
+        let path = self.summary_path(output_path);
+        std::fs::write(&path, self.to_string())?;
+        Ok(path)
+    }
+}
+
+impl Display for JobSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Update: {}", self.name)?;
+        writeln!(f, "Archive: {} ({})", self.archive_path.file_name().unwrap().to_string_lossy(), self.archiver)?;
+        match &self.password {
+            Some(password) => writeln!(f, "Password: {}{}", password, if self.hide_file_names { " (file names hidden)" } else { "" })?,
+            None => writeln!(f, "Password: none")?,
+        }
+        writeln!(f, "Memory needed to extract: {} MB", self.decompression_memory)?;
+        match self.verified {
+            Some(true) => writeln!(f, "Verification: passed")?,
+            Some(false) => writeln!(f, "Verification: failed")?,
+            None => writeln!(f, "Verification: skipped")?,
+        }
+        if let Some(input_hash) = &self.input_hash {
+            writeln!(f, "Input manifest SHA-256: {} (reproducible)", input_hash)?;
+        }
+        if let Some(checksums) = &self.checksums {
+            write!(f, "Checksums:\n{}", checksums)?;
+        }
+        if let Some(torrent) = &self.torrent {
+            writeln!(f, "Torrent: {}", torrent.path.file_name().unwrap().to_string_lossy())?;
+            writeln!(f, "Magnet link: {}", torrent.magnet_link)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reports, summaries and torrents are kept with the archives but should not be uploaded. Checksum
+/// files are uploaded with the volumes, so downloads can be checked.
+pub fn is_job_file(path: &Path) -> bool {
+    let name = path.to_string_lossy();
+    name.ends_with(REPORT_SUFFIX) || name.ends_with(SUMMARY_SUFFIX)
+        || path.extension().is_some_and(|extension| extension == TORRENT_EXTENSION)
+}
@@ -0,0 +1,13 @@
+use sysinfo::System;
+
+/// Physical memory which is available right now, in MB.
+pub fn available_memory() -> u64 {
+    let mut system = System::new();
+    system.refresh_memory();
+    system.available_memory() / (1024 * 1024)
+}
+
+/// The memory compressing may use before a warning is shown, in MB.
+pub fn memory_budget(warning_percentage: u8) -> u64 {
+    available_memory() * warning_percentage as u64 / 100
+}
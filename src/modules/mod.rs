@@ -1,8 +1,33 @@
 pub mod app;
+mod apply_update;
+mod archiver_backend;
+mod archiver_detection;
+mod binary_delta;
 mod changes;
+mod checksums;
 mod compression;
+mod compression_progress;
 mod compression_settings;
+mod content_classification;
+mod credentials;
 mod create_update;
 mod depot_downloader;
+mod depot_manifest;
 mod help;
+mod job_summary;
+mod memory;
+mod native_archiver;
+mod package_metadata;
+mod paths;
+mod presets;
+mod reproducible;
 mod settings;
+mod settings_schema;
+mod size_estimation;
+#[cfg(test)]
+mod test_support;
+mod torrent;
+mod update_applier;
+mod upload_destinations;
+mod verification;
+mod verify_install;
@@ -0,0 +1,717 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use sevenz_rust::{Password, SevenZArchiveEntry, SevenZMethod, SevenZMethodConfiguration, SevenZReader, SevenZWriter};
+use sevenz_rust::lzma::LZMA2Options;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
+use crate::modules::compression_progress::{CompressionProgress, ProgressTracker};
+use crate::modules::compression_settings::{SizeUnit, zstd_memory_usage};
+use crate::modules::content_classification::{ContentPlan, extension};
+use crate::modules::depot_downloader::DEPOT_DOWNLOADER_FOLDER;
+use crate::modules::size_estimation::SampleCodec;
+use crate::modules::verification::VerificationReport;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum NativeArchiveFormat {
+    Zip,
+    TarZstd,
+    SevenZip,
+}
+
+impl NativeArchiveFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            NativeArchiveFormat::Zip => "zip",
+            NativeArchiveFormat::TarZstd => "tar.zst",
+            NativeArchiveFormat::SevenZip => "7z",
+        }
+    }
+}
+
+impl Display for NativeArchiveFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+/// Settings for the built-in archiver, which does not need 7-Zip or WinRAR to be installed.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct NativeSettings {
+    pub archive_format: NativeArchiveFormat,
+    /// From 0 (store) to 9 (ultra), mapped onto each format's own levels.
+    pub compression_level: u8,
+    /// Only used by tar.zst, the other formats compress on a single thread.
+    pub number_of_cpu_threads: u8,
+    pub split_size: u16,
+    pub split_size_unit: SizeUnit,
+    /// Set by reproducible jobs, which leave out owners and timestamps.
+    #[serde(skip)]
+    pub reproducible: bool,
+    /// Set by jobs which store already compressed files and group the others by type.
+    #[serde(skip)]
+    pub content_plan: Option<ContentPlan>,
+    /// Set by jobs which ship modified files as deltas, named like the entries of `collect_entries`.
+    #[serde(skip)]
+    pub excluded_files: BTreeSet<String>,
+}
+
+impl Default for NativeSettings {
+    fn default() -> Self {
+        Self {
+            archive_format: NativeArchiveFormat::TarZstd,
+            compression_level: 7,
+            number_of_cpu_threads: thread::available_parallelism().unwrap().get() as u8,
+            split_size: 0,
+            split_size_unit: SizeUnit::Gigabytes,
+            reproducible: false,
+            content_plan: None,
+            excluded_files: BTreeSet::new(),
+        }
+    }
+}
+
+impl NativeSettings {
+    pub fn compress(
+        &self,
+        download_path: PathBuf,
+        output_path: PathBuf,
+        stdout_sender: Sender<String>,
+        progress_sender: Sender<CompressionProgress>,
+    ) -> std::io::Result<()> {
+        let _ = stdout_sender.send(format!("\nCompressing files with the built-in archiver ({})...\n", self.archive_format));
+
+        let folder_name = download_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let archive_path = self.archive_path(&download_path, &output_path);
+        std::fs::create_dir_all(archive_path.parent().unwrap())?;
+        let volume_size = self.split_size as u64 * self.split_size_unit.bytes();
+        let writer = VolumeWriter::new(archive_path, volume_size);
+
+        // Entries are stored under the download folder's name, like 7-Zip and WinRAR do
+        let mut entries = collect_entries(&download_path, &folder_name)?;
+        entries.retain(|entry| !self.excluded_files.contains(&entry.name));
+        // tar.zst is one stream, where similar files next to each other compress better
+        if self.content_plan.is_some() && self.archive_format == NativeArchiveFormat::TarZstd {
+            entries.sort_by_cached_key(|entry| (extension(&entry.name), entry.name.clone()));
+        }
+        let mut progress = Progress::new(&entries, stdout_sender, progress_sender);
+        match self.archive_format {
+            NativeArchiveFormat::Zip => self.write_zip(writer, &entries, &mut progress)?,
+            NativeArchiveFormat::TarZstd => self.write_tar_zstd(writer, &entries, &mut progress)?,
+            NativeArchiveFormat::SevenZip => self.write_7z(writer, &entries, &mut progress)?,
+        }
+        progress.finish();
+        Ok(())
+    }
+
+    /// The memory needed to compress and to decompress the archive in MB.
+    pub fn memory_usage(&self) -> (u64, u64) {
+        match self.archive_format {
+            NativeArchiveFormat::Zip => (1, 1),
+            NativeArchiveFormat::TarZstd => zstd_memory_usage(self.compression_level * 2 + 1, self.number_of_cpu_threads.max(1) as u64),
+            // The dictionary sizes of the LZMA2 presets, in KB
+            NativeArchiveFormat::SevenZip => {
+                let dictionary: u64 = [256, 1024, 2048, 4096, 4096, 8192, 8192, 16384, 32768, 65536][self.compression_level.min(9) as usize];
+                ((dictionary * 23 / 2).div_ceil(1024) + 1, dictionary / 1024 + 1)
+            }
+        }
+    }
+
+    /// Lowers the threads until compressing needs at most `budget` MB. Returns whether the
+    /// settings fit.
+    pub fn fit_to_memory(&mut self, budget: u64) -> bool {
+        while self.memory_usage().0 > budget {
+            if self.archive_format != NativeArchiveFormat::TarZstd || self.number_of_cpu_threads <= 1 {
+                return false;
+            }
+            self.number_of_cpu_threads -= 1;
+        }
+        true
+    }
+
+    /// The codec which estimates the archive's size, the same one the format compresses with.
+    pub fn sample_codec(&self) -> SampleCodec {
+        match (self.compression_level, self.archive_format) {
+            (0, _) => SampleCodec::Store,
+            (level, NativeArchiveFormat::Zip) => SampleCodec::Deflate { level },
+            (level, NativeArchiveFormat::TarZstd) => SampleCodec::Zstd { level: level as i32 * 2 + 1 },
+            (level, NativeArchiveFormat::SevenZip) => SampleCodec::Lzma2 { preset: level, dictionary_size: None },
+        }
+    }
+
+    /// Whether the file is stored without compression. tar.zst compresses everything in one stream.
+    fn is_stored(&self, name: &str) -> bool {
+        self.archive_format != NativeArchiveFormat::TarZstd
+            && self.content_plan.as_ref().is_some_and(|plan| plan.is_stored(name))
+    }
+
+    /// Split archives are put into their own folder, like the external archivers' are.
+    pub fn archive_path(&self, download_path: &Path, output_path: &Path) -> PathBuf {
+        let folder_name = download_path.file_name().unwrap_or_default().to_string_lossy();
+        let split_folder = if self.split_size > 0 { &*folder_name } else { "" };
+        output_path.join(split_folder).join(format!("{}.{}", folder_name, self.archive_format.extension()))
+    }
+
+    /// Reads every file in the archive, which checks their checksums, and compares the contents
+    /// with the downloaded files.
+    pub fn verify(&self, download_path: &Path, output_path: &Path, stdout_sender: Sender<String>) -> VerificationReport {
+        let _ = stdout_sender.send("\nVerifying the archive with the built-in archiver...\n".to_string());
+        let mut report = VerificationReport::new(self.archive_path(download_path, output_path), self.split_size > 0);
+        if report.volumes.is_empty() {
+            return report;
+        }
+
+        let volumes = report.volumes.iter().map(|(volume, _)| volume.clone()).collect();
+        let listing = VolumeReader::new(volumes).and_then(|reader| match self.archive_format {
+            NativeArchiveFormat::Zip => read_zip(reader),
+            NativeArchiveFormat::TarZstd => read_tar_zstd(reader),
+            NativeArchiveFormat::SevenZip => read_7z(reader),
+        });
+        match listing {
+            Ok(listing) => {
+                report.test_result = Ok(());
+                if let Err(error) = report.compare_listing(download_path, listing, |name| self.excluded_files.contains(name)) {
+                    report.listing_result = Err(error.to_string());
+                }
+            }
+            Err(error) => report.test_result = Err(error.to_string()),
+        }
+        report
+    }
+
+    fn write_zip(&self, writer: VolumeWriter, entries: &[ArchiveEntry], progress: &mut Progress) -> std::io::Result<()> {
+        let mut zip = ZipWriter::new(writer);
+        let options = match self.compression_level {
+            0 => FileOptions::default().compression_method(CompressionMethod::Stored),
+            level => FileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(level as i32)),
+        }.large_file(true);
+        // zip always stores a modification time, so every entry gets the earliest one it can store
+        let options = match self.reproducible {
+            true => options.last_modified_time(DateTime::default()),
+            false => options,
+        };
+        let stored_options = options.compression_method(CompressionMethod::Stored).compression_level(None);
+
+        for entry in entries {
+            if entry.path.is_dir() {
+                zip.add_directory(entry.name.as_str(), options)?;
+            } else {
+                let options = if self.is_stored(&entry.name) { stored_options } else { options };
+                zip.start_file(entry.name.as_str(), options)?;
+                std::io::copy(&mut File::open(&entry.path)?, &mut zip)?;
+            }
+            progress.add(entry);
+        }
+        zip.finish()?.flush()
+    }
+
+    fn write_tar_zstd(&self, writer: VolumeWriter, entries: &[ArchiveEntry], progress: &mut Progress) -> std::io::Result<()> {
+        // zstd has levels 1 to 19 without the ultra levels
+        let level = self.compression_level as i32 * 2 + 1;
+        let mut encoder = zstd::stream::write::Encoder::new(writer, level)?;
+        encoder.include_checksum(true)?;
+        encoder.multithread(self.number_of_cpu_threads as u32)?;
+        let mut tar = tar::Builder::new(encoder);
+        if self.reproducible {
+            tar.mode(tar::HeaderMode::Deterministic);
+        }
+        for entry in entries {
+            if entry.path.is_dir() {
+                tar.append_dir(&entry.name, &entry.path)?;
+            } else {
+                tar.append_path_with_name(&entry.path, &entry.name)?;
+            }
+            progress.add(entry);
+        }
+        tar.into_inner()?.finish()?.flush()
+    }
+
+    fn write_7z(&self, writer: VolumeWriter, entries: &[ArchiveEntry], progress: &mut Progress) -> std::io::Result<()> {
+        let mut archive = SevenZWriter::new(writer).map_err(to_io_error)?;
+        let compressed_methods = vec![
+            SevenZMethodConfiguration::new(SevenZMethod::LZMA2)
+                .with_options(LZMA2Options::with_preset(self.compression_level as u32).into()),
+        ];
+        for entry in entries {
+            // Every file is its own block, so the method can change between files
+            archive.set_content_methods(match self.is_stored(&entry.name) {
+                true => vec![SevenZMethodConfiguration::new(SevenZMethod::COPY)],
+                false => compressed_methods.clone(),
+            });
+            let mut archive_entry = SevenZArchiveEntry::from_path(&entry.path, entry.name.clone());
+            if self.reproducible {
+                archive_entry.has_creation_date = false;
+                archive_entry.has_last_modified_date = false;
+                archive_entry.has_access_date = false;
+            }
+            if entry.path.is_dir() {
+                archive.push_archive_entry::<File>(archive_entry, None).map_err(to_io_error)?;
+            } else {
+                archive.push_archive_entry(archive_entry, Some(File::open(&entry.path)?)).map_err(to_io_error)?;
+            }
+            progress.add(entry);
+        }
+        archive.finish().map_err(to_io_error)?.flush()
+    }
+}
+
+/// Each of the readers returns the files in the archive with their sizes, after reading them.
+fn read_zip(reader: VolumeReader) -> std::io::Result<Vec<(String, Option<u64>)>> {
+    let mut zip = ZipArchive::new(reader)?;
+    let mut files = Vec::new();
+    for index in 0..zip.len() {
+        let mut file = zip.by_index(index)?;
+        if !file.is_dir() {
+            let size = std::io::copy(&mut file, &mut std::io::sink())?;
+            files.push((file.name().to_string(), Some(size)));
+        }
+    }
+    Ok(files)
+}
+
+fn read_tar_zstd(reader: VolumeReader) -> std::io::Result<Vec<(String, Option<u64>)>> {
+    let mut tar = tar::Archive::new(zstd::stream::read::Decoder::new(reader)?);
+    let mut files = Vec::new();
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_file() {
+            let name = entry.path()?.to_string_lossy().to_string();
+            let size = std::io::copy(&mut entry, &mut std::io::sink())?;
+            files.push((name, Some(size)));
+        }
+    }
+    Ok(files)
+}
+
+fn read_7z(mut reader: VolumeReader) -> std::io::Result<Vec<(String, Option<u64>)>> {
+    let length = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(0))?;
+    let mut archive = SevenZReader::new(reader, length, Password::empty()).map_err(to_io_error)?;
+    let mut files = Vec::new();
+    archive.for_each_entries(|entry, data| {
+        if !entry.is_directory() {
+            let size = std::io::copy(data, &mut std::io::sink())?;
+            files.push((entry.name().to_string(), Some(size)));
+        }
+        Ok(true)
+    }).map_err(to_io_error)?;
+    Ok(files)
+}
+
+/// Opens an archive written by the built-in archiver. Split archives are opened by their first
+/// volume, such as `Game.7z.001`.
+fn open_archive(archive_path: &Path) -> std::io::Result<(NativeArchiveFormat, VolumeReader)> {
+    let name = archive_path.file_name().unwrap_or_default().to_string_lossy().to_string();
+    let (name, volumes) = match name.strip_suffix(".001") {
+        Some(name) => {
+            let mut volumes = Vec::new();
+            for number in 1.. {
+                let volume = archive_path.with_file_name(format!("{}.{:03}", name, number));
+                if !volume.is_file() {
+                    break;
+                }
+                volumes.push(volume);
+            }
+            (name.to_string(), volumes)
+        }
+        None => (name, vec![archive_path.to_path_buf()]),
+    };
+    let format = [NativeArchiveFormat::Zip, NativeArchiveFormat::TarZstd, NativeArchiveFormat::SevenZip].into_iter()
+        .find(|format| name.ends_with(&format!(".{}", format.extension())))
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::Unsupported,
+                                           format!("the built-in archiver cannot open {}", name)))?;
+    Ok((format, VolumeReader::new(volumes)?))
+}
+
+/// Lists the files in an archive written by the built-in archiver, without writing anything.
+pub fn list_archive(archive_path: &Path) -> std::io::Result<Vec<(String, Option<u64>)>> {
+    let (format, reader) = open_archive(archive_path)?;
+    match format {
+        NativeArchiveFormat::Zip => read_zip(reader),
+        NativeArchiveFormat::TarZstd => read_tar_zstd(reader),
+        NativeArchiveFormat::SevenZip => read_7z(reader),
+    }
+}
+
+/// Extracts `files`, named as in the listing, or everything if there are none into `output_folder`.
+/// Entries which would end up outside the output folder are skipped.
+pub fn extract_archive(archive_path: &Path, output_folder: &Path, files: &[String]) -> std::io::Result<()> {
+    let (format, mut reader) = open_archive(archive_path)?;
+    let wanted = |name: &str| files.is_empty() || files.iter().any(|file| file == name);
+    match format {
+        NativeArchiveFormat::Zip => {
+            let mut zip = ZipArchive::new(reader)?;
+            for index in 0..zip.len() {
+                let mut file = zip.by_index(index)?;
+                let Some(path) = file.enclosed_name().map(|path| output_folder.join(path)) else {
+                    continue;
+                };
+                if !wanted(file.name()) {
+                    continue;
+                }
+                if file.is_dir() {
+                    std::fs::create_dir_all(path)?;
+                } else {
+                    std::fs::create_dir_all(path.parent().unwrap())?;
+                    std::io::copy(&mut file, &mut File::create(path)?)?;
+                }
+            }
+        }
+        NativeArchiveFormat::TarZstd => {
+            // Unlike unpacking the whole archive, unpacking entries needs the output folder to exist
+            std::fs::create_dir_all(output_folder)?;
+            let mut tar = tar::Archive::new(zstd::stream::read::Decoder::new(reader)?);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                if wanted(&entry.path()?.to_string_lossy()) {
+                    entry.unpack_in(output_folder)?;
+                }
+            }
+        }
+        NativeArchiveFormat::SevenZip => {
+            let length = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(0))?;
+            let mut archive = SevenZReader::new(reader, length, Password::empty()).map_err(to_io_error)?;
+            archive.for_each_entries(|entry, data| {
+                let entry_path = Path::new(entry.name());
+                if !wanted(entry.name()) || entry_path.components().any(|component| !matches!(component, std::path::Component::Normal(_))) {
+                    // The data has to be read to get to the next entry
+                    std::io::copy(data, &mut std::io::sink())?;
+                    return Ok(true);
+                }
+                let path = output_folder.join(entry_path);
+                if entry.is_directory() {
+                    std::fs::create_dir_all(path)?;
+                } else {
+                    std::fs::create_dir_all(path.parent().unwrap())?;
+                    std::io::copy(data, &mut File::create(path)?)?;
+                }
+                Ok(true)
+            }).map_err(to_io_error)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_io_error<E: Display>(error: E) -> std::io::Error {
+    std::io::Error::other(error.to_string())
+}
+
+pub struct ArchiveEntry {
+    pub path: PathBuf,
+    /// The path inside the archive, always separated with '/'.
+    pub name: String,
+    pub size: u64,
+}
+
+/// Lists every file and folder under `root` in a stable order, named relative to `prefix`. Depot
+/// Downloader's state folder is left out, as it is never archived.
+pub fn collect_entries(root: &Path, prefix: &str) -> std::io::Result<Vec<ArchiveEntry>> {
+    let mut entries = Vec::new();
+    let mut folders = vec![(root.to_path_buf(), prefix.to_string())];
+    while let Some((folder, name)) = folders.pop() {
+        let mut children: Vec<_> = folder.read_dir()?.flatten()
+            .filter(|child| folder != root || child.file_name() != DEPOT_DOWNLOADER_FOLDER)
+            .collect();
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let child_name = format!("{}/{}", name, child.file_name().to_string_lossy());
+            let metadata = child.metadata()?;
+            if metadata.is_dir() {
+                folders.push((child.path(), child_name.clone()));
+            }
+            entries.push(ArchiveEntry {
+                path: child.path(),
+                name: child_name,
+                size: if metadata.is_file() { metadata.len() } else { 0 },
+            });
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+/// Reports progress whenever another percent of the input has been added.
+struct Progress {
+    stdout_sender: Sender<String>,
+    tracker: ProgressTracker,
+    total_files: usize,
+    total_bytes: u64,
+    files: usize,
+    bytes: u64,
+    last_percentage: u64,
+}
+
+impl Progress {
+    fn new(entries: &[ArchiveEntry], stdout_sender: Sender<String>, progress_sender: Sender<CompressionProgress>) -> Self {
+        let total_bytes = entries.iter().map(|entry| entry.size).sum::<u64>();
+        Self {
+            stdout_sender,
+            tracker: ProgressTracker::new(total_bytes, 1, progress_sender),
+            total_files: entries.len(),
+            total_bytes: total_bytes.max(1),
+            files: 0,
+            bytes: 0,
+            last_percentage: 0,
+        }
+    }
+
+    fn add(&mut self, entry: &ArchiveEntry) {
+        self.files += 1;
+        self.bytes += entry.size;
+        let percentage = self.bytes * 100 / self.total_bytes;
+        if percentage > self.last_percentage || self.files == self.total_files {
+            self.last_percentage = percentage;
+            self.tracker.update(percentage as f32, Some(&entry.name));
+        }
+    }
+
+    fn finish(&self) {
+        let _ = self.stdout_sender.send(format!("Added {} files ({} bytes).\n", self.files, self.bytes));
+    }
+}
+
+/// Writes one continuous stream split into volumes named `<archive>.001`, `<archive>.002`, ...,
+/// which 7-Zip can open directly. With a volume size of 0, the archive is written as one file.
+pub struct VolumeWriter {
+    path: PathBuf,
+    volume_size: u64,
+    volumes: Vec<File>,
+    position: u64,
+    length: u64,
+}
+
+impl VolumeWriter {
+    pub fn new(path: PathBuf, volume_size: u64) -> Self {
+        Self {
+            path,
+            volume_size,
+            volumes: Vec::new(),
+            position: 0,
+            length: 0,
+        }
+    }
+
+    fn volume_path(&self, index: usize) -> PathBuf {
+        match self.volume_size {
+            0 => self.path.clone(),
+            _ => PathBuf::from(format!("{}.{:03}", self.path.display(), index + 1)),
+        }
+    }
+
+    fn volume(&mut self, index: usize) -> std::io::Result<&mut File> {
+        while self.volumes.len() <= index {
+            let volume = File::create(self.volume_path(self.volumes.len()))?;
+            self.volumes.push(volume);
+        }
+        Ok(&mut self.volumes[index])
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let (index, offset, available) = match self.volume_size {
+            0 => (0, self.position, buf.len() as u64),
+            size => ((self.position / size) as usize, self.position % size, size - self.position % size),
+        };
+        let length = buf.len().min(available as usize);
+        let volume = self.volume(index)?;
+        volume.seek(SeekFrom::Start(offset))?;
+        let written = volume.write(&buf[..length])?;
+        self.position += written as u64;
+        self.length = self.length.max(self.position);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for volume in &mut self.volumes {
+            volume.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Seek for VolumeWriter {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid seek to a negative position")),
+        }
+    }
+}
+
+/// Reads the volumes written by `VolumeWriter` as one continuous stream.
+pub struct VolumeReader {
+    volumes: Vec<(File, u64)>,
+    position: u64,
+    length: u64,
+}
+
+impl VolumeReader {
+    pub fn new(paths: Vec<PathBuf>) -> std::io::Result<Self> {
+        let mut volumes = Vec::new();
+        for path in paths {
+            let file = File::open(path)?;
+            let length = file.metadata()?.len();
+            volumes.push((file, length));
+        }
+        let length = volumes.iter().map(|(_, length)| length).sum();
+        Ok(Self {
+            volumes,
+            position: 0,
+            length,
+        })
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut offset = self.position;
+        for (volume, length) in &mut self.volumes {
+            if offset < *length {
+                volume.seek(SeekFrom::Start(offset))?;
+                let available = (*length - offset).min(buf.len() as u64) as usize;
+                let read = volume.read(&mut buf[..available])?;
+                self.position += read as u64;
+                return Ok(read);
+            }
+            offset -= *length;
+        }
+        Ok(0)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, position: SeekFrom) -> std::io::Result<u64> {
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => self.length.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid seek to a negative position")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::test_support::random_bytes;
+
+    #[test]
+    fn volumes_are_read_as_one_stream() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("data.bin");
+        let data = random_bytes(2500, 1);
+        let mut writer = VolumeWriter::new(path.clone(), 1000);
+        writer.write_all(&data).unwrap();
+        // Archivers go back to fill in headers, which can cross a volume boundary
+        writer.seek(SeekFrom::Start(995)).unwrap();
+        writer.write_all(&[7; 10]).unwrap();
+        assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), 2500);
+        writer.flush().unwrap();
+        drop(writer);
+
+        let volumes: Vec<PathBuf> = (1..=3).map(|number| folder.path().join(format!("data.bin.{:03}", number))).collect();
+        let sizes: Vec<u64> = volumes.iter().map(|volume| volume.metadata().unwrap().len()).collect();
+        assert_eq!(sizes, [1000, 1000, 500]);
+        assert!(!folder.path().join("data.bin.004").exists());
+
+        let mut expected = data.clone();
+        expected[995..1005].fill(7);
+        let mut reader = VolumeReader::new(volumes).unwrap();
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read).unwrap();
+        assert_eq!(read, expected);
+
+        reader.seek(SeekFrom::End(-1010)).unwrap();
+        let mut middle = [0; 20];
+        reader.read_exact(&mut middle).unwrap();
+        assert_eq!(middle, expected[1490..1510]);
+        assert!(reader.seek(SeekFrom::Current(-2000)).is_err());
+    }
+
+    #[test]
+    fn unsplit_archives_are_one_file() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("data.bin");
+        let mut writer = VolumeWriter::new(path.clone(), 0);
+        writer.write_all(&random_bytes(2500, 2)).unwrap();
+        drop(writer);
+        assert_eq!(path.metadata().unwrap().len(), 2500);
+    }
+
+    #[test]
+    fn split_archives_round_trip() {
+        let folder = tempfile::tempdir().unwrap();
+        let download_path = folder.path().join("Game");
+        std::fs::create_dir_all(download_path.join("Data/Empty")).unwrap();
+        std::fs::create_dir_all(download_path.join(DEPOT_DOWNLOADER_FOLDER)).unwrap();
+        std::fs::write(download_path.join("Game.exe"), random_bytes(1536 * 1024, 3)).unwrap();
+        std::fs::write(download_path.join("Data/level.pak"), random_bytes(1024 * 1024, 4)).unwrap();
+        std::fs::write(download_path.join("Data/readme.txt"), "readme").unwrap();
+        std::fs::write(download_path.join("Data/delta.pak"), "shipped as a delta").unwrap();
+        std::fs::write(download_path.join(DEPOT_DOWNLOADER_FOLDER).join("state.bin"), "state").unwrap();
+
+        for format in [NativeArchiveFormat::Zip, NativeArchiveFormat::TarZstd, NativeArchiveFormat::SevenZip] {
+            let output_path = folder.path().join(format!("Output {}", format.extension()));
+            let settings = NativeSettings {
+                archive_format: format,
+                compression_level: 1,
+                number_of_cpu_threads: 2,
+                split_size: 1,
+                split_size_unit: SizeUnit::Megabytes,
+                excluded_files: BTreeSet::from(["Game/Data/delta.pak".to_string()]),
+                ..NativeSettings::default()
+            };
+            let (stdout_sender, _stdout_receiver) = crossbeam_channel::unbounded();
+            let (progress_sender, _progress_receiver) = crossbeam_channel::unbounded();
+            settings.compress(download_path.clone(), output_path.clone(), stdout_sender.clone(), progress_sender).unwrap();
+
+            let archive_path = settings.archive_path(&download_path, &output_path);
+            let first_volume = PathBuf::from(format!("{}.001", archive_path.display()));
+            assert!(PathBuf::from(format!("{}.003", archive_path.display())).is_file(), "{} is not split", format);
+            let report = settings.verify(&download_path, &output_path, stdout_sender);
+            assert!(report.passed(), "{} failed verification", format);
+
+            let names: Vec<String> = list_archive(&first_volume).unwrap().into_iter().map(|(name, _)| name).collect();
+            assert!(names.iter().any(|name| name == "Game/Data/level.pak"));
+            assert!(!names.iter().any(|name| name.contains("delta.pak") || name.contains(DEPOT_DOWNLOADER_FOLDER)));
+
+            let extracted_path = folder.path().join(format!("Extracted {}", format.extension()));
+            extract_archive(&first_volume, &extracted_path, &[]).unwrap();
+            for file in ["Game.exe", "Data/level.pak", "Data/readme.txt"] {
+                assert_eq!(std::fs::read(extracted_path.join("Game").join(file)).unwrap(),
+                           std::fs::read(download_path.join(file)).unwrap(), "{} differs in {}", file, format);
+            }
+            assert!(extracted_path.join("Game/Data/Empty").is_dir());
+            assert!(!extracted_path.join("Game/Data/delta.pak").exists());
+
+            let readme_path = folder.path().join(format!("Readme {}", format.extension()));
+            extract_archive(&first_volume, &readme_path, &["Game/Data/readme.txt".to_string()]).unwrap();
+            assert_eq!(std::fs::read_to_string(readme_path.join("Game/Data/readme.txt")).unwrap(), "readme");
+            assert!(!readme_path.join("Game/Game.exe").exists());
+        }
+    }
+}
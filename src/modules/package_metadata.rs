@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use crate::modules::binary_delta::FileStrategyRecord;
+use crate::modules::changes::Changes;
+use crate::modules::native_archiver::collect_entries;
+use crate::modules::reproducible::to_hex;
+
+/// The folder inside every package with the installer and the files describing the update.
+pub const INSTALLER_FOLDER: &str = ".RedAlt-SteamUp-Installer";
+pub const METADATA_FILE: &str = "update.json";
+/// Increased whenever installers need to handle the metadata differently.
+pub const METADATA_FORMAT_VERSION: u32 = 2;
+
+/// A file of the final build which the package ships, as it is once the update is installed.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct PackageFile {
+    /// The path inside the game folder, always separated with '/'.
+    pub path: String,
+    pub size: u64,
+    pub sha1: String,
+}
+
+/// Describes the package for installers and end users. It is written into the installer folder
+/// before compressing, so it is part of the archive.
+#[derive(Deserialize, Serialize)]
+#[serde(default)]
+pub struct PackageMetadata {
+    pub format_version: u32,
+    pub creator_version: String,
+    pub name: String,
+    pub app: String,
+    pub depot: String,
+    pub branch: String,
+    pub initial_build: String,
+    pub final_build: String,
+    pub manifest: String,
+    pub target_os: String,
+    /// Every added and modified file, so installers can check them after applying the update.
+    pub files: Vec<PackageFile>,
+    /// Files installers delete from the initial build.
+    pub removed: Vec<String>,
+    /// Memory needed to extract the archive, in MB.
+    pub decompression_memory: Option<u64>,
+    /// Whether each modified file is shipped in full or as a delta against the initial build.
+    pub file_strategies: Vec<FileStrategyRecord>,
+}
+
+impl Default for PackageMetadata {
+    fn default() -> Self {
+        Self {
+            format_version: METADATA_FORMAT_VERSION,
+            creator_version: env!("CARGO_PKG_VERSION").to_string(),
+            name: String::new(),
+            app: String::new(),
+            depot: String::new(),
+            branch: String::new(),
+            initial_build: String::new(),
+            final_build: String::new(),
+            manifest: String::new(),
+            target_os: String::new(),
+            files: Vec::new(),
+            removed: Vec::new(),
+            decompression_memory: None,
+            file_strategies: Vec::new(),
+        }
+    }
+}
+
+impl PackageMetadata {
+    /// The metadata for the update, without its files, which are hashed once they are downloaded.
+    pub fn new(changes: &Changes, target_os: &str) -> Self {
+        Self {
+            name: changes.name.clone(),
+            app: changes.app.clone(),
+            depot: changes.depot.clone(),
+            branch: changes.branch().to_string(),
+            initial_build: changes.initial_build.clone(),
+            final_build: changes.final_build.clone(),
+            manifest: changes.manifest.clone(),
+            target_os: target_os.to_string(),
+            removed: changes.removed.iter().map(|path| path.replace('\\', "/")).collect(),
+            ..Self::default()
+        }
+    }
+
+    pub fn path(download_path: &Path) -> PathBuf {
+        download_path.join(INSTALLER_FOLDER).join(METADATA_FILE)
+    }
+
+    /// Reads the metadata written so far, or returns the defaults if there is none.
+    pub fn read(download_path: &Path) -> Self {
+        std::fs::read_to_string(Self::path(download_path)).ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn write(&self, download_path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(Self::path(download_path), json)
+    }
+
+    /// Hashes every downloaded file outside the installer folder. This has to happen before
+    /// modified files are replaced by deltas.
+    pub fn hash_files(&mut self, download_path: &Path) -> std::io::Result<()> {
+        self.files.clear();
+        for entry in collect_entries(download_path, "")? {
+            let path = entry.name.trim_start_matches('/');
+            if !entry.path.is_file() || path.starts_with(&format!("{}/", INSTALLER_FOLDER)) {
+                continue;
+            }
+            let mut hasher = Sha1::new();
+            std::io::copy(&mut File::open(&entry.path)?, &mut hasher)?;
+            self.files.push(PackageFile {
+                path: path.to_string(),
+                size: entry.size,
+                sha1: to_hex(&hasher.finalize()),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::env::current_dir;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const APP_FOLDER: &str = "RedAlt-SteamUp-Creator";
+/// When this file is next to the executable (or in the working directory), everything is stored
+/// in that folder instead of the per-user config directory, like older versions did.
+pub const PORTABLE_MARKER: &str = "portable.txt";
+/// Files which older versions kept in the working directory.
+const MIGRATED_FILES: [&str; 2] = ["settings.json", "key.json"];
+
+/// The folder containing the creator's executable.
+pub fn app_dir() -> PathBuf {
+    std::env::current_exe().ok()
+        .and_then(|path| path.parent().map(|path| path.to_path_buf()))
+        .unwrap_or_else(|| current_dir().unwrap())
+}
+
+/// Returns the folder containing the portable marker, if the creator is running in portable mode.
+/// The marker is only looked for the first time, so the mode does not change while running.
+pub fn portable_dir() -> Option<&'static Path> {
+    static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+    PORTABLE_DIR.get_or_init(|| [current_dir().ok(), Some(app_dir())].into_iter().flatten()
+        .find(|dir| dir.join(PORTABLE_MARKER).is_file()))
+        .as_deref()
+}
+
+/// The folder is resolved and created the first time, which is when the settings are loaded.
+pub fn config_dir() -> &'static Path {
+    static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+    CONFIG_DIR.get_or_init(|| match portable_dir() {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            let dir = dirs::config_dir().unwrap_or_else(app_dir).join(APP_FOLDER);
+            let _ = std::fs::create_dir_all(&dir);
+            dir
+        }
+    })
+}
+
+pub fn settings_file() -> PathBuf {
+    config_dir().join("settings.json")
+}
+
+pub fn legacy_key_file() -> PathBuf {
+    config_dir().join("key.json")
+}
+
+pub fn file_list() -> PathBuf {
+    config_dir().join("files.txt")
+}
+
+/// A temporary folder for the files of the job compressing `download_path`. Every job downloads
+/// into its own folder, so jobs running at the same time never share it.
+pub fn job_dir(download_path: &Path) -> PathBuf {
+    std::env::temp_dir().join(APP_FOLDER).join(download_path.file_name().unwrap_or_default())
+}
+
+/// The files given to 7-Zip or WinRAR instead of the download folder.
+pub fn archive_file_list(download_path: &Path) -> PathBuf {
+    job_dir(download_path).join("archive_files.txt")
+}
+
+/// The files which are added to 7-Zip archives without compression.
+pub fn stored_file_list(download_path: &Path) -> PathBuf {
+    job_dir(download_path).join("stored_files.txt")
+}
+
+pub fn default_download_root() -> PathBuf {
+    default_data_dir("Downloads")
+}
+
+pub fn default_output_root() -> PathBuf {
+    default_data_dir("Completed")
+}
+
+/// Keeps using the folders from older versions if they exist, otherwise uses the user's
+/// downloads folder.
+fn default_data_dir(name: &str) -> PathBuf {
+    if let Some(dir) = portable_dir() {
+        return dir.join(name);
+    }
+
+    // Older versions used folders next to the working directory, which is usually the executable's
+    if let Some(legacy_dir) = [current_dir().ok(), Some(app_dir())].into_iter().flatten()
+        .map(|dir| dir.join(name))
+        .find(|dir| dir.is_dir()) {
+        return legacy_dir;
+    }
+
+    dirs::download_dir().or_else(dirs::home_dir).unwrap_or_else(app_dir)
+        .join(APP_FOLDER).join(name)
+}
+
+/// Copies the settings files which older versions kept in the working directory into the config
+/// directory. This does nothing in portable mode or once the config directory has settings.
+pub fn migrate_to_config_dir() -> std::io::Result<()> {
+    if portable_dir().is_some() || settings_file().exists() {
+        return Ok(());
+    }
+
+    for legacy_dir in [current_dir()?, app_dir()] {
+        if !legacy_dir.join(MIGRATED_FILES[0]).is_file() {
+            continue;
+        }
+
+        for file in MIGRATED_FILES {
+            if legacy_dir.join(file).is_file() {
+                std::fs::copy(legacy_dir.join(file), config_dir().join(file))?;
+                let _ = std::fs::remove_file(legacy_dir.join(file));
+            }
+        }
+        break;
+    }
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::modules::compression_settings::{SevenZipSettings, WinRARSettings};
+
+/// A named set of 7-Zip and WinRAR settings. The executables and passwords are not part of a
+/// preset, so presets can be shared without leaking passwords or paths of another computer.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CompressionPreset {
+    pub name: String,
+    pub seven_zip_settings: SevenZipSettings,
+    pub win_rar_settings: WinRARSettings,
+}
+
+impl Default for CompressionPreset {
+    fn default() -> Self {
+        Self::new("New preset".to_string(), &SevenZipSettings::default(), &WinRARSettings::default())
+    }
+}
+
+impl CompressionPreset {
+    pub fn new(name: String, seven_zip_settings: &SevenZipSettings, win_rar_settings: &WinRARSettings) -> Self {
+        Self {
+            name,
+            seven_zip_settings: SevenZipSettings {
+                path: None,
+                password: String::new(),
+                password_nonce: [0; 12],
+                encrypted_password: Vec::new(),
+                ..seven_zip_settings.clone()
+            },
+            win_rar_settings: WinRARSettings {
+                path: None,
+                password: String::new(),
+                password_nonce: [0; 12],
+                encrypted_password: Vec::new(),
+                ..win_rar_settings.clone()
+            },
+        }
+    }
+
+    /// Replaces the settings with the preset's, keeping the executables and passwords.
+    pub fn apply(&self, seven_zip_settings: &mut SevenZipSettings, win_rar_settings: &mut WinRARSettings) {
+        *seven_zip_settings = SevenZipSettings {
+            path: seven_zip_settings.path.take(),
+            password: std::mem::take(&mut seven_zip_settings.password),
+            password_nonce: seven_zip_settings.password_nonce,
+            encrypted_password: std::mem::take(&mut seven_zip_settings.encrypted_password),
+            ..self.seven_zip_settings.clone()
+        };
+        *win_rar_settings = WinRARSettings {
+            path: win_rar_settings.path.take(),
+            password: std::mem::take(&mut win_rar_settings.password),
+            password_nonce: win_rar_settings.password_nonce,
+            encrypted_password: std::mem::take(&mut win_rar_settings.encrypted_password),
+            ..self.win_rar_settings.clone()
+        };
+    }
+
+    pub fn export(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+    }
+
+    /// Reads a preset exported by `export`, renaming it if a preset with the same name exists.
+    pub fn import(path: &Path, presets: &[CompressionPreset]) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        let mut preset: CompressionPreset = serde_json::from_str(&json)?;
+        preset.name = unique_name(&preset.name, presets);
+        Ok(preset)
+    }
+}
+
+/// Appends a number to the name until no other preset uses it.
+pub fn unique_name(name: &str, presets: &[CompressionPreset]) -> String {
+    let mut unique_name = name.to_string();
+    let mut number = 2;
+    while presets.iter().any(|preset| preset.name == unique_name) {
+        unique_name = format!("{} ({})", name, number);
+        number += 1;
+    }
+    unique_name
+}
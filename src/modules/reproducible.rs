@@ -0,0 +1,51 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::path::Path;
+use sha2::{Digest, Sha256};
+use crate::modules::native_archiver::collect_entries;
+use crate::modules::package_metadata::INSTALLER_FOLDER;
+
+/// Archivers split the work between threads differently, so the thread count is fixed.
+pub const REPRODUCIBLE_THREADS: u8 = 4;
+/// The SHA-256 of every input file, in the format `sha256sum --check` reads.
+pub const INPUT_MANIFEST_FILE: &str = "input_hashes.sha256";
+
+/// Writes the SHA-256 of every file in the download which is archived into the installer folder,
+/// sorted by path, and returns the SHA-256 of the manifest itself. Identical manifests mean
+/// identical inputs.
+pub fn write_input_manifest(download_path: &Path, is_excluded: impl Fn(&str) -> bool) -> std::io::Result<String> {
+    let folder_name = download_path.file_name().unwrap().to_str().unwrap();
+    let manifest_name = format!("{}/{}/{}", folder_name, INSTALLER_FOLDER, INPUT_MANIFEST_FILE);
+    let mut manifest = String::new();
+    for entry in collect_entries(download_path, folder_name)? {
+        if entry.path.is_dir() || entry.name == manifest_name || is_excluded(&entry.name) {
+            continue;
+        }
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(&entry.path)?, &mut hasher)?;
+        let _ = writeln!(manifest, "{} *{}", to_hex(&hasher.finalize()), entry.name);
+    }
+
+    std::fs::create_dir_all(download_path.join(INSTALLER_FOLDER))?;
+    std::fs::write(download_path.join(INSTALLER_FOLDER).join(INPUT_MANIFEST_FILE), &manifest)?;
+    Ok(to_hex(&Sha256::digest(manifest.as_bytes())))
+}
+
+/// Writes the files to archive for which `include` returns true, relative to the download's parent
+/// folder and sorted by path. Only empty folders are listed, as archivers add listed folders with
+/// everything in them.
+pub fn write_file_list(download_path: &Path, file_list: &Path, include: impl Fn(&str) -> bool) -> std::io::Result<()> {
+    let folder_name = download_path.file_name().unwrap().to_str().unwrap();
+    let mut list = String::new();
+    for entry in collect_entries(download_path, folder_name)? {
+        let is_empty_folder = entry.path.read_dir().is_ok_and(|mut children| children.next().is_none());
+        if (entry.path.is_file() || is_empty_folder) && include(&entry.name) {
+            let _ = writeln!(list, "{}", entry.name);
+        }
+    }
+    std::fs::write(file_list, list)
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
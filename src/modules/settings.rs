@@ -1,84 +1,363 @@
-use crate::modules::compression::{Archiver, CompressionSettings};
-use crate::modules::compression_settings::{SevenZipSettings, WinRARSettings};
-use crate::modules::depot_downloader::{DepotDownloaderSettings, EncryptionKey};
-use eframe::egui::{ComboBox, Context, Slider, TextEdit, Ui};
+use crate::modules::archiver_detection::{DetectedArchiver, detect_archivers, supports_codec};
+use crate::modules::compression::{Archiver, CleanupPolicy, CompressionSettings};
+use crate::modules::compression_settings::{SevenZipArchiveFormat, SevenZipCompressionMethod, SizeUnit, WinRARArchiveFormat};
+use crate::modules::credentials::CredentialStore;
+use crate::modules::depot_downloader::{AccountProfile, DepotDownloaderSettings};
+use crate::modules::memory::memory_budget;
+use crate::modules::native_archiver::NativeArchiveFormat;
+use crate::modules::paths;
+use crate::modules::presets::{self, CompressionPreset};
+use crate::modules::size_estimation::SizeEstimator;
+use crate::modules::upload_destinations::UploadDestination;
+use crate::modules::settings_schema::{self, SETTINGS_VERSION};
+use eframe::egui::{Button, Checkbox, Color32, ComboBox, Context, SelectableLabel, Slider, TextEdit, Ui};
 use egui_file::FileDialog;
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
 use std::ffi::OsStr;
-use std::io::Read;
-use std::path::Path;
-use aes_gcm::{AeadCore, Aes256Gcm, Key, KeyInit};
-use aes_gcm::aead::{Aead, Nonce, OsRng};
-#[derive(Default, Deserialize, Serialize)]
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+/// How long to wait after the last edit before saving automatically.
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+/// How often `settings.json` is checked for changes made outside the creator.
+const EXTERNAL_CHANGE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize)]
 pub struct SettingsUI {
+    pub version: u32,
+    pub autosave: bool,
     pub depot_downloader_settings: DepotDownloaderSettings,
     pub compression_settings: CompressionSettings,
     #[serde(skip)]
-    pub read_settings: bool,
+    credential_store: Option<CredentialStore>,
+    #[serde(skip)]
+    credential_error: Option<String>,
+    #[serde(skip)]
+    download_root_dialog: Option<FileDialog>,
+    #[serde(skip)]
+    output_root_dialog: Option<FileDialog>,
+    /// Problems from loading, saving and importing, which are kept until the settings are reloaded.
+    #[serde(skip)]
+    problems: Vec<String>,
+    /// Problems with the current settings, checked again on every save.
+    #[serde(skip)]
+    validation_problems: Vec<String>,
+    #[serde(skip)]
+    changes: ChangeTracker,
+    #[serde(skip)]
+    size_estimator: SizeEstimator,
+}
+
+/// Tracks edits made since the settings were last loaded or saved, and edits made to
+/// `settings.json` by other programs.
+#[derive(Default)]
+struct ChangeTracker {
+    /// Hashes of everything that is saved, so the secrets are not kept in memory a second time.
+    saved_hash: u64,
+    current_hash: u64,
+    last_edit: Option<Instant>,
+    saved_modified_time: Option<SystemTime>,
+    last_external_check: Option<Instant>,
+    external_change: bool,
+}
+
+impl Default for SettingsUI {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            autosave: true,
+            depot_downloader_settings: DepotDownloaderSettings::default(),
+            compression_settings: CompressionSettings::default(),
+            credential_store: None,
+            credential_error: None,
+            download_root_dialog: None,
+            output_root_dialog: None,
+            problems: Vec::new(),
+            validation_problems: Vec::new(),
+            changes: ChangeTracker::default(),
+            size_estimator: SizeEstimator::default(),
+        }
+    }
 }
 
 impl SettingsUI {
+    /// Loads the settings when the creator starts and when they are reloaded.
     pub fn read_settings(&mut self) {
-        if self.read_settings {
-            return;
-        }
+        self.load_settings();
+        self.mark_saved();
+    }
 
-        if let Ok(mut file) = std::fs::File::open("settings.json") {
-            self.read_settings = true;
-            let settings: SettingsUI = serde_json::from_reader(&mut file).unwrap_or_default();
-            self.depot_downloader_settings = settings.depot_downloader_settings;
-            self.compression_settings = settings.compression_settings;
+    fn load_settings(&mut self) {
+        let _ = paths::migrate_to_config_dir();
+        if let Ok(file) = std::fs::read_to_string(paths::settings_file()) {
+            self.problems.clear();
+            let mut settings: Value = match serde_json::from_str(&file) {
+                Ok(settings) => settings,
+                Err(error) => {
+                    let backup = paths::settings_file().with_extension("json.bak");
+                    let _ = std::fs::copy(paths::settings_file(), &backup);
+                    self.problems.push(format!("settings.json could not be read and was ignored ({}). \
+                        A copy was saved to {}.", error, backup.display()));
+                    return;
+                }
+            };
 
-            // Decrypt username
-            if let Ok(mut file) = std::fs::File::open("key.json") {
-               self.depot_downloader_settings.encryption_key = serde_json::from_reader(&mut file).unwrap();
+            settings_schema::migrate(&mut settings, &mut self.problems);
+            self.autosave = settings.get("autosave").and_then(Value::as_bool).unwrap_or(true);
+            self.depot_downloader_settings = settings_schema::deserialize_section(
+                "depot_downloader_settings", settings.get("depot_downloader_settings").cloned(), &mut self.problems);
+            self.compression_settings = settings_schema::deserialize_section(
+                "compression_settings", settings.get("compression_settings").cloned(), &mut self.problems);
 
+            self.depot_downloader_settings.ensure_valid_profile();
+            self.validation_problems = self.validate();
+
+            // Decrypt usernames and archive passwords
+            let store = match CredentialStore::open() {
+                Ok(store) => store,
+                Err(error) => {
+                    self.credential_error = Some(error);
+                    return;
+                }
             };
 
-            let key = xor_cipher(&self.depot_downloader_settings.encryption_key.encrypted_encryption_key);
-            let nonce = Nonce::<Aes256Gcm>::from_slice(&self.depot_downloader_settings.username_nonce);
-            if key.is_empty() || nonce.is_empty() {
-                return;
+            for profile in self.depot_downloader_settings.profiles.iter_mut() {
+                if let Some(username) = store.decrypt(&profile.username_nonce, &profile.encrypted_username) {
+                    profile.username = username;
+                }
             }
 
-            let key = Key::<Aes256Gcm>::from_slice(&key);
-            let cipher = Aes256Gcm::new(&key);
+            let seven_zip_settings = &mut self.compression_settings.seven_zip_settings;
+            if let Some(password) = store.decrypt(&seven_zip_settings.password_nonce, &seven_zip_settings.encrypted_password) {
+                seven_zip_settings.password = password;
+            }
 
-            let decryption = cipher.decrypt(&nonce, self.depot_downloader_settings.encrypted_username.as_ref());
-            match decryption {
-                Ok(decrypted_username) => {
-                    self.depot_downloader_settings.username = String::from_utf8(decrypted_username).unwrap();
-                }
-                Err(_) => {}
+            let win_rar_settings = &mut self.compression_settings.win_rar_settings;
+            if let Some(password) = store.decrypt(&win_rar_settings.password_nonce, &win_rar_settings.encrypted_password) {
+                win_rar_settings.password = password;
             }
+
+            self.credential_store = Some(store);
         }
     }
 
     fn set_settings(&mut self) {
-        // let username = self.depot_downloader_settings.username.clone();
-        if self.depot_downloader_settings.remember_credentials {
-            let key = Aes256Gcm::generate_key(OsRng);
-            let nonce = Aes256Gcm::generate_nonce(OsRng);
-            let cipher = Aes256Gcm::new(&key);
-            let decrypted_username = self.depot_downloader_settings.username.clone();
-            let encrypted_username = cipher.encrypt(&nonce, decrypted_username.as_bytes());
-            self.depot_downloader_settings.encryption_key.encrypted_encryption_key = xor_cipher(&key.as_slice().to_owned().try_into().unwrap());
-            self.depot_downloader_settings.username_nonce = nonce.as_slice().to_owned().try_into().unwrap();
-            self.depot_downloader_settings.encrypted_username = encrypted_username.unwrap();
-
-            // self.depot_downloader_settings.username = String::new();
-        } else {
-            self.depot_downloader_settings.encryption_key.encrypted_encryption_key = [0; 32];
-            self.depot_downloader_settings.username_nonce = [0; 12];
-            self.depot_downloader_settings.encrypted_username = Vec::new();
-        }
-        let _ = std::fs::write("key.json", serde_json::to_string_pretty(&self.depot_downloader_settings.encryption_key).unwrap());
-        let _ = std::fs::write("settings.json", serde_json::to_string_pretty(&self).unwrap());
-        // self.depot_downloader_settings.username = username;
+        if self.credential_store.is_none() {
+            match CredentialStore::open() {
+                Ok(store) => self.credential_store = Some(store),
+                Err(error) => self.credential_error = Some(error),
+            }
+        }
+
+        // Secrets are never written in plain text. Without a keyring the ones saved before stay
+        // encrypted as they are, so they can be read again once the keyring is back
+        for profile in self.depot_downloader_settings.profiles.iter_mut() {
+            match &self.credential_store {
+                Some(store) if profile.remember_credentials => match store.encrypt(&profile.username) {
+                    Ok(encrypted) => (profile.username_nonce, profile.encrypted_username) = encrypted,
+                    Err(error) => self.problems.push(format!("The username of account profile \"{}\" could not be \
+                        encrypted ({}), so the one saved before is kept.", profile.name, error)),
+                },
+                None if profile.remember_credentials => {}
+                _ => (profile.username_nonce, profile.encrypted_username) = ([0; 12], Vec::new()),
+            }
+        }
+
+        let seven_zip_settings = &mut self.compression_settings.seven_zip_settings;
+        match &self.credential_store {
+            Some(store) if !seven_zip_settings.password.is_empty() => match store.encrypt(&seven_zip_settings.password) {
+                Ok(encrypted) => (seven_zip_settings.password_nonce, seven_zip_settings.encrypted_password) = encrypted,
+                Err(error) => self.problems.push(format!("The 7-Zip archive password could not be encrypted ({}), \
+                    so the one saved before is kept.", error)),
+            },
+            None => {}
+            _ => (seven_zip_settings.password_nonce, seven_zip_settings.encrypted_password) = ([0; 12], Vec::new()),
+        }
+
+        let win_rar_settings = &mut self.compression_settings.win_rar_settings;
+        match &self.credential_store {
+            Some(store) if !win_rar_settings.password.is_empty() => match store.encrypt(&win_rar_settings.password) {
+                Ok(encrypted) => (win_rar_settings.password_nonce, win_rar_settings.encrypted_password) = encrypted,
+                Err(error) => self.problems.push(format!("The WinRAR archive password could not be encrypted ({}), \
+                    so the one saved before is kept.", error)),
+            },
+            None => {}
+            _ => (win_rar_settings.password_nonce, win_rar_settings.encrypted_password) = ([0; 12], Vec::new()),
+        }
+
+        self.version = SETTINGS_VERSION;
+        self.validation_problems = self.validate();
+        let result = serde_json::to_string_pretty(&self).map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(paths::settings_file(), json));
+        match result {
+            Ok(_) => self.mark_saved(),
+            Err(error) => {
+                self.problems.push(format!("settings.json could not be saved ({}). The changes are not saved yet.", error));
+                // Autosave tries again after the next edit instead of every frame
+                self.changes.last_edit = None;
+            }
+        }
+    }
+
+    /// Hashes everything that is saved, including the secrets which are only saved encrypted.
+    fn settings_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(&self).unwrap().hash(&mut hasher);
+        for profile in &self.depot_downloader_settings.profiles {
+            profile.username.hash(&mut hasher);
+            profile.password.hash(&mut hasher);
+        }
+        self.compression_settings.seven_zip_settings.password.hash(&mut hasher);
+        self.compression_settings.win_rar_settings.password.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn mark_saved(&mut self) {
+        self.changes.saved_hash = self.settings_hash();
+        self.changes.current_hash = self.changes.saved_hash;
+        self.changes.last_edit = None;
+        self.changes.saved_modified_time = settings_modified_time();
+        self.changes.external_change = false;
+    }
+
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.changes.current_hash != self.changes.saved_hash
+    }
+
+    /// Called after every frame to detect edits, save them once editing has paused and notice when
+    /// `settings.json` was changed by another program.
+    pub fn track_changes(&mut self, ctx: &Context) {
+        // Settings are only edited through the UI, so frames without input cannot have changed them
+        if ctx.input(|input| !input.events.is_empty()) {
+            let hash = self.settings_hash();
+            if hash != self.changes.current_hash {
+                self.changes.current_hash = hash;
+                self.changes.last_edit = Some(Instant::now());
+                // The tab and the save status were drawn before the edit
+                ctx.request_repaint();
+            }
+        }
+
+        if self.changes.last_external_check.is_none_or(|time| time.elapsed() >= EXTERNAL_CHANGE_CHECK_INTERVAL) {
+            self.changes.last_external_check = Some(Instant::now());
+            if settings_modified_time() != self.changes.saved_modified_time {
+                self.changes.external_change = true;
+            }
+        }
+        ctx.request_repaint_after(EXTERNAL_CHANGE_CHECK_INTERVAL);
+
+        // Never overwrite changes made by another program without asking
+        if !self.autosave || self.changes.external_change || !self.has_unsaved_changes() {
+            return;
+        }
+
+        match self.changes.last_edit {
+            Some(time) if time.elapsed() >= AUTOSAVE_DELAY => self.set_settings(),
+            Some(time) => ctx.request_repaint_after(AUTOSAVE_DELAY - time.elapsed()),
+            None => {}
+        }
+    }
+
+    /// Checks for settings which can be read but cannot be used.
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let depot_downloader_settings = &self.depot_downloader_settings;
+        if depot_downloader_settings.max_servers == 0 || depot_downloader_settings.max_downloads == 0 {
+            problems.push("The number of server connections and concurrent downloads must be at least 1".to_string());
+        }
+
+        for profile in &depot_downloader_settings.profiles {
+            if profile.name.trim().is_empty() {
+                problems.push("Every account profile needs a name".to_string());
+            }
+            if let Some(app_id) = profile.app_ids.split(',').map(str::trim).find(|app_id| !app_id.is_empty() && app_id.parse::<u32>().is_err()) {
+                problems.push(format!("Account profile \"{}\" has an invalid app ID: {}", profile.name, app_id));
+            }
+        }
+
+        let compression_settings = &self.compression_settings;
+        let seven_zip_settings = &compression_settings.seven_zip_settings;
+        let win_rar_settings = &compression_settings.win_rar_settings;
+        let archiver_path = match compression_settings.archiver {
+            Archiver::SevenZip => Some(&seven_zip_settings.path),
+            Archiver::WinRAR => Some(&win_rar_settings.path),
+            Archiver::Native => None,
+        };
+        match archiver_path {
+            Some(Some(path)) if !path.is_file() => problems.push(format!("The archiver {} does not exist", path.display())),
+            Some(None) => problems.push("No archiver executable is selected".to_string()),
+            _ => {}
+        }
+
+        for (index, preset) in compression_settings.presets.iter().enumerate() {
+            if preset.name.trim().is_empty() {
+                problems.push("Every compression preset needs a name".to_string());
+            } else if compression_settings.presets[..index].iter().any(|other| other.name == preset.name) {
+                problems.push(format!("More than one compression preset is named \"{}\"", preset.name));
+            }
+        }
+
+        if compression_settings.cleanup_policy == CleanupPolicy::AfterVerifiedArchive && !compression_settings.verify_archives {
+            problems.push("Downloaded files are only removed after verifying the archive, but verification is disabled".to_string());
+        }
+
+        for destination in &compression_settings.upload_destinations {
+            if destination.name.trim().is_empty() {
+                problems.push("Every upload destination needs a name".to_string());
+            }
+            if destination.max_file_size == 0 {
+                problems.push(format!("The max file size of upload destination \"{}\" must be at least 1", destination.name));
+            }
+        }
+
+        for tracker in compression_settings.torrent_trackers.iter().map(|tracker| tracker.trim()).filter(|tracker| !tracker.is_empty()) {
+            if !["http://", "https://", "udp://"].iter().any(|scheme| tracker.starts_with(scheme)) {
+                problems.push(format!("The tracker \"{}\" must start with http://, https:// or udp://", tracker));
+            }
+        }
+
+        if ![0, 1, 3, 5, 7, 9].contains(&seven_zip_settings.compression_level) {
+            problems.push(format!("7-Zip compression level {} is not supported", seven_zip_settings.compression_level));
+        }
+        let method = seven_zip_settings.compression_method;
+        if seven_zip_settings.archive_format != SevenZipArchiveFormat::Tar && !seven_zip_settings.archive_format.supports_method(method) {
+            problems.push(format!("7-Zip cannot use {} in {} archives", method, seven_zip_settings.archive_format));
+        }
+        if let Some((_, label, range)) = method.dictionary_parameter() {
+            if !range.contains(&seven_zip_settings.dictionary_size) {
+                problems.push(format!("7-Zip {} for {} must be between {} and {}, not {}",
+                                      label.trim_end_matches(':'), method, range.start(), range.end(), seven_zip_settings.dictionary_size));
+            }
+        }
+        if let Some((_, label, range)) = method.word_parameter() {
+            if !range.contains(&seven_zip_settings.word_size) {
+                problems.push(format!("7-Zip {} for {} must be between {} and {}, not {}",
+                                      label.trim_end_matches(':'), method, range.start(), range.end(), seven_zip_settings.word_size));
+            }
+        }
+        if seven_zip_settings.solid_block_size == 0 {
+            problems.push("7-Zip solid block size must be at least 1".to_string());
+        }
+        if seven_zip_settings.number_of_cpu_threads == 0 || win_rar_settings.number_of_cpu_threads == 0 {
+            problems.push("The number of CPU threads must be at least 1".to_string());
+        }
+        if win_rar_settings.compression_level > 5 {
+            problems.push(format!("WinRAR compression level {} is not supported", win_rar_settings.compression_level));
+        }
+        if !win_rar_settings.dictionary_size.is_power_of_two() || win_rar_settings.dictionary_size > 1024 {
+            problems.push(format!("WinRAR dictionary size must be a power of 2 up to 1024 MB, not {} MB", win_rar_settings.dictionary_size));
+        }
+
+        problems
     }
 
     pub fn display(_ctx: &Context, ui: &mut Ui, settings_ui: &mut SettingsUI) {
         settings_ui.display_settings_buttons(ui);
+        settings_ui.display_problems(ui);
+        settings_ui.display_folder_settings(ui);
+        ui.separator();
         settings_ui.display_depot_downloader_settings(ui);
         ui.separator();
         settings_ui.display_compression_settings(ui);
@@ -93,27 +372,111 @@ impl SettingsUI {
             }
 
             if ui.button("Reload config").clicked() {
-                self.read_settings = false;
+                self.read_settings();
+            }
+
+            ui.checkbox(&mut self.autosave, "Save automatically");
+
+            if self.has_unsaved_changes() {
+                ui.colored_label(Color32::YELLOW, "Unsaved changes");
+            } else {
+                ui.label("All changes saved");
             }
         });
+
+        if self.changes.external_change {
+            ui.horizontal(|ui| {
+                ui.colored_label(Color32::YELLOW, "settings.json was changed by another program.");
+                if ui.button("Reload").clicked() {
+                    self.read_settings();
+                }
+                if ui.button("Keep my settings").clicked() {
+                    self.set_settings();
+                }
+            });
+        }
+    }
+
+    fn display_problems(&mut self, ui: &mut Ui) {
+        if self.problems.is_empty() && self.validation_problems.is_empty() && self.credential_error.is_none() {
+            return;
+        }
+
+        ui.separator();
+        ui.heading("Problems");
+        if let Some(error) = &self.credential_error {
+            ui.colored_label(Color32::RED, format!("The OS keyring is unavailable ({}), so usernames and archive passwords \
+                entered now are not saved. The ones saved before are kept.", error));
+        }
+        for problem in self.problems.iter().chain(&self.validation_problems) {
+            ui.colored_label(Color32::YELLOW, problem);
+        }
+    }
+
+    fn display_folder_settings(&mut self, ui: &mut Ui) {
+        ui.heading("Folders");
+        match paths::portable_dir() {
+            Some(dir) => ui.label(format!("Portable mode: settings are stored in {}", dir.display())),
+            None => ui.label(format!("Settings are stored in {}. Create {} next to the executable to use portable mode.",
+                                     paths::config_dir().display(), paths::PORTABLE_MARKER)),
+        };
+
+        display_folder_picker(ui, "Download folder:", &mut self.depot_downloader_settings.download_root, &mut self.download_root_dialog);
+        display_folder_picker(ui, "Output folder:", &mut self.compression_settings.output_root, &mut self.output_root_dialog);
     }
 
     fn display_depot_downloader_settings(&mut self, ui: &mut Ui) {
         ui.heading("Steam Depot Downloader Settings");
+        let settings = &mut self.depot_downloader_settings;
+        ui.horizontal(|ui| {
+            ui.label("Account profile:");
+            ComboBox::from_id_source("Settings Account Profile").selected_text(settings.profile().name.clone())
+                .show_ui(ui, |ui| {
+                    for (index, profile) in settings.profiles.iter().enumerate() {
+                        ui.selectable_value(&mut settings.selected_profile, index, profile.name.as_str());
+                    }
+                });
+
+            if ui.button("Add profile").clicked() {
+                settings.profiles.push(AccountProfile {
+                    name: format!("Profile {}", settings.profiles.len() + 1),
+                    ..Default::default()
+                });
+                settings.selected_profile = settings.profiles.len() - 1;
+            }
+
+            if ui.add_enabled(settings.profiles.len() > 1, Button::new("Remove profile")).clicked() {
+                settings.profiles.remove(settings.selected_profile);
+                settings.selected_profile = settings.selected_profile.saturating_sub(1);
+            }
+        });
+
+        let profile = settings.profile_mut();
+        ui.horizontal(|ui| {
+            ui.label("Profile name:");
+            ui.text_edit_singleline(&mut profile.name);
+        });
         ui.horizontal(|ui| {
             ui.label("Username:");
-            ui.text_edit_singleline(&mut self.depot_downloader_settings.username);
+            ui.text_edit_singleline(&mut profile.username);
         });
         ui.horizontal(|ui| {
             ui.label("Password:");
-            ui.add(TextEdit::singleline(&mut self.depot_downloader_settings.password)
+            ui.add(TextEdit::singleline(&mut profile.password)
                 .password(true));
         });
 
-        ui.checkbox(&mut self.depot_downloader_settings.remember_credentials,
+        ui.checkbox(&mut profile.remember_credentials,
                     "Remember credentials (Requires login with Depot Downloader at least once. \
                     Subsequent logins require the username only.)");
 
+        ui.horizontal(|ui| {
+            ui.label("Use for app IDs:");
+            ui.add(TextEdit::singleline(&mut profile.app_ids).hint_text("e.g. 730, 570"));
+        });
+
+        ui.checkbox(&mut settings.auto_select_profile, "Automatically select the profile by app ID");
+
         ui.horizontal(|ui| {
             ui.label("Max number of server connections:");
             ui.add(Slider::new(&mut self.depot_downloader_settings.max_servers, 1..=32));
@@ -133,6 +496,7 @@ impl SettingsUI {
                 .show_ui(ui, |ui| {
                     ui.selectable_value(&mut self.compression_settings.archiver, Archiver::SevenZip, "7-zip");
                     ui.selectable_value(&mut self.compression_settings.archiver, Archiver::WinRAR, "WinRAR");
+                    ui.selectable_value(&mut self.compression_settings.archiver, Archiver::Native, "Built-in");
                 });
         });
 
@@ -141,6 +505,8 @@ impl SettingsUI {
                 let path = match self.compression_settings.archiver {
                     Archiver::SevenZip => &self.compression_settings.seven_zip_settings.path,
                     Archiver::WinRAR => &self.compression_settings.win_rar_settings.path,
+                    // The built-in archiver has no executable
+                    Archiver::Native => return,
                 };
 
                 match &path {
@@ -149,10 +515,10 @@ impl SettingsUI {
                 };
 
                 if ui.button("Change path").clicked() {
-                    // Show only files with the extension "json".
+                    // Show only files with the extension "exe" on Windows
                     let filter = Box::new({
                         let ext = Some(OsStr::new("exe"));
-                        move |path: &Path| -> bool { path.extension() == ext }
+                        move |path: &Path| -> bool { path.extension() == ext || cfg!(not(windows)) }
                     });
                     let mut dialog = FileDialog::open_file(path.clone()).show_files_filter(filter);
                     dialog.open();
@@ -169,12 +535,41 @@ impl SettingsUI {
                                 Archiver::WinRAR => {
                                     self.compression_settings.win_rar_settings.path = Some(file.to_path_buf()).clone();
                                 }
+                                Archiver::Native => {}
                             }
                         }
                     }
                 }
             });
         });
+        if self.compression_settings.archiver != Archiver::Native {
+            self.display_detected_archivers(ui);
+            self.display_presets(ui);
+        }
+        ui.checkbox(&mut self.compression_settings.verify_archives, "Verify archives after compressing");
+        ui.checkbox(&mut self.compression_settings.reproducible, "Reproducible archives")
+            .on_hover_text("Adds the files in a fixed order without their timestamps, uses a fixed thread count and writes a hash of every \
+                            input file into the package, so identical downloads give identical archives. Encrypted archives \
+                            still differ, as every archive gets a random salt.");
+        ui.checkbox(&mut self.compression_settings.content_aware, "Store already compressed files")
+            .on_hover_text("Samples every file type and adds types which are compressed already, such as videos and \
+                            audio, without compressing them again. The other files are grouped by type.");
+        ui.horizontal(|ui| {
+            ui.label("Warn when compressing needs more than this share of the available memory:");
+            ui.add(Slider::new(&mut self.compression_settings.memory_warning_percentage, 10..=100).suffix("%"));
+        });
+        ui.horizontal(|ui| {
+            ui.label("Remove downloaded files:");
+            ComboBox::from_id_source("Cleanup Policy").selected_text(self.compression_settings.cleanup_policy.to_string())
+                .show_ui(ui, |ui| {
+                    for policy in [CleanupPolicy::Never, CleanupPolicy::AfterVerifiedArchive] {
+                        ui.selectable_value(&mut self.compression_settings.cleanup_policy, policy, policy.to_string());
+                    }
+                });
+        });
+        self.display_upload_destinations(ui);
+        self.display_torrent_settings(ui);
+
         match self.compression_settings.archiver {
             Archiver::SevenZip => {
                 self.display_7zip_settings(ui);
@@ -182,92 +577,380 @@ impl SettingsUI {
             Archiver::WinRAR => {
                 self.display_winrar_settings(ui);
             }
+            Archiver::Native => {
+                self.display_native_settings(ui);
+            }
         }
     }
 
-    fn display_7zip_settings(&mut self, ui: &mut Ui) {
-        ui.horizontal(|ui| {
-            ui.label("Password");
-            ui.text_edit_singleline(&mut self.compression_settings.seven_zip_settings.password);
+    fn display_torrent_settings(&mut self, ui: &mut Ui) {
+        let compression_settings = &mut self.compression_settings;
+        ui.checkbox(&mut compression_settings.create_torrent, "Create a .torrent for every archive")
+            .on_hover_text("Builds a hybrid BitTorrent v1 and v2 torrent of the archive, or of the folder of volumes, \
+                            in the output folder");
+        if compression_settings.create_torrent {
+            ui.label("Trackers, one per line:");
+            // Splitting on every line break keeps empty lines, so a new line can be typed
+            let mut trackers = compression_settings.torrent_trackers.join("\n");
+            if ui.add(TextEdit::multiline(&mut trackers).desired_rows(3)).changed() {
+                compression_settings.torrent_trackers = trackers.split('\n').map(str::to_string).collect();
+            }
+        }
+    }
+
+    fn display_detected_archivers(&mut self, ui: &mut Ui) {
+        let compression_settings = &mut self.compression_settings;
+        ui.collapsing("Detected archivers", |ui| {
+            // Detecting runs every archiver to read its version, so only do it when needed
+            if ui.button("Detect again").clicked() || compression_settings.detected_archivers.is_none() {
+                compression_settings.detected_archivers = Some(detect_archivers());
+            }
+
+            let archiver = compression_settings.archiver.clone();
+            let detected_archivers: Vec<&DetectedArchiver> = compression_settings.detected_archivers.iter().flatten()
+                .filter(|detected| detected.archiver == archiver)
+                .collect();
+            if detected_archivers.is_empty() {
+                ui.label(format!("{} was not found in the registry, PATH or common install locations.", archiver));
+            }
+
+            for detected in detected_archivers {
+                ui.horizontal(|ui| {
+                    if ui.add_enabled(detected.can_compress, Button::new("Use")).clicked() {
+                        match archiver {
+                            Archiver::SevenZip => compression_settings.seven_zip_settings.path = Some(detected.path.clone()),
+                            Archiver::WinRAR => compression_settings.win_rar_settings.path = Some(detected.path.clone()),
+                            Archiver::Native => {}
+                        }
+                    }
+                    ui.label(format!("{} ({}, found in {}){}",
+                                     detected.path.display(),
+                                     detected.version.as_deref().map_or("unknown version".to_string(), |version| format!("version {}", version)),
+                                     detected.source,
+                                     if detected.can_compress { "" } else { " - can only test archives" }));
+                });
+            }
+        });
+    }
+
+    fn display_upload_destinations(&mut self, ui: &mut Ui) {
+        let compression_settings = &mut self.compression_settings;
+        ui.collapsing("Upload destinations", |ui| {
+            ui.label("Archives are split to fit the largest file the destination chosen for an update accepts.");
+            ui.horizontal(|ui| {
+                ui.label("Destination:");
+                let selected_text = compression_settings.upload_destinations.get(compression_settings.selected_destination)
+                    .map_or("None".to_string(), |destination| destination.name.clone());
+                ComboBox::from_id_source("Settings Upload Destination").selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        for (index, destination) in compression_settings.upload_destinations.iter().enumerate() {
+                            ui.selectable_value(&mut compression_settings.selected_destination, index, destination.name.as_str());
+                        }
+                    });
+
+                if ui.button("Add destination").clicked() {
+                    compression_settings.upload_destinations.push(UploadDestination {
+                        name: format!("Destination {}", compression_settings.upload_destinations.len() + 1),
+                        ..Default::default()
+                    });
+                    compression_settings.selected_destination = compression_settings.upload_destinations.len() - 1;
+                }
+
+                let selected_destination = compression_settings.selected_destination;
+                if ui.add_enabled(selected_destination < compression_settings.upload_destinations.len(), Button::new("Remove destination")).clicked() {
+                    compression_settings.upload_destinations.remove(selected_destination);
+                    compression_settings.selected_destination = selected_destination.saturating_sub(1);
+                }
+            });
+
+            let Some(destination) = compression_settings.upload_destinations.get_mut(compression_settings.selected_destination) else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label("Destination name:");
+                ui.text_edit_singleline(&mut destination.name);
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max file size:");
+                match destination.max_file_size_unit {
+                    SizeUnit::Gigabytes => ui.add(Slider::new(&mut destination.max_file_size, 1..=100)),
+                    _ => ui.add(Slider::new(&mut destination.max_file_size, 1..=10000))
+                };
+                ComboBox::from_id_source("Max File Size Unit").selected_text(destination.max_file_size_unit.to_string())
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut destination.max_file_size_unit, SizeUnit::Megabytes, "MB");
+                        ui.selectable_value(&mut destination.max_file_size_unit, SizeUnit::Gigabytes, "GB");
+                    });
+            });
+        });
+    }
+
+    fn display_presets(&mut self, ui: &mut Ui) {
+        let compression_settings = &mut self.compression_settings;
+        let problems = &mut self.problems;
+        ui.collapsing("Presets", |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Preset:");
+                let selected_text = compression_settings.presets.get(compression_settings.selected_preset)
+                    .map_or("None".to_string(), |preset| preset.name.clone());
+                ComboBox::from_id_source("Compression Preset").selected_text(selected_text)
+                    .show_ui(ui, |ui| {
+                        for (index, preset) in compression_settings.presets.iter().enumerate() {
+                            ui.selectable_value(&mut compression_settings.selected_preset, index, preset.name.as_str());
+                        }
+                    });
+
+                if ui.button("Save as new preset").clicked() {
+                    let name = presets::unique_name("New preset", &compression_settings.presets);
+                    compression_settings.presets.push(CompressionPreset::new(
+                        name, &compression_settings.seven_zip_settings, &compression_settings.win_rar_settings));
+                    compression_settings.selected_preset = compression_settings.presets.len() - 1;
+                }
+
+                if ui.button("Import").clicked() {
+                    let mut dialog = FileDialog::open_file(None).show_files_filter(Box::new(is_json_file));
+                    dialog.open();
+                    compression_settings.preset_import_dialog = Some(dialog);
+                }
+            });
+
+            if let Some(dialog) = &mut compression_settings.preset_import_dialog {
+                if dialog.show(ui.ctx()).selected() {
+                    if let Some(file) = dialog.path() {
+                        match CompressionPreset::import(file, &compression_settings.presets) {
+                            Ok(preset) => {
+                                compression_settings.presets.push(preset);
+                                compression_settings.selected_preset = compression_settings.presets.len() - 1;
+                            }
+                            Err(error) => problems.push(format!("The preset {} could not be imported: {}", file.display(), error)),
+                        }
+                    }
+                }
+            }
+
+            let selected_preset = compression_settings.selected_preset;
+            let Some(preset) = compression_settings.presets.get_mut(selected_preset) else {
+                return;
+            };
+            ui.horizontal(|ui| {
+                ui.label("Preset name:");
+                ui.text_edit_singleline(&mut preset.name);
+            });
+
+            let mut remove = false;
+            ui.horizontal(|ui| {
+                if ui.button("Apply").clicked() {
+                    preset.apply(&mut compression_settings.seven_zip_settings, &mut compression_settings.win_rar_settings);
+                }
+
+                if ui.button("Update from current settings").clicked() {
+                    *preset = CompressionPreset::new(preset.name.clone(),
+                                                     &compression_settings.seven_zip_settings, &compression_settings.win_rar_settings);
+                }
+
+                if ui.button("Export").clicked() {
+                    let file = paths::config_dir().join(format!("{}.json", preset.name));
+                    let mut dialog = FileDialog::save_file(Some(file)).show_files_filter(Box::new(is_json_file));
+                    dialog.open();
+                    compression_settings.preset_export_dialog = Some(dialog);
+                }
+
+                remove = ui.button("Remove").clicked();
+            });
+            if remove {
+                compression_settings.presets.remove(selected_preset);
+                compression_settings.selected_preset = selected_preset.saturating_sub(1);
+                return;
+            }
+
+            if let Some(dialog) = &mut compression_settings.preset_export_dialog {
+                if dialog.show(ui.ctx()).selected() {
+                    if let (Some(file), Some(preset)) = (dialog.path(), compression_settings.presets.get(selected_preset)) {
+                        if let Err(error) = preset.export(file) {
+                            problems.push(format!("The preset could not be exported to {}: {}", file.display(), error));
+                        }
+                    }
+                }
+            }
         });
+    }
 
+    fn display_native_settings(&mut self, ui: &mut Ui) {
+        let native_settings = &mut self.compression_settings.native_settings;
         ui.horizontal(|ui| {
             ui.label("Archive format:");
-            ComboBox::from_id_source("Format").selected_text(self.compression_settings.seven_zip_settings.archive_format.to_string())
+            ComboBox::from_id_source("Format").selected_text(native_settings.archive_format.to_string())
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.archive_format, "7z".to_string(), "7z");
+                    ui.selectable_value(&mut native_settings.archive_format, NativeArchiveFormat::Zip, "zip");
+                    ui.selectable_value(&mut native_settings.archive_format, NativeArchiveFormat::TarZstd, "tar.zst");
+                    ui.selectable_value(&mut native_settings.archive_format, NativeArchiveFormat::SevenZip, "7z");
                 });
         });
 
         ui.horizontal(|ui| {
             ui.label("Compression level:");
-            ComboBox::from_id_source("Compression Level").selected_text(format!("{}", self.compression_settings.seven_zip_settings.compression_level))
-                .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_level, 0, "0 - Store");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_level, 1, "1 - Fastest");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_level, 3, "3 - Fast");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_level, 5, "5 - Normal");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_level, 7, "7 - Maximum");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_level, 9, "9 - Ultra");
-                });
+            ui.add(Slider::new(&mut native_settings.compression_level, 0..=9));
         });
 
         ui.horizontal(|ui| {
-            ui.label("Compression method:");
-            ComboBox::from_id_source("Compression Method").selected_text(self.compression_settings.seven_zip_settings.compression_method.to_string())
+            ui.label("CPU Threads:");
+            let max_cpu_threads = std::thread::available_parallelism().unwrap().get() as u8;
+            ui.add_enabled(native_settings.archive_format == NativeArchiveFormat::TarZstd,
+                           Slider::new(&mut native_settings.number_of_cpu_threads, 1..=max_cpu_threads));
+            if native_settings.archive_format != NativeArchiveFormat::TarZstd {
+                ui.label("zip and 7z are compressed on one thread. Use tar.zst to compress on more.");
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Split size:");
+            match native_settings.split_size_unit {
+                SizeUnit::Gigabytes => ui.add(Slider::new(&mut native_settings.split_size, 0..=100)),
+                _ => ui.add(Slider::new(&mut native_settings.split_size, 0..=10000))
+            };
+            ComboBox::from_id_source("Split Size Unit").selected_text(native_settings.split_size_unit.to_string())
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.compression_method, "LZMA2".to_string(), "7ZMA2");
+                    ui.selectable_value(&mut native_settings.split_size_unit, SizeUnit::Megabytes, "MB");
+                    ui.selectable_value(&mut native_settings.split_size_unit, SizeUnit::Gigabytes, "GB");
                 });
         });
 
+        let memory = self.compression_settings.native_settings.memory_usage();
+        let budget = memory_budget(self.compression_settings.memory_warning_percentage);
+        if display_memory_usage(ui, memory, budget) && !self.compression_settings.native_settings.fit_to_memory(budget) {
+            self.problems.push("The built-in archiver's settings cannot be lowered enough to fit into the available memory".to_string());
+        }
+        self.size_estimator.display(ui, &self.compression_settings.download_path, self.compression_settings.native_settings.sample_codec());
+    }
+
+    fn display_7zip_settings(&mut self, ui: &mut Ui) {
         ui.horizontal(|ui| {
-            ui.label("Dictionary size (MB):");
-            ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.dictionary_size, 1..=2048));
+            ui.label("Password");
+            ui.text_edit_singleline(&mut self.compression_settings.seven_zip_settings.password);
         });
+        // Only 7z archives can encrypt their headers
+        let seven_zip_format = self.compression_settings.seven_zip_settings.archive_format == SevenZipArchiveFormat::SevenZip;
+        ui.add_enabled(seven_zip_format, Checkbox::new(&mut self.compression_settings.seven_zip_settings.hide_file_names,
+                                                       "Hide file names (encrypt headers)"));
+
+        // Running 7-Zip to list its codecs is slow, so only do it when the executable changes
+        let seven_zip_path = self.compression_settings.seven_zip_settings.path.clone();
+        let zstd_supported = match (&self.compression_settings.seven_zip_zstd_support, &seven_zip_path) {
+            (Some((checked_path, supported)), Some(path)) if checked_path == path => *supported,
+            (_, Some(path)) => {
+                let supported = supports_codec(path, "zstd");
+                self.compression_settings.seven_zip_zstd_support = Some((path.clone(), supported));
+                supported
+            }
+            (_, None) => false,
+        };
 
+        let seven_zip_settings = &mut self.compression_settings.seven_zip_settings;
         ui.horizontal(|ui| {
-            ui.label("Word size:");
-            ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.word_size, 5..=273));
+            ui.label("Archive format:");
+            ComboBox::from_id_source("Format").selected_text(seven_zip_settings.archive_format.to_string())
+                .show_ui(ui, |ui| {
+                    for format in SevenZipArchiveFormat::ALL {
+                        ui.selectable_value(&mut seven_zip_settings.archive_format, format, format.to_string());
+                    }
+                });
         });
+        if !seven_zip_settings.supports_password() {
+            ui.label("The password is not used, as this format cannot be encrypted.");
+        }
 
         ui.horizontal(|ui| {
-            ui.label("Solid block size:");
-            match self.compression_settings.seven_zip_settings.solid_block_size_unit.as_str() {
-                "g" => ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.solid_block_size, 1..=100)),
-                _ => ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.solid_block_size, 1..=10000))
-            };
-            ComboBox::from_id_source("Solid Block Size Unit").selected_text(format!("{}B", self.compression_settings.seven_zip_settings.solid_block_size_unit.to_uppercase()))
+            ui.label("Compression level:");
+            ComboBox::from_id_source("Compression Level").selected_text(format!("{}", seven_zip_settings.compression_level))
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.solid_block_size_unit, "m".to_string(), "MB");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.solid_block_size_unit, "g".to_string(), "GB");
+                    ui.selectable_value(&mut seven_zip_settings.compression_level, 0, "0 - Store");
+                    ui.selectable_value(&mut seven_zip_settings.compression_level, 1, "1 - Fastest");
+                    ui.selectable_value(&mut seven_zip_settings.compression_level, 3, "3 - Fast");
+                    ui.selectable_value(&mut seven_zip_settings.compression_level, 5, "5 - Normal");
+                    ui.selectable_value(&mut seven_zip_settings.compression_level, 7, "7 - Maximum");
+                    ui.selectable_value(&mut seven_zip_settings.compression_level, 9, "9 - Ultra");
                 });
         });
 
+        // tar only stores the files
+        if seven_zip_settings.archive_format != SevenZipArchiveFormat::Tar {
+            ui.horizontal(|ui| {
+                ui.label("Compression method:");
+                ComboBox::from_id_source("Compression Method").selected_text(seven_zip_settings.compression_method.to_string())
+                    .show_ui(ui, |ui| {
+                        for method in SevenZipCompressionMethod::ALL {
+                            if !seven_zip_settings.archive_format.supports_method(method) {
+                                continue;
+                            }
+                            let enabled = method != SevenZipCompressionMethod::Zstd || zstd_supported;
+                            let selected = seven_zip_settings.compression_method == method;
+                            if ui.add_enabled(enabled, SelectableLabel::new(selected, method.to_string()))
+                                .on_disabled_hover_text("This 7-Zip build does not include the zstd codec")
+                                .clicked() {
+                                seven_zip_settings.compression_method = method;
+                            }
+                        }
+                    });
+            });
+        }
+        seven_zip_settings.fit_to_method();
+
+        let method = seven_zip_settings.compression_method;
+        if let Some((_, label, range)) = method.dictionary_parameter() {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                ui.add(Slider::new(&mut seven_zip_settings.dictionary_size, range));
+            });
+        }
+
+        if let Some((_, label, range)) = method.word_parameter() {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                ui.add(Slider::new(&mut seven_zip_settings.word_size, range));
+            });
+        }
+
+        // Only 7z archives are solid
+        if seven_zip_settings.archive_format == SevenZipArchiveFormat::SevenZip {
+            ui.horizontal(|ui| {
+                ui.label("Solid block size:");
+                match seven_zip_settings.solid_block_size_unit {
+                    SizeUnit::Gigabytes => ui.add(Slider::new(&mut seven_zip_settings.solid_block_size, 1..=100)),
+                    _ => ui.add(Slider::new(&mut seven_zip_settings.solid_block_size, 1..=10000))
+                };
+                ComboBox::from_id_source("Solid Block Size Unit").selected_text(seven_zip_settings.solid_block_size_unit.to_string())
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut seven_zip_settings.solid_block_size_unit, SizeUnit::Megabytes, "MB");
+                        ui.selectable_value(&mut seven_zip_settings.solid_block_size_unit, SizeUnit::Gigabytes, "GB");
+                    });
+            });
+        }
+
         ui.horizontal(|ui| {
             ui.label("CPU Threads:");
-            let max_cpu_threads = std::thread::available_parallelism().unwrap().get() as u8;
-            ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.number_of_cpu_threads, 1..=max_cpu_threads));
+            let max_cpu_threads = (std::thread::available_parallelism().unwrap().get() as u8).min(method.max_threads());
+            ui.add(Slider::new(&mut seven_zip_settings.number_of_cpu_threads, 1..=max_cpu_threads));
         });
 
         ui.horizontal(|ui| {
             ui.label("Split size:");
-            match self.compression_settings.seven_zip_settings.split_size_unit.as_str() {
-                "g" => ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.split_size, 0..=100)),
+            match self.compression_settings.seven_zip_settings.split_size_unit {
+                SizeUnit::Gigabytes => ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.split_size, 0..=100)),
                 _ => ui.add(Slider::new(&mut self.compression_settings.seven_zip_settings.split_size, 0..=10000))
             };
-            ComboBox::from_id_source("Split Size Unit").selected_text(format!("{}B", self.compression_settings.seven_zip_settings.split_size_unit.to_uppercase()))
+            ComboBox::from_id_source("Split Size Unit").selected_text(self.compression_settings.seven_zip_settings.split_size_unit.to_string())
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.split_size_unit, "m".to_string(), "MB");
-                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.split_size_unit, "g".to_string(), "GB");
+                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.split_size_unit, SizeUnit::Megabytes, "MB");
+                    ui.selectable_value(&mut self.compression_settings.seven_zip_settings.split_size_unit, SizeUnit::Gigabytes, "GB");
                 });
         });
 
-        let memory = calculate_7zip_memory_usage(&self.compression_settings.seven_zip_settings);
-
-        ui.horizontal(|ui| {
-            ui.label(format!("Memory usage for Compressing: {} MB.", memory.0));
-            ui.label(format!("Memory usage for Decompressing: {} MB.", memory.1));
-        });
+        let memory = self.compression_settings.seven_zip_settings.memory_usage();
+        let budget = memory_budget(self.compression_settings.memory_warning_percentage);
+        if display_memory_usage(ui, memory, budget) && !self.compression_settings.seven_zip_settings.fit_to_memory(budget) {
+            self.problems.push("The 7-Zip settings cannot be lowered enough to fit into the available memory".to_string());
+        }
+        self.size_estimator.display(ui, &self.compression_settings.download_path, self.compression_settings.seven_zip_settings.sample_codec());
     }
 
     fn display_winrar_settings(&mut self, ui: &mut Ui) {
@@ -275,12 +958,13 @@ impl SettingsUI {
             ui.label("Password");
             ui.text_edit_singleline(&mut self.compression_settings.win_rar_settings.password);
         });
+        ui.checkbox(&mut self.compression_settings.win_rar_settings.hide_file_names, "Hide file names (encrypt headers)");
 
         ui.horizontal(|ui| {
             ui.label("Archive format:");
             ComboBox::from_id_source("Format").selected_text(self.compression_settings.win_rar_settings.archive_format.to_string())
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.win_rar_settings.archive_format, "rar".to_string(), "RAR");
+                    ui.selectable_value(&mut self.compression_settings.win_rar_settings.archive_format, WinRARArchiveFormat::Rar, "RAR");
                 });
         });
 
@@ -314,23 +998,23 @@ impl SettingsUI {
 
         ui.horizontal(|ui| {
             ui.label("Split size:");
-            match self.compression_settings.win_rar_settings.split_size_unit.as_str() {
-                "g" => ui.add(Slider::new(&mut self.compression_settings.win_rar_settings.split_size, 0..=100)),
+            match self.compression_settings.win_rar_settings.split_size_unit {
+                SizeUnit::Gigabytes => ui.add(Slider::new(&mut self.compression_settings.win_rar_settings.split_size, 0..=100)),
                 _ => ui.add(Slider::new(&mut self.compression_settings.win_rar_settings.split_size, 0..=10000))
             };
-            ComboBox::from_id_source("Split Size Unit").selected_text(format!("{}B", self.compression_settings.win_rar_settings.split_size_unit.to_uppercase()))
+            ComboBox::from_id_source("Split Size Unit").selected_text(self.compression_settings.win_rar_settings.split_size_unit.to_string())
                 .show_ui(ui, |ui| {
-                    ui.selectable_value(&mut self.compression_settings.win_rar_settings.split_size_unit, "m".to_string(), "MB");
-                    ui.selectable_value(&mut self.compression_settings.win_rar_settings.split_size_unit, "g".to_string(), "GB");
+                    ui.selectable_value(&mut self.compression_settings.win_rar_settings.split_size_unit, SizeUnit::Megabytes, "MB");
+                    ui.selectable_value(&mut self.compression_settings.win_rar_settings.split_size_unit, SizeUnit::Gigabytes, "GB");
                 });
         });
 
-        let memory = calculate_winrar_memory_usage(&self.compression_settings.win_rar_settings);
-
-        ui.horizontal(|ui| {
-            ui.label(format!("Memory usage for Compressing: {} MB.", memory.0));
-            ui.label(format!("Memory usage for Decompressing: {} MB.", memory.1));
-        });
+        let memory = self.compression_settings.win_rar_settings.memory_usage();
+        let budget = memory_budget(self.compression_settings.memory_warning_percentage);
+        if display_memory_usage(ui, memory, budget) && !self.compression_settings.win_rar_settings.fit_to_memory(budget) {
+            self.problems.push("The WinRAR settings cannot be lowered enough to fit into the available memory".to_string());
+        }
+        self.size_estimator.display(ui, &self.compression_settings.download_path, self.compression_settings.win_rar_settings.sample_codec());
     }
 
     fn display_multiup_direct_settings(&mut self, ui: &mut Ui) {
@@ -365,82 +1049,46 @@ impl SettingsUI {
     }
 }
 
-fn xor_cipher(key: &[u8; 32]) -> [u8; 32] {
-    let secret_key = "red-alt-steam-up-creator-xor-key".as_bytes();
-    let mut output = [0; 32];
-    for i in 0..32 {
-        output[i] = key[i] ^ secret_key[i];
-    }
-    output
+fn settings_modified_time() -> Option<SystemTime> {
+    std::fs::metadata(paths::settings_file()).and_then(|metadata| metadata.modified()).ok()
 }
 
-fn calculate_7zip_memory_usage(settings: &SevenZipSettings) -> (u128, u128) {
-    if settings.compression_level == 0 {
-        return (1, 1);
-    }
-
-    let bytes_ratio = 1024 * 1024;
-    // Convert the dictionary size to bytes
-    let dictionary_size = settings.dictionary_size as u128 * bytes_ratio;
-
-    let mut size = 0;
+/// Shows the memory needed to compress and decompress, with a warning and an auto-fit button when
+/// compressing needs more than the budget. Returns whether auto-fit was clicked.
+fn display_memory_usage(ui: &mut Ui, memory: (u64, u64), budget: u64) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(format!("Memory usage for Compressing: {} MB.", memory.0));
+        ui.label(format!("Memory usage for Decompressing: {} MB.", memory.1));
+    });
 
-    if settings.archive_format == "7z" && settings.compression_level == 9 {
-        size += 29 * bytes_ratio;
-    }
-
-    let mut hs = dictionary_size - 1;
-    hs |= hs >> 1;
-    hs |= hs >> 2;
-    hs |= hs >> 4;
-    hs |= hs >> 8;
-    hs >>= 1;
-    hs |= 0xFFFF;
-    if hs > (1 << 24) {
-        hs >>= 1;
-    }
-    hs += 1;
-
-    let mut size_1 = hs * 4;
-    size_1 += dictionary_size * 4;
-    if settings.compression_level >= 5 {
-        size_1 += dictionary_size * 4;
-    }
-    size_1 += 2 * bytes_ratio;
-
-    let mut num_threads_1 = 1;
-    if settings.number_of_cpu_threads > 1 && settings.compression_level >= 5 {
-        size_1 += (2 * bytes_ratio) + (4 * bytes_ratio);
-        num_threads_1 = 2;
+    if memory.0 <= budget {
+        return false;
     }
+    ui.horizontal(|ui| {
+        ui.colored_label(Color32::YELLOW, format!("Compressing needs more than the {} MB of memory it may use.", budget));
+        ui.button("Auto-fit").on_hover_text("Lowers the CPU threads, then the dictionary size, until compressing fits").clicked()
+    }).inner
+}
 
-    let num_block_threads = settings.number_of_cpu_threads / num_threads_1;
+fn is_json_file(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("json"))
+}
 
-    if num_block_threads == 1 {
-        size_1 += (dictionary_size * 3) / 2;
-    } else {
-        let mut chunk_size = dictionary_size * 4;
-        chunk_size = chunk_size.max(bytes_ratio);
-        chunk_size = chunk_size.min(256 * bytes_ratio);
-        chunk_size = chunk_size.max(dictionary_size);
-        size_1 += chunk_size * 2;
-    }
+fn display_folder_picker(ui: &mut Ui, label: &str, path: &mut PathBuf, dialog: &mut Option<FileDialog>) {
+    ui.horizontal(|ui| {
+        ui.label(format!("{} {}", label, path.display()));
+        if ui.button("Change folder").clicked() {
+            let mut folder_dialog = FileDialog::select_folder(Some(path.clone()));
+            folder_dialog.open();
+            *dialog = Some(folder_dialog);
+        }
+    });
 
-    // calculate the solid block size
-    let mut solid_block_size = dictionary_size;
-    if settings.compression_level == 9 {
-        solid_block_size *= num_block_threads as u128;
+    if let Some(folder_dialog) = dialog {
+        if folder_dialog.show(ui.ctx()).selected() {
+            if let Some(folder) = folder_dialog.path() {
+                *path = folder.to_path_buf();
+            }
+        }
     }
-
-    size += size_1 * num_block_threads as u128 + solid_block_size;
-
-    // Return the size for compression and decompression in MB
-    (
-        (size + bytes_ratio - 1) / bytes_ratio,
-        (dictionary_size + (2 * bytes_ratio) + bytes_ratio - 1) / bytes_ratio,
-    )
-}
-
-fn calculate_winrar_memory_usage(settings: &WinRARSettings) -> (u16, u16) {
-    (settings.dictionary_size * 6, settings.dictionary_size)
 }
@@ -0,0 +1,241 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// The version written to `settings.json`. Files without a version were written by v1.2.1 and older.
+pub const SETTINGS_VERSION: u32 = 2;
+
+/// Upgrades the settings to the current version, one version at a time.
+pub fn migrate(settings: &mut Value, problems: &mut Vec<String>) {
+    let mut version = settings.get("version").and_then(Value::as_u64).unwrap_or(1) as u32;
+    if version > SETTINGS_VERSION {
+        problems.push(format!("settings.json was written by a newer version (settings version {}). \
+            Unknown settings will be lost when saving.", version));
+        return;
+    }
+
+    while version < SETTINGS_VERSION {
+        match version {
+            1 => migrate_v1_to_v2(settings),
+            _ => {
+                problems.push(format!("settings.json has an unknown settings version ({}) and was reset to the defaults.", version));
+                *settings = Value::Object(Map::new());
+                break;
+            }
+        }
+        version += 1;
+    }
+    if let Some(settings) = settings.as_object_mut() {
+        settings.insert("version".to_string(), Value::from(SETTINGS_VERSION));
+    }
+}
+
+/// Version 2 moved the single Steam account into account profiles and replaced the strings used
+/// for units, formats and methods with enums.
+fn migrate_v1_to_v2(settings: &mut Value) {
+    if let Some(depot_downloader_settings) = settings.get_mut("depot_downloader_settings").and_then(Value::as_object_mut) {
+        let encrypted_username = depot_downloader_settings.remove("encrypted_username");
+        let username_nonce = depot_downloader_settings.remove("username_nonce");
+        let remember_credentials = depot_downloader_settings.remove("remember_credentials");
+        if let (Some(encrypted_username), Some(username_nonce)) = (encrypted_username, username_nonce) {
+            let has_username = encrypted_username.as_array().is_some_and(|username| !username.is_empty());
+            let profiles = depot_downloader_settings.entry("profiles").or_insert_with(|| Value::Array(Vec::new()));
+            if let (true, Some(profiles)) = (has_username, profiles.as_array_mut()) {
+                let mut profile = Map::new();
+                profile.insert("name".to_string(), Value::from("Default"));
+                profile.insert("encrypted_username".to_string(), encrypted_username);
+                profile.insert("username_nonce".to_string(), username_nonce);
+                profile.insert("remember_credentials".to_string(), remember_credentials.unwrap_or(Value::Bool(true)));
+                profiles.insert(0, Value::Object(profile));
+            }
+        }
+    }
+
+    let units = [("m", "Megabytes"), ("g", "Gigabytes")];
+    let seven_zip_formats = [("7z", "SevenZip")];
+    let seven_zip_methods = [("LZMA2", "Lzma2")];
+    let win_rar_formats = [("rar", "Rar")];
+    if let Some(seven_zip_settings) = settings.pointer_mut("/compression_settings/seven_zip_settings") {
+        rename_value(seven_zip_settings, "solid_block_size_unit", &units);
+        rename_value(seven_zip_settings, "split_size_unit", &units);
+        rename_value(seven_zip_settings, "archive_format", &seven_zip_formats);
+        rename_value(seven_zip_settings, "compression_method", &seven_zip_methods);
+    }
+    if let Some(win_rar_settings) = settings.pointer_mut("/compression_settings/win_rar_settings") {
+        rename_value(win_rar_settings, "split_size_unit", &units);
+        rename_value(win_rar_settings, "archive_format", &win_rar_formats);
+    }
+}
+
+fn rename_value(settings: &mut Value, field: &str, names: &[(&str, &str)]) {
+    if let Some(value) = settings.get_mut(field) {
+        if let Some((_, new_name)) = names.iter().find(|(old_name, _)| value.as_str() == Some(*old_name)) {
+            *value = Value::from(*new_name);
+        }
+    }
+}
+
+/// Deserialises one section of the settings, keeping every valid field. Invalid fields are reset
+/// to their defaults and reported, instead of resetting the whole section.
+pub fn deserialize_section<T: Default + Serialize + DeserializeOwned>(name: &str, section: Option<Value>, problems: &mut Vec<String>) -> T {
+    let Some(section) = section else {
+        return T::default();
+    };
+
+    if let Ok(settings) = serde_json::from_value(section.clone()) {
+        return settings;
+    }
+
+    let mut merged = serde_json::to_value(T::default()).unwrap();
+    merge_valid_fields::<T>(&mut merged, "", section, name, problems);
+    serde_json::from_value(merged).unwrap_or_default()
+}
+
+fn merge_valid_fields<T: DeserializeOwned>(merged: &mut Value, pointer: &str, section: Value, name: &str, problems: &mut Vec<String>) {
+    let Value::Object(fields) = section else {
+        problems.push(format!("{} is invalid and was reset to its defaults", name));
+        return;
+    };
+
+    for (key, value) in fields {
+        let field_pointer = format!("{}/{}", pointer, key);
+        let field_name = format!("{}.{}", name, key);
+        let mut candidate = merged.clone();
+        if let Some(object) = candidate.pointer_mut(pointer).and_then(Value::as_object_mut) {
+            object.insert(key.clone(), value.clone());
+        }
+
+        if serde_json::from_value::<T>(candidate.clone()).is_ok() {
+            *merged = candidate;
+        } else if value.is_object() && merged.pointer(&field_pointer).is_some_and(Value::is_object) {
+            merge_valid_fields::<T>(merged, &field_pointer, value, &field_name, problems);
+        } else {
+            problems.push(format!("{} has an invalid value ({}) and was reset to its default", field_name, value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn migrates_version_1_settings() {
+        let mut settings = json!({
+            "depot_downloader_settings": {
+                "encrypted_username": [1, 2, 3],
+                "username_nonce": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                "remember_credentials": false,
+            },
+            "compression_settings": {
+                "seven_zip_settings": {
+                    "solid_block_size_unit": "m",
+                    "split_size_unit": "g",
+                    "archive_format": "7z",
+                    "compression_method": "LZMA2",
+                },
+                "win_rar_settings": {"split_size_unit": "m", "archive_format": "rar"},
+            },
+        });
+        let mut problems = Vec::new();
+        migrate(&mut settings, &mut problems);
+
+        assert!(problems.is_empty());
+        assert_eq!(settings["version"], json!(SETTINGS_VERSION));
+        let depot_downloader_settings = &settings["depot_downloader_settings"];
+        assert!(depot_downloader_settings.get("encrypted_username").is_none());
+        assert_eq!(depot_downloader_settings["profiles"], json!([{
+            "name": "Default",
+            "encrypted_username": [1, 2, 3],
+            "username_nonce": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            "remember_credentials": false,
+        }]));
+        assert_eq!(settings["compression_settings"], json!({
+            "seven_zip_settings": {
+                "solid_block_size_unit": "Megabytes",
+                "split_size_unit": "Gigabytes",
+                "archive_format": "SevenZip",
+                "compression_method": "Lzma2",
+            },
+            "win_rar_settings": {"split_size_unit": "Megabytes", "archive_format": "Rar"},
+        }));
+    }
+
+    #[test]
+    fn migration_skips_empty_usernames() {
+        let mut settings = json!({
+            "depot_downloader_settings": {"encrypted_username": [], "username_nonce": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]},
+        });
+        migrate(&mut settings, &mut Vec::new());
+        assert_eq!(settings["depot_downloader_settings"], json!({"profiles": []}));
+    }
+
+    #[test]
+    fn current_settings_are_unchanged() {
+        let original = json!({"version": SETTINGS_VERSION, "compression_settings": {"win_rar_settings": {"archive_format": "rar"}}});
+        let mut settings = original.clone();
+        let mut problems = Vec::new();
+        migrate(&mut settings, &mut problems);
+        assert_eq!(settings, original);
+        assert!(problems.is_empty());
+    }
+
+    #[test]
+    fn newer_settings_are_reported() {
+        let original = json!({"version": SETTINGS_VERSION + 1, "autosave": false});
+        let mut settings = original.clone();
+        let mut problems = Vec::new();
+        migrate(&mut settings, &mut problems);
+        assert_eq!(settings, original);
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[test]
+    fn unknown_old_settings_are_reset() {
+        let mut settings = json!({"version": 0, "autosave": false});
+        let mut problems = Vec::new();
+        migrate(&mut settings, &mut problems);
+        assert_eq!(settings, json!({"version": SETTINGS_VERSION}));
+        assert_eq!(problems.len(), 1);
+    }
+
+    #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+    #[serde(default)]
+    struct Section {
+        name: String,
+        count: u32,
+        nested: Nested,
+    }
+
+    #[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+    #[serde(default)]
+    struct Nested {
+        enabled: bool,
+        level: u8,
+    }
+
+    #[test]
+    fn keeps_the_valid_fields_of_a_section() {
+        let section = json!({"name": "kept", "count": "many", "nested": {"enabled": true, "level": 300}});
+        let mut problems = Vec::new();
+        let settings: Section = deserialize_section("section", Some(section), &mut problems);
+        assert_eq!(settings, Section {
+            name: "kept".to_string(),
+            count: 0,
+            nested: Nested { enabled: true, level: 0 },
+        });
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("section.count"));
+        assert!(problems[1].starts_with("section.nested.level"));
+    }
+
+    #[test]
+    fn resets_a_section_which_is_not_an_object() {
+        let mut problems = Vec::new();
+        let settings: Section = deserialize_section("section", Some(json!([1, 2])), &mut problems);
+        assert_eq!(settings, Section::default());
+        assert_eq!(problems, ["section is invalid and was reset to its defaults"]);
+    }
+}
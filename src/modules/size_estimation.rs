@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use crossbeam_channel::{Receiver, Sender};
+use eframe::egui::{Button, Spinner, Ui};
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use sevenz_rust::lzma::{CountingWriter, LZMA2Options, LZMA2Writer};
+use crate::modules::content_classification::{extension, read_sample};
+use crate::modules::native_archiver::{ArchiveEntry, collect_entries};
+
+/// Bytes sampled from the largest files of each file type.
+const SAMPLE_BYTES_PER_TYPE: usize = 1024 * 1024;
+
+/// The codec the samples are compressed with, which is the archiver's own where there is one here.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SampleCodec {
+    Store,
+    Deflate { level: u8 },
+    Zstd { level: i32 },
+    /// The dictionary size is in MB, or the preset's when it is `None`. LZMA is estimated with
+    /// LZMA2 too, which only splits its stream into chunks.
+    Lzma2 { preset: u8, dictionary_size: Option<u16> },
+    /// LZMA2 in place of a method without a codec here, such as RAR or PPMd.
+    Substitute { method: &'static str, preset: u8, dictionary_size: Option<u16> },
+}
+
+impl SampleCodec {
+    /// Compresses the sample and returns the compressed size.
+    pub fn compress(&self, sample: &[u8]) -> std::io::Result<usize> {
+        match *self {
+            SampleCodec::Store => Ok(sample.len()),
+            SampleCodec::Deflate { level } => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::new(level.min(9) as u32));
+                encoder.write_all(sample)?;
+                Ok(encoder.finish()?.len())
+            }
+            SampleCodec::Zstd { level } => Ok(zstd::bulk::compress(sample, level)?.len()),
+            SampleCodec::Lzma2 { preset, dictionary_size } | SampleCodec::Substitute { preset, dictionary_size, .. } => {
+                let mut options = LZMA2Options::with_preset(preset.min(9) as u32);
+                // A dictionary larger than the sample finds the same matches, without allocating it
+                if let Some(dictionary_size) = dictionary_size {
+                    options.dict_size = (dictionary_size as u64 * 1024 * 1024).min(sample.len().max(4096) as u64) as u32;
+                }
+                let output = CountingWriter::new(std::io::sink());
+                let compressed = output.counting();
+                let mut writer = LZMA2Writer::new(output, &options);
+                writer.write_all(sample)?;
+                writer.finish()?;
+                Ok(compressed.get())
+            }
+        }
+    }
+}
+
+/// The expected archive size, from compressing samples of every file type.
+#[derive(Clone)]
+pub struct SizeEstimate {
+    pub input_bytes: u64,
+    pub archive_bytes: u64,
+    pub sampled_bytes: u64,
+    pub file_types: usize,
+    pub codec: SampleCodec,
+}
+
+impl Display for SizeEstimate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let percentage = match self.input_bytes {
+            0 => 100.0,
+            input_bytes => self.archive_bytes as f64 * 100.0 / input_bytes as f64,
+        };
+        write!(f, "about {:.2} GB ({:.0}% of {:.2} GB, from {:.1} MB of samples of {} file types)",
+               self.archive_bytes as f64 / (1024.0 * 1024.0 * 1024.0), percentage,
+               self.input_bytes as f64 / (1024.0 * 1024.0 * 1024.0), self.sampled_bytes as f64 / (1024.0 * 1024.0),
+               self.file_types)?;
+        if let SampleCodec::Substitute { method, .. } = self.codec {
+            write!(f, ", compressed with LZMA2 as there is no {} codec here", method)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compresses samples of each file type with the codec and scales their sizes up to all files of
+/// that type. Solid archives usually end up a little smaller.
+pub fn estimate_archive_size(download_path: &Path, codec: SampleCodec) -> std::io::Result<SizeEstimate> {
+    let entries = collect_entries(download_path, "")?;
+    let mut file_types: BTreeMap<String, Vec<&ArchiveEntry>> = BTreeMap::new();
+    for entry in entries.iter().filter(|entry| entry.path.is_file()) {
+        file_types.entry(extension(&entry.name).unwrap_or_default()).or_default().push(entry);
+    }
+
+    let mut estimate = SizeEstimate {
+        input_bytes: 0,
+        archive_bytes: 0,
+        sampled_bytes: 0,
+        file_types: file_types.len(),
+        codec,
+    };
+    for (_, mut files) in file_types {
+        let bytes: u64 = files.iter().map(|file| file.size).sum();
+        estimate.input_bytes += bytes;
+        if codec == SampleCodec::Store {
+            estimate.archive_bytes += bytes;
+            continue;
+        }
+
+        files.sort_by_key(|file| std::cmp::Reverse(file.size));
+        let mut sample = Vec::new();
+        for file in files.iter().filter(|file| file.size > 0) {
+            if sample.len() >= SAMPLE_BYTES_PER_TYPE {
+                break;
+            }
+            sample.extend(read_sample(&file.path, file.size)?);
+        }
+        if sample.is_empty() {
+            continue;
+        }
+        let compressed = codec.compress(&sample)?;
+        estimate.sampled_bytes += sample.len() as u64;
+        estimate.archive_bytes += (bytes as f64 * compressed as f64 / sample.len() as f64) as u64;
+    }
+    Ok(estimate)
+}
+
+type EstimateResult = (PathBuf, SampleCodec, Result<SizeEstimate, String>);
+
+/// Estimates archive sizes in the background and shows the last estimate.
+pub struct SizeEstimator {
+    sender: Sender<EstimateResult>,
+    receiver: Receiver<EstimateResult>,
+    running: bool,
+    /// The folder and codec of the last estimate, with its result.
+    last_estimate: Option<EstimateResult>,
+}
+
+impl Default for SizeEstimator {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self {
+            sender,
+            receiver,
+            running: false,
+            last_estimate: None,
+        }
+    }
+}
+
+impl SizeEstimator {
+    /// For estimates made elsewhere, such as by a compression job before it starts.
+    pub fn sender(&mut self) -> Sender<EstimateResult> {
+        self.running = true;
+        self.sender.clone()
+    }
+
+    pub fn start(&mut self, download_path: PathBuf, codec: SampleCodec) {
+        let sender = self.sender();
+        thread::spawn(move || {
+            let result = estimate_archive_size(&download_path, codec).map_err(|error| error.to_string());
+            let _ = sender.send((download_path, codec, result));
+        });
+    }
+
+    /// Shows the estimate for the folder with the codec, with a button to estimate it again.
+    pub fn display(&mut self, ui: &mut Ui, download_path: &Path, codec: SampleCodec) {
+        while let Ok(estimate) = self.receiver.try_recv() {
+            self.running = false;
+            self.last_estimate = Some(estimate);
+        }
+
+        ui.horizontal(|ui| {
+            let name = download_path.file_name().unwrap_or_default().to_string_lossy();
+            if self.running {
+                ui.add(Spinner::new());
+                ui.label(format!("Estimating the archive size of {}...", name));
+                return;
+            }
+            match &self.last_estimate {
+                Some((path, last_codec, Ok(estimate))) if path == download_path && *last_codec == codec => {
+                    ui.label(format!("Estimated archive size of {}: {}.", name, estimate));
+                }
+                Some((path, last_codec, Err(error))) if path == download_path && *last_codec == codec => {
+                    ui.label(format!("The archive size could not be estimated: {}.", error));
+                }
+                _ => {}
+            }
+            if ui.add_enabled(download_path.is_dir(), Button::new("Estimate size"))
+                .on_hover_text("Compresses samples of every file type in the last downloaded folder").clicked() {
+                self.start(download_path.to_path_buf(), codec);
+            }
+        });
+        if self.running {
+            ui.ctx().request_repaint();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::test_support::random_bytes;
+
+    #[test]
+    fn every_codec_compresses_repetitive_samples_only() {
+        let codecs = [
+            SampleCodec::Deflate { level: 6 },
+            SampleCodec::Zstd { level: 3 },
+            SampleCodec::Lzma2 { preset: 6, dictionary_size: Some(64) },
+            SampleCodec::Substitute { method: "RAR", preset: 6, dictionary_size: None },
+        ];
+        let repetitive = b"RedAlt-SteamUp-Creator ".repeat(10_000);
+        let random = random_bytes(100_000, 7);
+        for codec in codecs {
+            assert!(codec.compress(&repetitive).unwrap() < repetitive.len() / 20, "{:?}", codec);
+            assert!(codec.compress(&random).unwrap() > random.len() * 99 / 100, "{:?}", codec);
+        }
+        assert_eq!(SampleCodec::Store.compress(&random).unwrap(), random.len());
+    }
+}
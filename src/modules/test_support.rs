@@ -0,0 +1,10 @@
+/// Bytes from a xorshift generator. They neither repeat nor compress, so delta blocks only match
+/// where they were copied and archives of them need more than one volume.
+pub fn random_bytes(length: usize, mut seed: u64) -> Vec<u8> {
+    (0..length).map(|_| {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed as u8
+    }).collect()
+}
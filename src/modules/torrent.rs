@@ -0,0 +1,340 @@
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use crate::modules::job_summary::is_job_file;
+use crate::modules::reproducible::to_hex;
+
+pub const TORRENT_EXTENSION: &str = "torrent";
+/// BitTorrent v2 hashes files in blocks of 16 KiB.
+const BLOCK_SIZE: usize = 16 * 1024;
+/// Pieces are sized so that there are about this many, within the limits clients handle well.
+const TARGET_PIECES: u64 = 1500;
+const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// A value in bencoding, the format of .torrent files. Dictionaries are sorted by their raw keys.
+enum Bencode {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Bencode>),
+    Dictionary(BTreeMap<Vec<u8>, Bencode>),
+}
+
+impl Bencode {
+    fn string(value: &str) -> Self {
+        Bencode::Bytes(value.as_bytes().to_vec())
+    }
+
+    fn dictionary<const N: usize>(entries: [(&str, Bencode); N]) -> Self {
+        Bencode::Dictionary(entries.into_iter().map(|(key, value)| (key.as_bytes().to_vec(), value)).collect())
+    }
+
+    fn encode(&self, output: &mut Vec<u8>) {
+        match self {
+            Bencode::Integer(value) => output.extend(format!("i{}e", value).as_bytes()),
+            Bencode::Bytes(bytes) => {
+                output.extend(format!("{}:", bytes.len()).as_bytes());
+                output.extend(bytes);
+            }
+            Bencode::List(values) => {
+                output.push(b'l');
+                values.iter().for_each(|value| value.encode(output));
+                output.push(b'e');
+            }
+            Bencode::Dictionary(entries) => {
+                output.push(b'd');
+                for (key, value) in entries {
+                    Bencode::Bytes(key.clone()).encode(output);
+                    value.encode(output);
+                }
+                output.push(b'e');
+            }
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut output = Vec::new();
+        self.encode(&mut output);
+        output
+    }
+}
+
+/// The hashes of one file for both versions of the protocol.
+struct FileHashes {
+    name: String,
+    length: u64,
+    /// SHA-1 of each piece, with the last piece padded with zeros unless it ends the torrent.
+    v1_pieces: Vec<u8>,
+    /// SHA-256 Merkle root of each piece, used when the file has more than one.
+    piece_layer: Vec<[u8; 32]>,
+    pieces_root: Option<[u8; 32]>,
+}
+
+/// The torrent file which was written and the magnet link for it.
+pub struct Torrent {
+    pub path: PathBuf,
+    pub magnet_link: String,
+}
+
+/// Picks a power of two which gives about `TARGET_PIECES` pieces.
+pub fn piece_length(total_bytes: u64) -> u64 {
+    (total_bytes / TARGET_PIECES).max(1).next_power_of_two().clamp(MIN_PIECE_LENGTH, MAX_PIECE_LENGTH)
+}
+
+/// Writes a hybrid BitTorrent v1 and v2 torrent for `content`, which is either a single archive or a
+/// folder of volumes. In folders, reports and summaries are left out like they are for uploads.
+pub fn create_torrent(content: &Path, torrent_path: &Path, trackers: &[String], comment: &str) -> std::io::Result<Torrent> {
+    let name = content.file_name().unwrap().to_string_lossy().to_string();
+    let mut files: Vec<PathBuf> = match content.is_dir() {
+        true => content.read_dir()?.flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && !is_job_file(path))
+            .collect(),
+        false => vec![content.to_path_buf()],
+    };
+    // The v1 file list has to be in the same order as the sorted v2 file tree
+    files.sort_by_key(|file| file.file_name().unwrap().to_string_lossy().to_string());
+
+    let total_bytes = files.iter().map(|file| file.metadata().map(|metadata| metadata.len()).unwrap_or(0)).sum();
+    let piece_length = piece_length(total_bytes);
+    let hashes = files.iter().enumerate()
+        .map(|(index, file)| hash_file(file, piece_length, index + 1 == files.len()))
+        .collect::<std::io::Result<Vec<FileHashes>>>()?;
+
+    let mut info = BTreeMap::new();
+    info.insert(b"name".to_vec(), Bencode::string(&name));
+    info.insert(b"piece length".to_vec(), Bencode::Integer(piece_length as i64));
+    info.insert(b"meta version".to_vec(), Bencode::Integer(2));
+    info.insert(b"pieces".to_vec(), Bencode::Bytes(hashes.iter().flat_map(|file| file.v1_pieces.clone()).collect()));
+    let mut file_tree = BTreeMap::new();
+    let mut piece_layers = BTreeMap::new();
+    let mut v1_files = Vec::new();
+    for (index, file) in hashes.iter().enumerate() {
+        let mut file_entry = BTreeMap::new();
+        file_entry.insert(b"length".to_vec(), Bencode::Integer(file.length as i64));
+        if let Some(pieces_root) = file.pieces_root {
+            file_entry.insert(b"pieces root".to_vec(), Bencode::Bytes(pieces_root.to_vec()));
+            if file.length > piece_length {
+                piece_layers.insert(pieces_root.to_vec(), Bencode::Bytes(file.piece_layer.concat()));
+            }
+        }
+        file_tree.insert(file.name.as_bytes().to_vec(), Bencode::dictionary([("", Bencode::Dictionary(file_entry))]));
+
+        v1_files.push(Bencode::dictionary([
+            ("length", Bencode::Integer(file.length as i64)),
+            ("path", Bencode::List(vec![Bencode::string(&file.name)])),
+        ]));
+        // Hybrid torrents pad every file but the last to a piece boundary, so the pieces line up
+        let padding = (piece_length - file.length % piece_length) % piece_length;
+        if index + 1 < hashes.len() && padding > 0 {
+            v1_files.push(Bencode::dictionary([
+                ("attr", Bencode::string("p")),
+                ("length", Bencode::Integer(padding as i64)),
+                ("path", Bencode::List(vec![Bencode::string(".pad"), Bencode::string(&padding.to_string())])),
+            ]));
+        }
+    }
+    info.insert(b"file tree".to_vec(), Bencode::Dictionary(file_tree));
+    match content.is_dir() {
+        true => { info.insert(b"files".to_vec(), Bencode::List(v1_files)); }
+        false => { info.insert(b"length".to_vec(), Bencode::Integer(hashes[0].length as i64)); }
+    }
+    let info = Bencode::Dictionary(info).to_bytes();
+
+    let mut torrent = Vec::new();
+    torrent.push(b'd');
+    let trackers: Vec<&str> = trackers.iter().map(|tracker| tracker.trim()).filter(|tracker| !tracker.is_empty()).collect();
+    if let Some(tracker) = trackers.first() {
+        Bencode::string("announce").encode(&mut torrent);
+        Bencode::string(tracker).encode(&mut torrent);
+        // Every tracker gets its own tier, so clients announce to all of them
+        Bencode::string("announce-list").encode(&mut torrent);
+        Bencode::List(trackers.iter().map(|tracker| Bencode::List(vec![Bencode::string(tracker)])).collect()).encode(&mut torrent);
+    }
+    Bencode::string("comment").encode(&mut torrent);
+    Bencode::string(comment).encode(&mut torrent);
+    Bencode::string("created by").encode(&mut torrent);
+    Bencode::string(&format!("RedAlt-SteamUp-Creator {}", env!("CARGO_PKG_VERSION"))).encode(&mut torrent);
+    Bencode::string("creation date").encode(&mut torrent);
+    Bencode::Integer(chrono::Utc::now().timestamp()).encode(&mut torrent);
+    // The info dictionary is written as it was hashed
+    Bencode::string("info").encode(&mut torrent);
+    torrent.extend(&info);
+    Bencode::string("piece layers").encode(&mut torrent);
+    Bencode::Dictionary(piece_layers).encode(&mut torrent);
+    torrent.push(b'e');
+    std::fs::write(torrent_path, torrent)?;
+
+    let mut magnet_link = format!("magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}&dn={}",
+                                  to_hex(&Sha1::digest(&info)), to_hex(&Sha256::digest(&info)), url_encode(&name));
+    for tracker in trackers {
+        magnet_link += &format!("&tr={}", url_encode(tracker));
+    }
+    Ok(Torrent {
+        path: torrent_path.to_path_buf(),
+        magnet_link,
+    })
+}
+
+/// Reads the file once, piece by piece, for the v1 piece hashes and the v2 Merkle tree.
+fn hash_file(path: &Path, piece_length: u64, is_last: bool) -> std::io::Result<FileHashes> {
+    let mut file = File::open(path)?;
+    let blocks_per_piece = (piece_length as usize / BLOCK_SIZE).max(1);
+    let mut hashes = FileHashes {
+        name: path.file_name().unwrap().to_string_lossy().to_string(),
+        length: 0,
+        v1_pieces: Vec::new(),
+        piece_layer: Vec::new(),
+        pieces_root: None,
+    };
+    let mut blocks = Vec::new();
+    let mut buffer = vec![0; piece_length as usize];
+    loop {
+        let read = read_full(&mut file, &mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hashes.length += read as u64;
+
+        let mut v1_piece = Sha1::new();
+        v1_piece.update(&buffer[..read]);
+        if read < buffer.len() && !is_last {
+            v1_piece.update(vec![0; buffer.len() - read]);
+        }
+        hashes.v1_pieces.extend(v1_piece.finalize());
+
+        let piece_blocks: Vec<[u8; 32]> = buffer[..read].chunks(BLOCK_SIZE).map(|block| Sha256::digest(block).into()).collect();
+        hashes.piece_layer.push(merkle_root(piece_blocks.clone(), blocks_per_piece, [0; 32]));
+        blocks.extend(piece_blocks);
+        if read < buffer.len() {
+            break;
+        }
+    }
+
+    hashes.pieces_root = match hashes.piece_layer.len() {
+        0 => None,
+        // A file within one piece has a tree over only as many blocks as it needs
+        1 => Some(merkle_root(blocks.clone(), blocks.len().next_power_of_two(), [0; 32])),
+        pieces => Some(merkle_root(hashes.piece_layer.clone(), pieces.next_power_of_two(), zero_subtree(blocks_per_piece))),
+    };
+    Ok(hashes)
+}
+
+/// The root of a tree with `leaves` leaves, where missing leaves are `padding`.
+fn merkle_root(mut layer: Vec<[u8; 32]>, leaves: usize, padding: [u8; 32]) -> [u8; 32] {
+    layer.resize(leaves.max(1), padding);
+    while layer.len() > 1 {
+        layer = layer.chunks(2).map(|pair| Sha256::new().chain_update(pair[0]).chain_update(pair[1]).finalize().into()).collect();
+    }
+    layer[0]
+}
+
+/// The root of a tree of `leaves` zero hashes, which pads the piece layer.
+fn zero_subtree(leaves: usize) -> [u8; 32] {
+    merkle_root(Vec::new(), leaves, [0; 32])
+}
+
+/// Fills the buffer unless the file ends first, and returns how much was read.
+fn read_full(file: &mut File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut total = 0;
+    while total < buffer.len() {
+        match file.read(&mut buffer[total..])? {
+            0 => break,
+            read => total += read,
+        }
+    }
+    Ok(total)
+}
+
+fn url_encode(value: &str) -> String {
+    value.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bencode_sorts_dictionaries_by_key() {
+        let value = Bencode::dictionary([
+            ("length", Bencode::Integer(-42)),
+            ("attr", Bencode::List(vec![Bencode::string("p"), Bencode::Bytes(vec![0, 255])])),
+        ]);
+        assert_eq!(value.to_bytes(), b"d4:attrl1:p2:\x00\xffe6:lengthi-42ee");
+        assert_eq!(Bencode::Dictionary(BTreeMap::new()).to_bytes(), b"de");
+    }
+
+    #[test]
+    fn piece_length_is_a_power_of_two_within_limits() {
+        assert_eq!(piece_length(0), MIN_PIECE_LENGTH);
+        assert_eq!(piece_length(TARGET_PIECES * 1024 * 1024), 1024 * 1024);
+        assert_eq!(piece_length(TARGET_PIECES * (1024 * 1024 + 1)), 2 * 1024 * 1024);
+        assert_eq!(piece_length(u64::MAX / 2), MAX_PIECE_LENGTH);
+    }
+
+    #[test]
+    fn hashes_a_file_within_one_piece() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("Game.7z");
+        let data: Vec<u8> = (0..20 * 1024).map(|index| (index % 251) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let last = hash_file(&path, 32 * 1024, true).unwrap();
+        assert_eq!(last.length, data.len() as u64);
+        assert_eq!(last.v1_pieces, Sha1::digest(&data).to_vec());
+        let blocks: Vec<[u8; 32]> = data.chunks(BLOCK_SIZE).map(|block| Sha256::digest(block).into()).collect();
+        let root: [u8; 32] = Sha256::new().chain_update(blocks[0]).chain_update(blocks[1]).finalize().into();
+        assert_eq!(last.pieces_root, Some(root));
+
+        // Files before the last one are padded to the piece length for v1
+        let padded = hash_file(&path, 32 * 1024, false).unwrap();
+        let mut padded_data = data.clone();
+        padded_data.resize(32 * 1024, 0);
+        assert_eq!(padded.v1_pieces, Sha1::digest(&padded_data).to_vec());
+        assert_eq!(padded.pieces_root, Some(root));
+    }
+
+    #[test]
+    fn hashes_a_file_over_several_pieces() {
+        let folder = tempfile::tempdir().unwrap();
+        let path = folder.path().join("Game.7z.001");
+        let data: Vec<u8> = (0..3 * BLOCK_SIZE).map(|index| (index % 253) as u8).collect();
+        std::fs::write(&path, &data).unwrap();
+
+        let hashes = hash_file(&path, BLOCK_SIZE as u64, true).unwrap();
+        assert_eq!(hashes.v1_pieces.len(), 3 * 20);
+        let layer: Vec<[u8; 32]> = data.chunks(BLOCK_SIZE).map(|block| Sha256::digest(block).into()).collect();
+        assert_eq!(hashes.piece_layer, layer);
+        // The layer is padded to a power of two with the roots of empty pieces
+        let left: [u8; 32] = Sha256::new().chain_update(layer[0]).chain_update(layer[1]).finalize().into();
+        let right: [u8; 32] = Sha256::new().chain_update(layer[2]).chain_update([0; 32]).finalize().into();
+        let root: [u8; 32] = Sha256::new().chain_update(left).chain_update(right).finalize().into();
+        assert_eq!(hashes.pieces_root, Some(root));
+    }
+
+    #[test]
+    fn writes_the_torrent_and_magnet_link() {
+        let folder = tempfile::tempdir().unwrap();
+        let content = folder.path().join("Game Update.7z");
+        std::fs::write(&content, b"archive").unwrap();
+        let torrent_path = folder.path().join("Game Update.torrent");
+        let trackers = ["udp://tracker.example:1337".to_string(), " ".to_string()];
+        let torrent = create_torrent(&content, &torrent_path, &trackers, "Depot 1").unwrap();
+
+        let bytes = std::fs::read(&torrent.path).unwrap();
+        let info_start = bytes.windows(6).position(|window| window == b"4:info").unwrap() + 6;
+        let info_end = bytes.windows(15).position(|window| window == b"12:piece layers").unwrap();
+        let info = &bytes[info_start..info_end];
+        assert!(info.starts_with(b"d9:file treed14:Game Update.7zd0:d6:lengthi7e11:pieces root32:"));
+        assert!(bytes.starts_with(b"d8:announce26:udp://tracker.example:1337"));
+        assert_eq!(torrent.magnet_link, format!(
+            "magnet:?xt=urn:btih:{}&xt=urn:btmh:1220{}&dn=Game%20Update.7z&tr=udp%3A%2F%2Ftracker.example%3A1337",
+            to_hex(&Sha1::digest(info)), to_hex(&Sha256::digest(info))));
+    }
+}
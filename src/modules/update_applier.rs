@@ -0,0 +1,360 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use crate::modules::binary_delta::{FileStrategy, FileStrategyRecord, apply_delta, sha256_file};
+use crate::modules::changes::Changes;
+use crate::modules::native_archiver::collect_entries;
+use crate::modules::package_metadata::{INSTALLER_FOLDER, METADATA_FILE, PackageMetadata};
+use crate::modules::reproducible::to_hex;
+
+/// Lists what was changed, so the update can be rolled back. It is kept in the backup folder.
+pub const BACKUP_FILE: &str = "backup.json";
+/// Appended to files written from a delta until they replace the file of the initial build.
+const PATCHED_SUFFIX: &str = ".rsu-patched";
+
+/// One change to the game folder, with the path of the file inside it.
+pub enum UpdateOperation {
+    Add(String),
+    Replace(String),
+    ApplyDelta(FileStrategyRecord),
+    Remove(String),
+}
+
+impl Display for UpdateOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateOperation::Add(path) => write!(f, "Add {}", path),
+            UpdateOperation::Replace(path) => write!(f, "Replace {}", path),
+            UpdateOperation::ApplyDelta(record) => write!(f, "Patch {} with a delta", record.path),
+            UpdateOperation::Remove(path) => write!(f, "Delete {}", path),
+        }
+    }
+}
+
+/// Everything applying a package to a game folder would change.
+pub struct UpdatePlan {
+    /// The folder with the package's files and its installer folder.
+    pub package_path: PathBuf,
+    pub game_path: PathBuf,
+    pub metadata: PackageMetadata,
+    pub operations: Vec<UpdateOperation>,
+}
+
+/// The files changed by an update, relative to the game folder.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Backup {
+    pub game_path: PathBuf,
+    /// Files the update added, which are deleted when rolling back.
+    pub added: Vec<String>,
+    /// Files the update replaced or deleted, which are in the backup folder.
+    pub backed_up: Vec<String>,
+    /// Folders the update created for added files, parents first.
+    pub created_folders: Vec<String>,
+}
+
+impl Backup {
+    pub fn read(backup_path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(backup_path.join(BACKUP_FILE))?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    fn write(&self, backup_path: &Path) -> std::io::Result<()> {
+        std::fs::write(backup_path.join(BACKUP_FILE), serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl UpdatePlan {
+    /// Plans the update from a package folder.
+    pub fn new(package_path: &Path, game_path: &Path) -> std::io::Result<Self> {
+        let package_path = find_package_root(package_path)?;
+        let files = collect_entries(&package_path, "")?.into_iter()
+            .filter(|entry| entry.path.is_file())
+            .map(|entry| entry.name.trim_start_matches('/').to_string())
+            .collect();
+        Self::from_files(package_path, files, game_path)
+    }
+
+    /// Plans the update from an archive's listing, so dry runs do not have to extract it. Only the
+    /// metadata in the installer folder is extracted, into `metadata_folder`.
+    pub fn from_listing(listing: &[String], metadata_folder: &Path, game_path: &Path,
+                        extract: impl FnOnce(&[String]) -> std::io::Result<()>) -> std::io::Result<Self> {
+        let names: Vec<String> = listing.iter().map(|name| name.replace('\\', "/")).collect();
+        let marker = format!("{}/", INSTALLER_FOLDER);
+        let prefix = names.iter()
+            .find_map(|name| name.find(&marker).map(|index| name[..index].to_string()))
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "the archive does not contain a package with an installer folder"))?;
+        // Listings can include folders, which are the parents of other entries
+        let folders: BTreeSet<&str> = names.iter().filter_map(|name| name.rsplit_once('/').map(|(folder, _)| folder)).collect();
+        let files: Vec<String> = names.iter()
+            .filter(|name| !folders.contains(name.trim_end_matches('/')))
+            .filter_map(|name| name.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        let metadata_files: Vec<String> = listing.iter().zip(&names)
+            .filter(|(_, name)| name.starts_with(&format!("{}{}", prefix, marker)) && name.ends_with(".json"))
+            .map(|(original, _)| original.clone())
+            .collect();
+        if !metadata_files.is_empty() {
+            extract(&metadata_files)?;
+        }
+        Self::from_files(metadata_folder.join(prefix.trim_end_matches('/')), files, game_path)
+    }
+
+    fn from_files(package_path: PathBuf, files: Vec<String>, game_path: &Path) -> std::io::Result<Self> {
+        let mut metadata = read_metadata(&package_path)?;
+        // Packages made on Windows can list paths with backslashes, which are only separators there
+        let metadata_paths = metadata.files.iter_mut().map(|file| &mut file.path)
+            .chain(&mut metadata.removed)
+            .chain(metadata.file_strategies.iter_mut().flat_map(|record| [Some(&mut record.path), record.delta_path.as_mut()]).flatten());
+        for path in metadata_paths {
+            *path = path.replace('\\', "/");
+        }
+        // Paths from the package must stay inside the game folder
+        let paths = metadata.files.iter().map(|file| &file.path)
+            .chain(&metadata.removed)
+            .chain(&files)
+            .chain(metadata.file_strategies.iter().flat_map(|record| [Some(&record.path), record.delta_path.as_ref()]).flatten());
+        for path in paths {
+            check_relative_path(path)?;
+        }
+        let mut operations = Vec::new();
+
+        let deltas: Vec<&FileStrategyRecord> = metadata.file_strategies.iter()
+            .filter(|record| record.strategy == FileStrategy::Delta)
+            .collect();
+        operations.extend(deltas.iter().map(|&record| UpdateOperation::ApplyDelta(record.clone())));
+        let delta_paths: BTreeSet<&str> = deltas.iter().map(|record| record.path.as_str()).collect();
+        for path in files {
+            if path.starts_with(&format!("{}/", INSTALLER_FOLDER)) || delta_paths.contains(path.as_str()) {
+                continue;
+            }
+            match game_path.join(&path).is_file() {
+                true => operations.push(UpdateOperation::Replace(path)),
+                false => operations.push(UpdateOperation::Add(path)),
+            }
+        }
+        for path in &metadata.removed {
+            if game_path.join(path).is_file() {
+                operations.push(UpdateOperation::Remove(path.clone()));
+            }
+        }
+
+        Ok(Self {
+            package_path,
+            game_path: game_path.to_path_buf(),
+            metadata,
+            operations,
+        })
+    }
+
+    /// Backups are kept next to the game folder, so files can be moved instead of copied.
+    pub fn backup_path(&self) -> PathBuf {
+        let name = self.game_path.file_name().unwrap().to_string_lossy();
+        self.game_path.with_file_name(format!("{} - Backup before Build {} ({})", name, self.metadata.final_build,
+                                              chrono::Local::now().format("%Y-%m-%d %H-%M-%S")))
+    }
+
+    /// Applies every operation, moving the files it replaces or deletes into a backup folder, and
+    /// checks the result against the package metadata. Returns the backup folder.
+    pub fn apply(&self, output_sender: &Sender<String>) -> std::io::Result<PathBuf> {
+        // Deltas only work on the exact file they were made for, so they are checked before anything changes
+        for operation in &self.operations {
+            if let UpdateOperation::ApplyDelta(record) = operation {
+                let base = self.game_path.join(&record.path);
+                if record.base_sha256.as_ref().is_some_and(|hash| sha256_file(&base).ok().as_ref() != Some(hash)) {
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                                   format!("{} is not the file of Build {}", record.path, self.metadata.initial_build)));
+                }
+            }
+        }
+
+        let backup_path = self.backup_path();
+        std::fs::create_dir_all(&backup_path)?;
+        let mut backup = Backup {
+            game_path: self.game_path.clone(),
+            ..Backup::default()
+        };
+        // The backup is written even if applying fails, so a partial update can be rolled back
+        let result = self.apply_operations(&backup_path, &mut backup, output_sender);
+        backup.write(&backup_path)?;
+        result?;
+
+        let _ = output_sender.send("\nChecking the updated files...\n".to_string());
+        let mismatches = self.check_files()?;
+        if !mismatches.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                           format!("{} files differ from the package metadata: {}", mismatches.len(), mismatches.join(", "))));
+        }
+        let _ = output_sender.send(format!("All {} files match the package metadata.\n", self.metadata.files.len()));
+        Ok(backup_path)
+    }
+
+    fn apply_operations(&self, backup_path: &Path, backup: &mut Backup, output_sender: &Sender<String>) -> std::io::Result<()> {
+        for operation in &self.operations {
+            let _ = output_sender.send(format!("{}\n", operation));
+            match operation {
+                UpdateOperation::Add(path) => {
+                    create_parent_folders(&self.game_path, path, backup)?;
+                    copy_file(&self.package_path.join(path), &self.game_path.join(path))?;
+                    backup.added.push(path.clone());
+                }
+                UpdateOperation::Replace(path) => {
+                    move_file(&self.game_path.join(path), &backup_path.join(path))?;
+                    backup.backed_up.push(path.clone());
+                    copy_file(&self.package_path.join(path), &self.game_path.join(path))?;
+                }
+                UpdateOperation::ApplyDelta(record) => {
+                    let target = self.game_path.join(&record.path);
+                    let patched = PathBuf::from(format!("{}{}", target.display(), PATCHED_SUFFIX));
+                    let delta = self.package_path.join(INSTALLER_FOLDER).join(record.delta_path.as_deref().unwrap_or_default());
+                    apply_delta(&target, &delta, &patched)?;
+                    if sha256_file(&patched)? != record.sha256 {
+                        let _ = std::fs::remove_file(&patched);
+                        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                                       format!("the patched {} does not match the package", record.path)));
+                    }
+                    move_file(&target, &backup_path.join(&record.path))?;
+                    backup.backed_up.push(record.path.clone());
+                    std::fs::rename(patched, target)?;
+                }
+                UpdateOperation::Remove(path) => {
+                    move_file(&self.game_path.join(path), &backup_path.join(path))?;
+                    backup.backed_up.push(path.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Files whose size or SHA-1 differs from the metadata. Older packages have no hashes to check.
+    fn check_files(&self) -> std::io::Result<Vec<String>> {
+        let mut mismatches = Vec::new();
+        for file in &self.metadata.files {
+            let path = self.game_path.join(&file.path);
+            let matches = path.metadata().is_ok_and(|metadata| metadata.len() == file.size) && {
+                let mut hasher = Sha1::new();
+                std::io::copy(&mut File::open(&path)?, &mut hasher)?;
+                to_hex(&hasher.finalize()) == file.sha1
+            };
+            if !matches {
+                mismatches.push(file.path.clone());
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+impl Display for UpdatePlan {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Update {} from Build {} to Build {} in {}:", self.metadata.name, self.metadata.initial_build,
+                 self.metadata.final_build, self.game_path.display())?;
+        for operation in &self.operations {
+            writeln!(f, "  {}", operation)?;
+        }
+        writeln!(f, "{} operations.", self.operations.len())
+    }
+}
+
+/// Puts back every file an update replaced or deleted, deletes the files it added and removes the
+/// backup folder.
+pub fn roll_back(backup_path: &Path, output_sender: &Sender<String>) -> std::io::Result<()> {
+    let backup = Backup::read(backup_path)?;
+    for path in backup.added.iter().chain(&backup.backed_up).chain(&backup.created_folders) {
+        check_relative_path(path)?;
+    }
+    for path in &backup.added {
+        let _ = output_sender.send(format!("Delete {}\n", path));
+        let file = backup.game_path.join(path);
+        if file.is_file() {
+            std::fs::remove_file(file)?;
+        }
+    }
+    for path in &backup.backed_up {
+        let _ = output_sender.send(format!("Restore {}\n", path));
+        let file = backup.game_path.join(path);
+        if file.is_file() {
+            std::fs::remove_file(&file)?;
+        }
+        move_file(&backup_path.join(path), &file)?;
+    }
+    // Folders which hold other files by now are kept
+    for folder in backup.created_folders.iter().rev() {
+        let _ = std::fs::remove_dir(backup.game_path.join(folder));
+    }
+    std::fs::remove_dir_all(backup_path)
+}
+
+/// Fails for absolute paths and paths which leave their folder, such as `..\\Game.exe`.
+fn check_relative_path(path: &str) -> std::io::Result<()> {
+    let normalized = path.replace('\\', "/");
+    let components = Path::new(&normalized).components();
+    match !normalized.is_empty() && components.into_iter().all(|component| matches!(component, Component::Normal(_))) {
+        true => Ok(()),
+        false => Err(std::io::Error::new(std::io::ErrorKind::InvalidData,
+                                         format!("the package contains the path {}, which is outside the game folder", path))),
+    }
+}
+
+/// The package is either the given folder or the only folder in it, such as an extracted archive.
+fn find_package_root(folder: &Path) -> std::io::Result<PathBuf> {
+    if folder.join(INSTALLER_FOLDER).is_dir() {
+        return Ok(folder.to_path_buf());
+    }
+    let children: Vec<PathBuf> = folder.read_dir()?.flatten()
+        .map(|child| child.path())
+        .filter(|child| child.join(INSTALLER_FOLDER).is_dir())
+        .collect();
+    match children.as_slice() {
+        [root] => Ok(root.clone()),
+        _ => Err(std::io::Error::new(std::io::ErrorKind::NotFound,
+                                     format!("{} does not contain a package with an installer folder", folder.display()))),
+    }
+}
+
+/// Packages made before update.json was written only have the changes from the userscript.
+fn read_metadata(package_path: &Path) -> std::io::Result<PackageMetadata> {
+    if PackageMetadata::path(package_path).is_file() {
+        return Ok(PackageMetadata::read(package_path));
+    }
+    if !package_path.join(INSTALLER_FOLDER).is_dir() {
+        return Ok(PackageMetadata::default());
+    }
+    let changes = package_path.join(INSTALLER_FOLDER).read_dir()?.flatten()
+        .map(|file| file.path())
+        .filter(|file| file.extension().is_some_and(|extension| extension == "json") && !file.ends_with(METADATA_FILE))
+        .find_map(|file| std::fs::read_to_string(file).ok().and_then(|json| serde_json::from_str::<Changes>(&json).ok()));
+    Ok(changes.map(|changes| PackageMetadata::new(&changes, "")).unwrap_or_default())
+}
+
+/// Creates the folders of a new file, remembering the ones which did not exist yet.
+fn create_parent_folders(game_path: &Path, path: &str, backup: &mut Backup) -> std::io::Result<()> {
+    let parts: Vec<&str> = path.split('/').collect();
+    for index in 1..parts.len() {
+        let folder = parts[..index].join("/");
+        if !game_path.join(&folder).is_dir() {
+            std::fs::create_dir(game_path.join(&folder))?;
+            backup.created_folders.push(folder);
+        }
+    }
+    Ok(())
+}
+
+fn copy_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to.parent().unwrap())?;
+    std::fs::copy(from, to)?;
+    Ok(())
+}
+
+/// Renames the file, or copies it when it is on another drive.
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(to.parent().unwrap())?;
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+    Ok(())
+}
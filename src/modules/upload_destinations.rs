@@ -0,0 +1,42 @@
+use serde::{Deserialize, Serialize};
+use crate::modules::compression_settings::SizeUnit;
+
+/// An upload host and the largest file it accepts, which decides how archives are split.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct UploadDestination {
+    pub name: String,
+    pub max_file_size: u16,
+    pub max_file_size_unit: SizeUnit,
+}
+
+impl Default for UploadDestination {
+    fn default() -> Self {
+        Self {
+            name: "New destination".to_string(),
+            max_file_size: 2,
+            max_file_size_unit: SizeUnit::Gigabytes,
+        }
+    }
+}
+
+impl UploadDestination {
+    pub fn max_file_bytes(&self) -> u64 {
+        self.max_file_size as u64 * self.max_file_size_unit.bytes()
+    }
+
+    /// The split size for an archive of about `archive_bytes`, or `None` when it fits into one file.
+    pub fn split_size(&self, archive_bytes: u64) -> Option<(u16, SizeUnit)> {
+        match archive_bytes > self.max_file_bytes() && self.max_file_size > 0 {
+            true => Some((self.max_file_size, self.max_file_size_unit)),
+            false => None,
+        }
+    }
+
+    pub fn expected_volumes(&self, archive_bytes: u64) -> u64 {
+        match self.split_size(archive_bytes) {
+            Some(_) => archive_bytes.div_ceil(self.max_file_bytes()),
+            None => 1,
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use crate::modules::checksums::is_checksum_file;
+use crate::modules::native_archiver::collect_entries;
+
+/// Appended to the archive's file name for the report, which is kept next to the archive.
+pub const REPORT_SUFFIX: &str = ".verification.txt";
+
+/// The result of testing an archive and comparing its contents with the downloaded files.
+pub struct VerificationReport {
+    pub archive_path: PathBuf,
+    /// Every volume found for the archive with its size, in order.
+    pub volumes: Vec<(PathBuf, u64)>,
+    /// Numbers of the volumes which are missing between the first and the last one.
+    pub missing_volumes: Vec<usize>,
+    pub test_result: Result<(), String>,
+    /// Files in the archive compared with the download, or why they could not be compared.
+    pub listing_result: Result<usize, String>,
+    pub missing_files: Vec<String>,
+    pub unexpected_files: Vec<String>,
+    pub size_mismatches: Vec<String>,
+}
+
+impl VerificationReport {
+    pub fn new(archive_path: PathBuf, is_split: bool) -> Self {
+        let volumes: Vec<(PathBuf, u64)> = find_volumes(&archive_path, is_split).into_iter()
+            .map(|volume| {
+                let size = volume.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                (volume, size)
+            })
+            .collect();
+
+        let numbers: BTreeSet<usize> = volumes.iter()
+            .filter_map(|(volume, _)| volume_number(volume))
+            .collect();
+        let missing_volumes = match numbers.last() {
+            Some(&last) if is_split => (1..last).filter(|number| !numbers.contains(number)).collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            archive_path,
+            volumes,
+            missing_volumes,
+            test_result: Err("The archive was not tested".to_string()),
+            listing_result: Err("The archive was not listed".to_string()),
+            missing_files: Vec::new(),
+            unexpected_files: Vec::new(),
+            size_mismatches: Vec::new(),
+        }
+    }
+
+    /// The volume the archivers are given to open a split archive.
+    pub fn first_volume(&self) -> Option<&Path> {
+        self.volumes.first().map(|(volume, _)| volume.as_path())
+    }
+
+    /// Compares the files listed in the archive, named like the entries of `collect_entries`, with
+    /// the files in the download folder, apart from the excluded ones. Sizes are only compared when
+    /// the archiver lists them.
+    pub fn compare_listing(&mut self, download_path: &Path, listing: Vec<(String, Option<u64>)>,
+                           is_excluded: impl Fn(&str) -> bool) -> std::io::Result<()> {
+        let folder_name = download_path.file_name().unwrap().to_str().unwrap();
+        let mut entries = collect_entries(download_path, folder_name)?;
+        entries.retain(|entry| !is_excluded(&entry.name));
+        let expected_folders: BTreeSet<&str> = entries.iter()
+            .filter(|entry| entry.path.is_dir())
+            .map(|entry| entry.name.as_str())
+            .collect();
+        let mut expected_files: BTreeMap<&str, u64> = entries.iter()
+            .filter(|entry| !entry.path.is_dir())
+            .map(|entry| (entry.name.as_str(), entry.size))
+            .collect();
+
+        let mut compared = 0;
+        for (name, size) in listing {
+            let name = name.replace('\\', "/");
+            let name = name.trim_end_matches('/');
+            if name == folder_name || expected_folders.contains(name) {
+                continue;
+            }
+            compared += 1;
+            match expected_files.remove(name) {
+                None => self.unexpected_files.push(name.to_string()),
+                Some(expected_size) => match size {
+                    Some(size) if size != expected_size => {
+                        self.size_mismatches.push(format!("{} ({} bytes, {} bytes downloaded)", name, size, expected_size));
+                    }
+                    _ => {}
+                },
+            }
+        }
+        self.missing_files = expected_files.into_keys().map(|name| name.to_string()).collect();
+        self.listing_result = Ok(compared);
+        Ok(())
+    }
+
+    pub fn passed(&self) -> bool {
+        !self.volumes.is_empty()
+            && self.missing_volumes.is_empty()
+            && self.test_result.is_ok()
+            && self.listing_result.is_ok()
+            && self.missing_files.is_empty()
+            && self.unexpected_files.is_empty()
+            && self.size_mismatches.is_empty()
+    }
+
+    pub fn report_path(&self) -> PathBuf {
+        let name = self.archive_path.file_name().unwrap().to_str().unwrap();
+        self.archive_path.with_file_name(format!("{}{}", name, REPORT_SUFFIX))
+    }
+
+    /// Writes the report next to the archive and returns its path.
+    pub fn write(&self) -> std::io::Result<PathBuf> {
+        let path = self.report_path();
+        std::fs::write(&path, self.to_string())?;
+        Ok(path)
+    }
+}
+
+impl Display for VerificationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Archive: {}", self.archive_path.display())?;
+        writeln!(f, "Result: {}", if self.passed() { "PASSED" } else { "FAILED" })?;
+
+        writeln!(f, "\nVolumes ({}):", self.volumes.len())?;
+        for (volume, size) in &self.volumes {
+            writeln!(f, "  {} ({} bytes)", volume.file_name().unwrap().to_string_lossy(), size)?;
+        }
+        if self.volumes.is_empty() {
+            writeln!(f, "  The archive does not exist")?;
+        }
+        if !self.missing_volumes.is_empty() {
+            let missing: Vec<String> = self.missing_volumes.iter().map(|number| number.to_string()).collect();
+            writeln!(f, "  Missing volumes: {}", missing.join(", "))?;
+        }
+
+        match &self.test_result {
+            Ok(_) => writeln!(f, "\nArchive test: passed")?,
+            Err(error) => writeln!(f, "\nArchive test: failed ({})", error)?,
+        }
+
+        match &self.listing_result {
+            Ok(compared) => writeln!(f, "\nContents: {} files compared with the download", compared)?,
+            Err(error) => writeln!(f, "\nContents: not compared ({})", error)?,
+        }
+        for (heading, files) in [
+            ("Missing from the archive", &self.missing_files),
+            ("Not in the download", &self.unexpected_files),
+            ("Different size", &self.size_mismatches),
+        ] {
+            if !files.is_empty() {
+                writeln!(f, "  {} ({}):", heading, files.len())?;
+                for file in files {
+                    writeln!(f, "    {}", file)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split archives are in their own folder, where every file named after the archive is a volume,
+/// such as `Game.7z.001` or `Game.part01.rar`.
+pub fn find_volumes(archive_path: &Path, is_split: bool) -> Vec<PathBuf> {
+    if !is_split {
+        return archive_path.is_file().then(|| archive_path.to_path_buf()).into_iter().collect();
+    }
+
+    // The folder is named after the download, which can contain dots itself
+    let stem = archive_path.parent().and_then(Path::file_name).unwrap_or_default().to_string_lossy().to_string();
+    let mut volumes: Vec<PathBuf> = archive_path.parent().and_then(|folder| folder.read_dir().ok()).into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with(&format!("{}.", stem)) && !name.ends_with(REPORT_SUFFIX) && !is_checksum_file(path)
+                && volume_number(path).is_some()
+        })
+        .collect();
+    volumes.sort_by_key(|volume| volume_number(volume));
+    volumes
+}
+
+/// The last number in the file name, which is the volume number for every naming scheme used.
+fn volume_number(path: &Path) -> Option<usize> {
+    let name = path.file_name()?.to_str()?;
+    let digits: String = name.chars().rev()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.chars().rev().collect::<String>().parse().ok()
+}
@@ -0,0 +1,197 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::thread;
+use crossbeam_channel::{Receiver, Sender};
+use eframe::egui::{Button, Color32, ScrollArea, TextEdit, Ui};
+use egui_file::FileDialog;
+use crate::modules::depot_manifest::{DepotManifest, InstallReport, verify_install};
+
+type VerifyResult = std::io::Result<(DepotManifest, InstallReport)>;
+
+/// Checks an installed game against the manifest of a build and writes changes which repair it.
+pub struct VerifyInstallUI {
+    output_sender: Sender<String>,
+    output_receiver: Receiver<String>,
+    result_sender: Sender<VerifyResult>,
+    result_receiver: Receiver<VerifyResult>,
+    game_path: Option<PathBuf>,
+    game_dialog: Option<FileDialog>,
+    manifest_path: Option<PathBuf>,
+    manifest_dialog: Option<FileDialog>,
+    /// The manifest only has the depot, the app and build are needed to download the files.
+    app: String,
+    build: String,
+    /// Deleting files which are not part of the build also removes saves and mods.
+    remove_extra_files: bool,
+    running: bool,
+    result: Option<VerifyResult>,
+    repair_dialog: Option<FileDialog>,
+    stdout: String,
+}
+
+impl Default for VerifyInstallUI {
+    fn default() -> Self {
+        let (output_sender, output_receiver) = crossbeam_channel::unbounded();
+        let (result_sender, result_receiver) = crossbeam_channel::bounded(1);
+        Self {
+            output_sender,
+            output_receiver,
+            result_sender,
+            result_receiver,
+            game_path: None,
+            game_dialog: None,
+            manifest_path: None,
+            manifest_dialog: None,
+            app: String::new(),
+            build: String::new(),
+            remove_extra_files: false,
+            running: false,
+            result: None,
+            repair_dialog: None,
+            stdout: String::new(),
+        }
+    }
+}
+
+impl VerifyInstallUI {
+    pub fn display(ui: &mut Ui, verify_install_ui: &mut VerifyInstallUI) {
+        verify_install_ui.display_paths(ui);
+        verify_install_ui.display_verify_button(ui);
+        verify_install_ui.display_result(ui);
+        ui.separator();
+        verify_install_ui.display_stdout(ui);
+    }
+
+    fn display_paths(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            match &self.game_path {
+                None => ui.label("Choose the game folder:"),
+                Some(path) => ui.label(format!("Game folder: {}", path.display())),
+            };
+            if ui.button("Choose folder").clicked() {
+                let mut dialog = FileDialog::select_folder(self.game_path.clone());
+                dialog.open();
+                self.game_dialog = Some(dialog);
+            }
+        });
+        if let Some(dialog) = &mut self.game_dialog {
+            if dialog.show(ui.ctx()).selected() {
+                if let Some(folder) = dialog.path() {
+                    self.game_path = Some(folder.to_path_buf());
+                }
+            }
+        }
+
+        ui.horizontal(|ui| {
+            match &self.manifest_path {
+                None => ui.label("Choose the manifest:"),
+                Some(path) => ui.label(format!("Manifest: {}", path.display())),
+            };
+            if ui.button("Open file").clicked() {
+                let filter = Box::new({
+                    let ext = Some(OsStr::new("txt"));
+                    move |path: &Path| -> bool { path.extension() == ext }
+                });
+                let mut dialog = FileDialog::open_file(self.manifest_path.clone()).show_files_filter(filter);
+                dialog.open();
+                self.manifest_dialog = Some(dialog);
+            }
+        });
+        if let Some(dialog) = &mut self.manifest_dialog {
+            if dialog.show(ui.ctx()).selected() {
+                if let Some(file) = dialog.path() {
+                    self.manifest_path = Some(file.to_path_buf());
+                }
+            }
+        }
+    }
+
+    fn display_verify_button(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let ready = !self.running && self.game_path.is_some() && self.manifest_path.is_some();
+            if ui.add_enabled(ready, Button::new("Verify install")).clicked() {
+                let game_path = self.game_path.clone().unwrap();
+                let manifest_path = self.manifest_path.clone().unwrap();
+                let output_sender = self.output_sender.clone();
+                let result_sender = self.result_sender.clone();
+                self.running = true;
+                self.result = None;
+                thread::spawn(move || {
+                    let _ = output_sender.send(format!("\nVerifying {} against {}...\n", game_path.display(), manifest_path.display()));
+                    let result = DepotManifest::read(&manifest_path).and_then(|manifest| {
+                        let report = verify_install(&game_path, &manifest, &output_sender)?;
+                        Ok((manifest, report))
+                    });
+                    let _ = result_sender.send(result);
+                });
+            }
+            if self.running {
+                ui.spinner();
+                ui.ctx().request_repaint();
+            }
+        });
+
+        if let Ok(result) = self.result_receiver.try_recv() {
+            let _ = match &result {
+                Ok((_, report)) => self.output_sender.send(format!("\n{}", report)),
+                Err(error) => self.output_sender.send(format!("\nFailed to verify the install: {}.\n", error)),
+            };
+            self.result = Some(result);
+            self.running = false;
+        }
+    }
+
+    fn display_result(&mut self, ui: &mut Ui) {
+        let Some(Ok((manifest, report))) = &self.result else {
+            return;
+        };
+        match report.is_intact() {
+            true => ui.label(format!("All {} files match Depot {} Manifest {}.", report.checked_files, manifest.depot, manifest.manifest)),
+            false => ui.colored_label(Color32::RED, format!("{} files are missing and {} are different.",
+                                                            report.missing.len(), report.mismatched.len())),
+        };
+        ui.label(format!("{} files are not part of the build.", report.extra.len()));
+        if report.is_intact() && report.extra.is_empty() {
+            return;
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("App: ");
+            ui.text_edit_singleline(&mut self.app);
+            ui.label("Build: ");
+            ui.text_edit_singleline(&mut self.build).on_hover_text("Leave empty to name the repair after the manifest");
+        });
+        ui.checkbox(&mut self.remove_extra_files, "Delete files which are not part of the build");
+        if ui.add_enabled(!self.app.is_empty(), Button::new("Save repair JSON")).clicked() {
+            let name = format!("repair_{}_{}.json", manifest.depot, manifest.manifest);
+            let mut dialog = FileDialog::save_file(self.game_path.as_ref().and_then(|path| path.parent()).map(|folder| folder.join(name)));
+            dialog.open();
+            self.repair_dialog = Some(dialog);
+        }
+
+        if let Some(dialog) = &mut self.repair_dialog {
+            if dialog.show(ui.ctx()).selected() {
+                if let Some(file) = dialog.path() {
+                    let changes = report.repair_changes(manifest, &self.app, &self.build, self.remove_extra_files);
+                    let _ = match serde_json::to_string_pretty(&changes).map_err(std::io::Error::from)
+                        .and_then(|json| std::fs::write(file, json)) {
+                        Ok(_) => self.output_sender.send(format!("\nSaved the repair changes to {}. Open them in Create Update to download the files.\n", file.display())),
+                        Err(error) => self.output_sender.send(format!("\nFailed to save the repair changes: {}.\n", error)),
+                    };
+                }
+            }
+        }
+    }
+
+    fn display_stdout(&mut self, ui: &mut Ui) {
+        let mut output = self.stdout.clone();
+        ScrollArea::vertical().id_source("Verify Install Output").max_height(ui.available_height()).show(ui, |ui| {
+            ui.add(TextEdit::multiline(&mut output).desired_width(ui.available_width()).cursor_at_end(true));
+            while let Ok(output) = self.output_receiver.try_recv() {
+                self.stdout += &output;
+                ui.scroll_to_cursor(None);
+                ui.ctx().request_repaint();
+            }
+        });
+    }
+}